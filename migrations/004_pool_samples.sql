@@ -0,0 +1,12 @@
+create table pool_samples (
+  id integer primary key autoincrement,
+  pool varchar not null,
+  time timestamp not null,
+  size integer not null,
+  allocated integer not null,
+  free integer not null,
+  capacity integer not null,
+  health varchar not null
+);
+
+create index pool_samples_pool_time_idx on pool_samples (pool, time);
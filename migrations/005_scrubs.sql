@@ -0,0 +1,22 @@
+create table scrub_results (
+  id integer primary key autoincrement,
+  pool varchar not null,
+  started timestamp not null,
+  finished timestamp,
+  scheduled boolean not null,
+  state varchar not null,
+  duration integer,
+  repaired integer,
+  errors integer
+);
+
+create index scrub_results_pool_started_idx on scrub_results (pool, started);
+create index scrub_results_finished_idx on scrub_results (finished);
+
+create table scrub_schedules (
+  id integer primary key autoincrement,
+  pool varchar not null,
+  interval integer not null,
+  next_run timestamp not null,
+  UNIQUE(pool)
+);
@@ -0,0 +1,16 @@
+create table backups (
+  id integer primary key autoincrement,
+  dataset varchar not null,
+  snapshot varchar not null,
+  parent_id integer,
+  file varchar not null,
+  size integer not null,
+  checksum varchar,
+  status varchar not null,
+  error varchar,
+  started timestamp not null,
+  finished timestamp
+);
+
+create index backups_dataset_idx on backups (dataset);
+create index backups_parent_id_idx on backups (parent_id);
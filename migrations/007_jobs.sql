@@ -0,0 +1,18 @@
+create table jobs (
+  id integer primary key autoincrement,
+  kind varchar not null,
+  package varchar not null,
+  version varchar not null,
+  status varchar not null,
+  phase varchar not null,
+  log text not null,
+  error varchar,
+  user_id integer,
+  created timestamp not null,
+  started timestamp,
+  finished timestamp,
+  foreign key (user_id) references users (user_id)
+);
+
+create index jobs_status_idx on jobs (status);
+create index jobs_created_idx on jobs (created);
@@ -0,0 +1,9 @@
+create table package_updates (
+  id integer primary key autoincrement,
+  name varchar not null,
+  installed varchar not null,
+  available varchar not null,
+  changes text not null,
+  checked timestamp not null,
+  UNIQUE(name)
+);
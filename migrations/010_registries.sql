@@ -0,0 +1,7 @@
+create table registries (
+  id integer primary key autoincrement,
+  name varchar not null,
+  path varchar not null,
+  created timestamp not null,
+  UNIQUE(name)
+);
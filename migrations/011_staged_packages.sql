@@ -0,0 +1,8 @@
+create table staged_packages (
+  id integer primary key autoincrement,
+  registry varchar not null,
+  name varchar not null,
+  version varchar not null,
+  created timestamp not null,
+  UNIQUE(name, version)
+);
@@ -1,15 +1,166 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
 use gild::config::Config;
 use gild::server::Server;
+use std::path::PathBuf;
+
+/// The gild server. Settings come from the configuration file, and any GILD_* environment
+/// variables override them.
+#[derive(Debug, Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Configuration file; without one, settings come from the environment alone
+    #[arg(short, long, global = true, env = "GILD_CONFIG")]
+    config: Option<PathBuf>,
+    /// Configuration file to serve with, as in older versions
+    #[arg(hide = true)]
+    legacy_config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the server (the default)
+    Serve,
+    /// Apply database migrations
+    Migrate,
+    /// Check the configuration and show the settings in effect
+    CheckConfig,
+    /// Create an account; every account has full access
+    CreateAdmin {
+        username: String,
+        /// Prompted for when not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Print a new signing key and salt
+    GenerateSigningKey {
+        /// Print as environment variables rather than YAML
+        #[arg(long)]
+        env: bool,
+    },
+    /// Print the version
+    Version,
+    /// Export users, the audit log, package settings and schedules
+    Export {
+        file: PathBuf,
+        /// Include password hashes
+        #[arg(long)]
+        passwords: bool,
+    },
+    /// Import an export, skipping anything that already exists
+    Import { file: PathBuf },
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // FIXME: replace this with clap later
-    let config = if std::env::args().len() < 2 {
-        Config::default()
-    } else {
-        Config::from_file(std::env::args().skip(1).next().unwrap().into())?
-    };
+    let cli = Cli::parse();
+    let config = cli.config.or(cli.legacy_config);
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => Server::new(load(config)?).await?.start().await,
+        Command::Migrate => gild::db::migrate(load(config)?.db).await,
+        Command::CheckConfig => check_config(&load(config)?).await,
+        Command::CreateAdmin { username, password } => {
+            let config = load(config)?;
+            let password = match password {
+                Some(password) => password,
+                None => rpassword::prompt_password("Password: ")?,
+            };
+            gild::system::create_admin(&config, &username, &password).await?;
+            println!("created user {}", username);
+            Ok(())
+        }
+        Command::GenerateSigningKey { env } => {
+            let (key, salt) = gild::config::generate_signing_key();
+            let format = |bytes: Vec<u8>| format!("{:?}", bytes);
+            if env {
+                println!(
+                    "{}='{}'",
+                    gild::config::environment_variable(&["signing_key"]),
+                    format(key)
+                );
+                println!(
+                    "{}='{}'",
+                    gild::config::environment_variable(&["signing_key_salt"]),
+                    format(salt)
+                );
+            } else {
+                println!("signing_key: {}", format(key));
+                println!("signing_key_salt: {}", format(salt));
+            }
+            Ok(())
+        }
+        Command::Version => {
+            println!("gild {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        Command::Export { file, passwords } => {
+            gild::system::export_file(&load(config)?, &file, passwords).await
+        }
+        Command::Import { file } => {
+            let report = gild::system::import_file(&load(config)?, &file).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+    }
+}
+
+fn load(config: Option<PathBuf>) -> Result<Config> {
+    match config {
+        Some(file) => Config::from_file(file),
+        None => Config::from_env(),
+    }
+}
 
-    Server::new(config).await?.start().await
+async fn check_config(config: &Config) -> Result<()> {
+    println!("listen: {}", config.listen);
+    println!("db: {}", config.db.display());
+    println!("buckle socket: {}", config.sockets.buckle.display());
+    println!("charon socket: {}", config.sockets.charon.display());
+    println!("pool: {}", config.zfs.pool);
+    println!("backups: {}", config.zfs.backups.display());
+    println!("registry: {}", config.packages.registry.display());
+    println!("sideload registry: {}", config.packages.sideload.display());
+    println!("log level: {:?}", config.log_level);
+
+    let mut problems = Vec::new();
+    for (name, socket) in [
+        ("buckle", &config.sockets.buckle),
+        ("charon", &config.sockets.charon),
+    ] {
+        if !socket.exists() {
+            problems.push(format!(
+                "{} socket {} does not exist",
+                name,
+                socket.display()
+            ));
+        }
+    }
+    if !config.packages.registry.join("packages").is_dir() {
+        problems.push(format!(
+            "registry {} has no packages directory",
+            config.packages.registry.display()
+        ));
+    } else if config.sockets.charon.exists() {
+        if let Err(e) = config.check_registry().await {
+            problems.push(e.to_string());
+        }
+    }
+    if config
+        .zfs
+        .capacity_alerts
+        .iter()
+        .any(|alert| *alert == 0 || *alert > 100)
+    {
+        problems.push("capacity alerts must be percentages from 1 to 100".into());
+    }
+
+    if problems.is_empty() {
+        println!("configuration is valid");
+        Ok(())
+    } else {
+        Err(anyhow!("{}", problems.join("\n")))
+    }
 }
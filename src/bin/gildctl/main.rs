@@ -0,0 +1,493 @@
+mod output;
+mod session;
+
+use anyhow::{anyhow, Result};
+use charon::PackageTitle;
+use clap::{Args, Parser, Subcommand};
+use gild::{
+    client::{Client, Error},
+    db::models::{Job, User},
+    packages::{Retention, SearchQuery},
+    server::messages::*,
+};
+use output::Format;
+use serde::Serialize;
+use serde_json::Value;
+use session::Session;
+use std::collections::HashMap;
+
+const DEFAULT_URL: &str = "http://localhost:3000";
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Manage a gild server over its API.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Server to talk to; defaults to the one last logged in to
+    #[arg(long, global = true, env = "GILD_URL")]
+    url: Option<String>,
+    /// Use this token instead of the stored one
+    #[arg(long, global = true, env = "GILD_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Print JSON instead of tables
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Log in and store the session token
+    Login {
+        username: String,
+        /// Read from standard input when not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Forget the stored session token
+    Logout,
+    /// Show the logged in user
+    Whoami,
+    /// Check the server and the services behind it
+    Ping,
+    /// Show the audit log
+    Log(PageArgs),
+    /// Manage user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Install, run and inspect packages
+    #[command(subcommand)]
+    Package(PackageCommand),
+    /// Follow package jobs
+    #[command(subcommand)]
+    Job(JobCommand),
+    /// Inspect the storage pool
+    #[command(subcommand)]
+    Zfs(ZfsCommand),
+    /// Inspect systemd units
+    #[command(subcommand)]
+    Systemd(SystemdCommand),
+}
+
+#[derive(Debug, Args)]
+struct PageArgs {
+    /// Only entries after this time (RFC 3339)
+    #[arg(long)]
+    since: Option<chrono::DateTime<chrono::Local>>,
+    #[arg(long)]
+    per_page: Option<u8>,
+    #[arg(long)]
+    page: Option<u8>,
+}
+
+impl From<PageArgs> for Pagination {
+    fn from(args: PageArgs) -> Self {
+        Self {
+            since: args.since,
+            per_page: args.per_page,
+            page: args.page,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct Title {
+    name: String,
+    version: String,
+}
+
+impl From<Title> for PackageTitle {
+    fn from(title: Title) -> Self {
+        Self {
+            name: title.name,
+            version: title.version,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum UserCommand {
+    /// List users
+    List,
+    /// Show a user
+    Show { id: u32 },
+    /// Create a user
+    Create {
+        username: String,
+        #[arg(long)]
+        realname: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+        #[arg(long)]
+        phone: Option<String>,
+        /// Read from standard input when not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Remove a user
+    Remove { id: u32 },
+}
+
+#[derive(Debug, Subcommand)]
+enum PackageCommand {
+    /// Every package in every registry
+    List,
+    /// Installed packages
+    Installed,
+    /// Show a package definition
+    Show(Title),
+    /// Search every registry
+    Search {
+        /// Matches part of the description
+        text: Option<String>,
+        /// Matches part of the name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Show what installing a package involves
+    Plan(Title),
+    /// Render a package with its saved responses without installing it
+    DryRun(Title),
+    /// Show the questions a package asks
+    Prompts(Title),
+    /// Show the saved answers to a package's questions
+    Responses { name: String },
+    /// Install a package and its dependencies
+    Install {
+        #[command(flatten)]
+        title: Title,
+        #[arg(long)]
+        approve_privileges: bool,
+        /// Wait for the job to finish
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Uninstall a package
+    Uninstall {
+        #[command(flatten)]
+        title: Title,
+        /// Volumes to snapshot before uninstalling
+        #[arg(long)]
+        snapshot: Vec<String>,
+        /// Volumes to destroy after uninstalling
+        #[arg(long)]
+        destroy: Vec<String>,
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Move an installed package to another version
+    Upgrade {
+        #[command(flatten)]
+        title: Title,
+        #[arg(long)]
+        approve_privileges: bool,
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Newer versions of installed packages
+    Updates,
+    /// Runtime status of installed packages
+    Status,
+    /// Start a package's service
+    Start(Title),
+    /// Stop a package's service
+    Stop(Title),
+    /// Restart a package's service
+    Restart(Title),
+    /// Show a package's log
+    Log {
+        #[command(flatten)]
+        title: Title,
+        #[arg(short = 'n', long, default_value_t = 100)]
+        count: usize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum JobCommand {
+    /// List jobs, newest first
+    List(PageArgs),
+    /// Show a job
+    Show { id: u32 },
+    /// Wait for a job to finish
+    Wait { id: u32 },
+    /// Cancel a queued or running job
+    Cancel { id: u32 },
+}
+
+#[derive(Debug, Subcommand)]
+enum ZfsCommand {
+    /// List datasets and volumes
+    List { filter: Option<String> },
+    /// Show pool health
+    Status,
+    /// Show pool capacity
+    Capacity,
+    /// Show dataset quotas
+    Quotas { filter: Option<String> },
+    /// Destroy a dataset or volume
+    Destroy { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum SystemdCommand {
+    /// List units
+    List { filter: Option<String> },
+    /// Show a unit's log
+    Log {
+        unit: String,
+        #[arg(short = 'n', long, default_value_t = 100)]
+        count: usize,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let format = if cli.json {
+        Format::Json
+    } else {
+        Format::Table
+    };
+
+    let session = Session::load()?;
+    let url = cli
+        .url
+        .or(session.as_ref().map(|s| s.url.clone()))
+        .unwrap_or(DEFAULT_URL.into());
+    let token = cli.token.or(session.and_then(|s| s.token));
+
+    let value = match cli.command {
+        Command::Login { username, password } => {
+            let password = password_or_prompt(password)?;
+            let mut client = Client::new(&url);
+            client
+                .login(Authentication { username, password })
+                .await
+                .map_err(explain)?;
+            Session {
+                url,
+                token: client.token(),
+            }
+            .save()?;
+            Value::Null
+        }
+        Command::Logout => {
+            Session::remove()?;
+            Value::Null
+        }
+        command => run(&Client::new(&url).with_token(token), command)
+            .await
+            .map_err(explain)?,
+    };
+
+    output::print(format, &value);
+    Ok(())
+}
+
+/// Errors come back as problem details, shown as they are, apart from an expired login.
+fn explain(e: impl Into<anyhow::Error>) -> anyhow::Error {
+    let e = e.into();
+    match e.downcast_ref::<Error>() {
+        Some(Error::Unauthorized(_)) => {
+            anyhow!("not logged in, or the session has expired; run `gildctl login`")
+        }
+        _ => e,
+    }
+}
+
+/// Responses are only printed, so they are turned into JSON values for the table layout.
+fn value<T: Serialize>(result: gild::client::Result<T>) -> Result<Value> {
+    Ok(serde_json::to_value(result?)?)
+}
+
+async fn run(client: &Client, command: Command) -> Result<Value> {
+    match command {
+        Command::Login { .. } | Command::Logout => unreachable!(),
+        Command::Whoami => value(client.me().await),
+        Command::Ping => value(client.ping().await),
+        Command::Log(page) => value(client.log(&page.into()).await),
+        Command::User(command) => user(client, command).await,
+        Command::Package(command) => package(client, command).await,
+        Command::Job(command) => job(client, command).await,
+        Command::Zfs(command) => zfs(client, command).await,
+        Command::Systemd(command) => systemd(client, command).await,
+    }
+}
+
+async fn user(client: &Client, command: UserCommand) -> Result<Value> {
+    match command {
+        UserCommand::List => value(client.list_users(None).await),
+        UserCommand::Show { id } => value(client.get_user(id).await),
+        UserCommand::Create {
+            username,
+            realname,
+            email,
+            phone,
+            password,
+        } => {
+            let mut user = User::default();
+            user.username = username;
+            user.realname = realname;
+            user.email = email;
+            user.phone = phone;
+            user.plaintext_password = Some(password_or_prompt(password)?);
+            value(client.create_user(&user).await)
+        }
+        UserCommand::Remove { id } => value(client.remove_user(id).await),
+    }
+}
+
+async fn package(client: &Client, command: PackageCommand) -> Result<Value> {
+    match command {
+        PackageCommand::List => value(client.list_registry_packages().await),
+        PackageCommand::Installed => value(client.list_installed().await),
+        PackageCommand::Show(title) => value(client.package_detail(&title.into()).await),
+        PackageCommand::Search { text, name } => value(
+            client
+                .search_packages(&SearchQuery {
+                    name,
+                    text,
+                    ..Default::default()
+                })
+                .await,
+        ),
+        PackageCommand::Plan(title) => value(client.plan_install(&title.into()).await),
+        PackageCommand::DryRun(title) => value(
+            client
+                .dry_run_install(&DryRunRequest {
+                    package: title.into(),
+                    responses: None,
+                })
+                .await,
+        ),
+        PackageCommand::Prompts(title) => value(client.get_prompts(&title.into()).await),
+        PackageCommand::Responses { name } => value(
+            client
+                .get_responses(&PackageTitle {
+                    name,
+                    version: String::new(),
+                })
+                .await,
+        ),
+        PackageCommand::Install {
+            title,
+            approve_privileges,
+            wait,
+        } => {
+            let job = client
+                .install_package(&InstallRequest {
+                    package: title.into(),
+                    approve_privileges,
+                })
+                .await?;
+            finish(client, job, wait).await
+        }
+        PackageCommand::Uninstall {
+            title,
+            snapshot,
+            destroy,
+            wait,
+        } => {
+            let mut volumes = HashMap::new();
+            for name in snapshot {
+                volumes.insert(name, Retention::Snapshot);
+            }
+            for name in destroy {
+                volumes.insert(name, Retention::Destroy);
+            }
+
+            let job = client
+                .uninstall_package(&UninstallRequest {
+                    package: title.into(),
+                    volumes,
+                })
+                .await?;
+            finish(client, job, wait).await
+        }
+        PackageCommand::Upgrade {
+            title,
+            approve_privileges,
+            wait,
+        } => {
+            let job = client
+                .upgrade_package(&UpgradeRequest {
+                    name: title.name,
+                    version: title.version,
+                    responses: None,
+                    approve_privileges,
+                })
+                .await?;
+            finish(client, job, wait).await
+        }
+        PackageCommand::Updates => value(client.package_updates().await),
+        PackageCommand::Status => value(client.package_status().await),
+        PackageCommand::Start(title) => value(client.start_package(&title.into()).await),
+        PackageCommand::Stop(title) => value(client.stop_package(&title.into()).await),
+        PackageCommand::Restart(title) => value(client.restart_package(&title.into()).await),
+        PackageCommand::Log { title, count } => value(
+            client
+                .package_log(&PackageLogParameters {
+                    package: title.into(),
+                    count,
+                    cursors: Default::default(),
+                    direction: None,
+                })
+                .await,
+        ),
+    }
+}
+
+async fn job(client: &Client, command: JobCommand) -> Result<Value> {
+    match command {
+        JobCommand::List(page) => value(client.list_jobs(&page.into()).await),
+        JobCommand::Show { id } => value(client.get_job(id).await),
+        JobCommand::Wait { id } => value(client.wait_for_job(id, POLL_INTERVAL).await),
+        JobCommand::Cancel { id } => value(client.cancel_job(id).await),
+    }
+}
+
+async fn zfs(client: &Client, command: ZfsCommand) -> Result<Value> {
+    match command {
+        ZfsCommand::List { filter } => value(client.zfs_list(&filter.unwrap_or_default()).await),
+        ZfsCommand::Status => value(client.zfs_pool_status().await),
+        ZfsCommand::Capacity => value(client.zfs_pool_capacity().await),
+        ZfsCommand::Quotas { filter } => {
+            value(client.zfs_quotas(&filter.unwrap_or_default()).await)
+        }
+        ZfsCommand::Destroy { name } => value(client.zfs_destroy(&name).await),
+    }
+}
+
+async fn systemd(client: &Client, command: SystemdCommand) -> Result<Value> {
+    match command {
+        SystemdCommand::List { filter } => value(client.list_units(filter.as_deref()).await),
+        SystemdCommand::Log { unit, count } => value(
+            client
+                .unit_log(&LogParameters {
+                    name: unit,
+                    count,
+                    cursor: None,
+                    direction: None,
+                })
+                .await,
+        ),
+    }
+}
+
+/// Shows a job that was just started, or waits for it when asked to.
+async fn finish(client: &Client, job: Job, wait: bool) -> Result<Value> {
+    if wait {
+        value(client.wait_for_job(job.id, POLL_INTERVAL).await)
+    } else {
+        value(Ok(job))
+    }
+}
+
+fn password_or_prompt(password: Option<String>) -> Result<String> {
+    match password {
+        Some(password) => Ok(password),
+        None => Ok(rpassword::prompt_password("Password: ")?),
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::Value;
+
+//
+// Tables are built from the shape of the response: a list of records gets a column per field,
+// a single record gets a row per field.
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+pub fn print(format: Format, value: &Value) {
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        ),
+        Format::Table => print!("{}", table(value)),
+    }
+}
+
+fn table(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Array(rows) if rows.iter().all(Value::is_object) && !rows.is_empty() => {
+            let mut columns: Vec<&str> = Vec::new();
+            for row in rows {
+                for key in row.as_object().unwrap().keys() {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+
+            let cells = rows
+                .iter()
+                .map(|row| {
+                    columns
+                        .iter()
+                        .map(|column| cell(row.get(column).unwrap_or(&Value::Null)))
+                        .collect()
+                })
+                .collect();
+
+            layout(
+                Some(columns.iter().map(|c| c.to_uppercase()).collect()),
+                cells,
+            )
+        }
+        Value::Array(rows) => rows.iter().map(|row| format!("{}\n", cell(row))).collect(),
+        Value::Object(fields) => layout(
+            None,
+            fields
+                .iter()
+                .map(|(key, value)| vec![key.clone(), cell(value)])
+                .collect(),
+        ),
+        value => format!("{}\n", cell(value)),
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        // nested values are kept on one line
+        value => value.to_string(),
+    }
+    .replace('\n', " ")
+}
+
+fn layout(header: Option<Vec<String>>, rows: Vec<Vec<String>>) -> String {
+    let rows: Vec<Vec<String>> = header.into_iter().chain(rows).collect();
+
+    let mut widths = Vec::new();
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(i) {
+                Some(w) if *w < width => *w = width,
+                Some(_) => {}
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{:width$}", cell, width = widths[i]))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
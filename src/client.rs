@@ -0,0 +1,687 @@
+use crate::{
+    db::models::{
+        AuditLog, Backup, Job, PackageRegistry, PackageUpdate, PoolSample, ScrubResult,
+        ScrubSchedule, User,
+    },
+    packages::{
+        DefinitionError, DryRun, InstallPlan, PackageLog, PackageStatus, Privileges,
+        RegistryPackage, SearchQuery, SearchResults, UnitStatus, VolumeRetention,
+    },
+    server::messages::*,
+    system::{ImportReport, SystemExport},
+    zfs::{PoolStatus, Property, Quota},
+};
+use charon::{PackageTitle, PromptCollection, PromptResponses};
+use reqwest::{Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::Mutex;
+
+//
+// A typed client for the gild API. Requests and responses are CBOR, using the same types the
+// server does. Logging in keeps the credentials, so an expired session is renewed by logging in
+// again the first time a request is refused.
+//
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The problem details the server sends with a failed request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Problem {
+    #[serde(default)]
+    pub status: u16,
+    pub title: Option<String>,
+    pub detail: Option<String>,
+    // problems with individual parts of the request, such as each invalid response
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<serde_json::Value>,
+}
+
+impl Problem {
+    /// The listed errors as `T`, skipping any that do not decode as one.
+    pub fn errors_as<T: DeserializeOwned>(&self) -> Vec<T> {
+        self.errors
+            .iter()
+            .filter_map(|error| serde_json::from_value(error.clone()).ok())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.title, &self.detail) {
+            (Some(title), Some(detail)) => write!(f, "{}: {}", title, detail),
+            (Some(message), None) | (None, Some(message)) => write!(f, "{}", message),
+            (None, None) => write!(f, "status {}", self.status),
+        }?;
+
+        for error in &self.errors {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Not logged in, or the session has expired.
+    Unauthorized(Problem),
+    Forbidden(Problem),
+    NotFound(Problem),
+    /// The request was rejected as malformed or invalid.
+    Invalid(Problem),
+    /// Any other failure reported by the server.
+    Server(Problem),
+    /// The server could not be reached, or the connection failed.
+    Transport(reqwest::Error),
+    /// A request or response could not be encoded or decoded.
+    Encoding(String),
+}
+
+impl Error {
+    fn from_response(status: StatusCode, body: &[u8]) -> Self {
+        let mut problem = serde_json::from_slice::<Problem>(body).unwrap_or_else(|_| Problem {
+            detail: Some(String::from_utf8_lossy(body).to_string()).filter(|s| !s.is_empty()),
+            ..Default::default()
+        });
+
+        if problem.status == 0 {
+            problem.status = status.as_u16();
+        }
+
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized(problem),
+            StatusCode::FORBIDDEN => Self::Forbidden(problem),
+            StatusCode::NOT_FOUND => Self::NotFound(problem),
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Self::Invalid(problem),
+            _ => Self::Server(problem),
+        }
+    }
+
+    /// The problem the server reported, if it reported one.
+    pub fn problem(&self) -> Option<&Problem> {
+        match self {
+            Self::Unauthorized(problem)
+            | Self::Forbidden(problem)
+            | Self::NotFound(problem)
+            | Self::Invalid(problem)
+            | Self::Server(problem) => Some(problem),
+            Self::Transport(_) | Self::Encoding(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "{}", e),
+            Self::Encoding(e) => write!(f, "{}", e),
+            problem => write!(f, "{}", problem.problem().unwrap()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: Mutex<Option<String>>,
+    credentials: Option<Authentication>,
+}
+
+impl Client {
+    /// A client for the server at `url`, e.g. `http://localhost:3000`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.into().trim_end_matches('/').to_string(),
+            token: Default::default(),
+            credentials: None,
+        }
+    }
+
+    /// Uses a token from an earlier login. It is not renewed when it expires.
+    pub fn with_token(self, token: Option<String>) -> Self {
+        self.set_token(token);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.token.lock().unwrap().clone()
+    }
+
+    pub fn set_token(&self, token: Option<String>) {
+        *self.token.lock().unwrap() = token;
+    }
+
+    pub async fn get<O>(&self, path: &str) -> Result<O>
+    where
+        O: DeserializeOwned,
+    {
+        decode(self.request(Method::GET, path, None).await?)
+    }
+
+    pub async fn delete<O>(&self, path: &str) -> Result<O>
+    where
+        O: DeserializeOwned,
+    {
+        decode(self.request(Method::DELETE, path, None).await?)
+    }
+
+    pub async fn post<I, O>(&self, path: &str, input: I) -> Result<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        decode(
+            self.request(Method::POST, path, Some(encode(&input)?))
+                .await?,
+        )
+    }
+
+    pub async fn put<I, O>(&self, path: &str, input: I) -> Result<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        decode(
+            self.request(Method::PUT, path, Some(encode(&input)?))
+                .await?,
+        )
+    }
+
+    /// Like [Self::post], for endpoints that answer with no body.
+    pub async fn post_empty<I>(&self, path: &str, input: I) -> Result<()>
+    where
+        I: Serialize,
+    {
+        self.request(Method::POST, path, Some(encode(&input)?))
+            .await?;
+        Ok(())
+    }
+
+    /// Like [Self::delete], for endpoints that answer with no body.
+    pub async fn delete_empty(&self, path: &str) -> Result<()> {
+        self.request(Method::DELETE, path, None).await?;
+        Ok(())
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<Vec<u8>>) -> Result<Vec<u8>> {
+        match self.send(method.clone(), path, body.clone()).await {
+            Err(Error::Unauthorized(_)) if self.credentials.is_some() => {
+                self.renew().await?;
+                self.send(method, path, body).await
+            }
+            res => res,
+        }
+    }
+
+    /// Makes a request, returning the body of a successful response as it came.
+    async fn send(&self, method: Method, path: &str, body: Option<Vec<u8>>) -> Result<Vec<u8>> {
+        let mut req = self
+            .http
+            .request(method, format!("{}{}", self.url, path))
+            .header("Content-type", "application/cbor");
+
+        if let Some(token) = self.token() {
+            req = req.header("Authorization", format!("Bearer {}", token));
+        }
+
+        if let Some(body) = body {
+            req = req.body(body);
+        }
+
+        let resp = req.send().await?;
+        let status = resp.status();
+        let body = resp.bytes().await?;
+
+        if !status.is_success() {
+            return Err(Error::from_response(status, &body));
+        }
+
+        Ok(body.to_vec())
+    }
+
+    async fn renew(&self) -> Result<()> {
+        let Some(credentials) = &self.credentials else {
+            return Ok(());
+        };
+
+        self.set_token(None);
+        let token: Token = decode(
+            self.send(Method::POST, "/session/login", Some(encode(credentials)?))
+                .await?,
+        )?;
+        self.set_token(Some(token.token));
+        Ok(())
+    }
+
+    //
+    // session
+    //
+
+    /// Logs in, keeping the credentials to renew the session when it expires.
+    pub async fn login(&mut self, credentials: Authentication) -> Result<()> {
+        self.credentials = Some(credentials);
+        if let Err(e) = self.renew().await {
+            self.credentials = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Forgets the token and credentials. Sessions end on their own on the server.
+    pub fn logout(&mut self) {
+        self.credentials = None;
+        self.set_token(None);
+    }
+
+    pub async fn me(&self) -> Result<User> {
+        self.get("/session/me").await
+    }
+
+    //
+    // status
+    //
+
+    pub async fn ping(&self) -> Result<PingResult> {
+        self.get("/status/ping").await
+    }
+
+    pub async fn log(&self, pagination: &Pagination) -> Result<Vec<AuditLog>> {
+        self.post("/status/log", pagination).await
+    }
+
+    //
+    // users
+    //
+
+    pub async fn list_users(&self, pagination: Option<&Pagination>) -> Result<Vec<User>> {
+        self.post("/users", pagination).await
+    }
+
+    pub async fn create_user(&self, user: &User) -> Result<User> {
+        self.put("/users", user).await
+    }
+
+    pub async fn get_user(&self, id: u32) -> Result<User> {
+        self.get(&format!("/user/{}", id)).await
+    }
+
+    pub async fn update_user(&self, id: u32, user: &User) -> Result<()> {
+        self.post_empty(&format!("/user/{}", id), user).await
+    }
+
+    pub async fn remove_user(&self, id: u32) -> Result<()> {
+        self.delete_empty(&format!("/user/{}", id)).await
+    }
+
+    //
+    // jobs
+    //
+
+    pub async fn list_jobs(&self, pagination: &Pagination) -> Result<Vec<Job>> {
+        self.post("/jobs", pagination).await
+    }
+
+    pub async fn get_job(&self, id: u32) -> Result<Job> {
+        self.get(&format!("/jobs/{}", id)).await
+    }
+
+    pub async fn cancel_job(&self, id: u32) -> Result<Job> {
+        self.post(&format!("/jobs/{}/cancel", id), ()).await
+    }
+
+    /// Polls a background job until it finishes.
+    pub async fn wait_for_job(&self, id: u32, interval: std::time::Duration) -> Result<Job> {
+        loop {
+            let job = self.get_job(id).await?;
+            if job.is_finished() {
+                return Ok(job);
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    //
+    // systemd
+    //
+
+    pub async fn list_units(&self, filter: Option<&str>) -> Result<Vec<buckle::systemd::Unit>> {
+        self.post("/systemd/list", filter).await
+    }
+
+    pub async fn set_unit(&self, settings: &buckle::systemd::UnitSettings) -> Result<()> {
+        self.post_empty("/systemd/set_unit", settings).await
+    }
+
+    pub async fn unit_log(
+        &self,
+        params: &LogParameters,
+    ) -> Result<Vec<buckle::systemd::LogMessage>> {
+        self.post("/systemd/log", params).await
+    }
+
+    //
+    // packages
+    //
+
+    pub async fn list_packages(&self) -> Result<Vec<PackageTitle>> {
+        self.get("/packages/list").await
+    }
+
+    pub async fn list_installed(&self) -> Result<Vec<PackageTitle>> {
+        self.get("/packages/list_installed").await
+    }
+
+    pub async fn installed(&self, title: &PackageTitle) -> Result<bool> {
+        self.post("/packages/installed", title).await
+    }
+
+    /// The package's definition as it is in its registry.
+    pub async fn package_detail(&self, title: &PackageTitle) -> Result<serde_json::Value> {
+        self.post("/packages/detail", title).await
+    }
+
+    pub async fn search_packages(&self, query: &SearchQuery) -> Result<SearchResults> {
+        self.post("/packages/search", query).await
+    }
+
+    pub async fn package_privileges(&self, title: &PackageTitle) -> Result<Privileges> {
+        self.post("/packages/privileges", title).await
+    }
+
+    pub async fn get_prompts(&self, title: &PackageTitle) -> Result<PromptCollection> {
+        self.post("/packages/prompts", title).await
+    }
+
+    pub async fn get_responses(&self, title: &PackageTitle) -> Result<PromptResponses> {
+        self.post("/packages/get_responses", title).await
+    }
+
+    pub async fn set_responses(&self, responses: &PromptResponsesWithName) -> Result<()> {
+        self.post_empty("/packages/set_responses", responses).await
+    }
+
+    pub async fn plan_install(&self, title: &PackageTitle) -> Result<InstallPlan> {
+        self.post("/packages/plan", title).await
+    }
+
+    pub async fn dry_run_install(&self, request: &DryRunRequest) -> Result<DryRun> {
+        self.post("/packages/dry_run", request).await
+    }
+
+    pub async fn install_package(&self, request: &InstallRequest) -> Result<Job> {
+        self.post("/packages/install", request).await
+    }
+
+    pub async fn upgrade_package(&self, request: &UpgradeRequest) -> Result<Job> {
+        self.post("/packages/upgrade", request).await
+    }
+
+    pub async fn uninstall_package(&self, request: &UninstallRequest) -> Result<Job> {
+        self.post("/packages/uninstall", request).await
+    }
+
+    pub async fn uninstall_volumes(&self, title: &PackageTitle) -> Result<Vec<VolumeRetention>> {
+        self.post("/packages/uninstall_volumes", title).await
+    }
+
+    pub async fn package_updates(&self) -> Result<Vec<PackageUpdate>> {
+        self.get("/packages/updates").await
+    }
+
+    pub async fn check_updates(&self) -> Result<Vec<PackageUpdate>> {
+        self.post("/packages/check_updates", ()).await
+    }
+
+    pub async fn package_status(&self) -> Result<Vec<PackageStatus>> {
+        self.get("/packages/status").await
+    }
+
+    pub async fn start_package(&self, title: &PackageTitle) -> Result<Vec<UnitStatus>> {
+        self.post("/packages/start", title).await
+    }
+
+    pub async fn stop_package(&self, title: &PackageTitle) -> Result<Vec<UnitStatus>> {
+        self.post("/packages/stop", title).await
+    }
+
+    pub async fn restart_package(&self, title: &PackageTitle) -> Result<Vec<UnitStatus>> {
+        self.post("/packages/restart", title).await
+    }
+
+    pub async fn package_log(&self, params: &PackageLogParameters) -> Result<PackageLog> {
+        self.post("/packages/log", params).await
+    }
+
+    pub async fn export_package(&self, request: &PackageExportRequest) -> Result<PackageExport> {
+        self.post("/packages/export", request).await
+    }
+
+    pub async fn import_package(&self, request: &PackageImportRequest) -> Result<Job> {
+        self.post("/packages/import", request).await
+    }
+
+    /// Fetches an archive from the server's backups directory, such as one made by an export.
+    pub async fn download_archive(&self, file: &str) -> Result<Vec<u8>> {
+        self.request(Method::GET, &format!("/packages/archives/{}", file), None)
+            .await
+    }
+
+    /// Stores an archive in the server's backups directory, ready to import.
+    pub async fn upload_archive(&self, file: &str, archive: Vec<u8>) -> Result<()> {
+        self.request(
+            Method::PUT,
+            &format!("/packages/archives/{}", file),
+            Some(archive),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn validate_package(
+        &self,
+        request: &SideloadRequest,
+    ) -> Result<Vec<DefinitionError>> {
+        self.post("/packages/validate", request).await
+    }
+
+    pub async fn sideload_package(&self, request: &SideloadRequest) -> Result<RegistryPackage> {
+        self.post("/packages/sideload", request).await
+    }
+
+    //
+    // registries
+    //
+
+    pub async fn list_registries(&self) -> Result<Vec<RegistryInfo>> {
+        self.get("/registries").await
+    }
+
+    pub async fn add_registry(&self, request: &RegistryRequest) -> Result<PackageRegistry> {
+        self.put("/registries", request).await
+    }
+
+    pub async fn remove_registry(&self, name: &str) -> Result<()> {
+        self.delete_empty(&format!("/registries/{}", name)).await
+    }
+
+    pub async fn refresh_registries(&self) -> Result<Vec<RegistryRefresh>> {
+        self.post("/registries/refresh", ()).await
+    }
+
+    /// Every package in every registry, where [Self::list_packages] only has charon's.
+    pub async fn list_registry_packages(&self) -> Result<Vec<RegistryPackage>> {
+        self.get("/registries/packages").await
+    }
+
+    //
+    // system
+    //
+
+    pub async fn export_system(&self, request: &SystemExportRequest) -> Result<SystemExport> {
+        self.post("/system/export", request).await
+    }
+
+    pub async fn import_system(&self, export: &SystemExport) -> Result<ImportReport> {
+        self.post("/system/import", export).await
+    }
+
+    //
+    // zfs
+    //
+
+    pub async fn zfs_list(&self, filter: &str) -> Result<Vec<ZFSListEntry>> {
+        self.post("/zfs/list", filter).await
+    }
+
+    pub async fn zfs_create_dataset(&self, dataset: &buckle::client::Dataset) -> Result<()> {
+        self.post_empty("/zfs/create_dataset", dataset).await
+    }
+
+    pub async fn zfs_modify_dataset(&self, request: &ModifyDatasetRequest) -> Result<()> {
+        self.post_empty("/zfs/modify_dataset", request).await
+    }
+
+    pub async fn zfs_create_volume(&self, volume: &buckle::client::Volume) -> Result<()> {
+        self.post_empty("/zfs/create_volume", volume).await
+    }
+
+    pub async fn zfs_modify_volume(&self, request: &ModifyVolumeRequest) -> Result<()> {
+        self.post_empty("/zfs/modify_volume", request).await
+    }
+
+    pub async fn zfs_create_encrypted_dataset(
+        &self,
+        request: &EncryptedDatasetRequest,
+    ) -> Result<()> {
+        self.post_empty("/zfs/create_encrypted_dataset", request)
+            .await
+    }
+
+    pub async fn zfs_unlock(&self, request: &UnlockRequest) -> Result<()> {
+        self.post_empty("/zfs/unlock", request).await
+    }
+
+    pub async fn zfs_lock(&self, name: &str) -> Result<()> {
+        self.post_empty("/zfs/lock", name).await
+    }
+
+    pub async fn zfs_change_key(&self, request: &ChangeKeyRequest) -> Result<()> {
+        self.post_empty("/zfs/change_key", request).await
+    }
+
+    pub async fn zfs_properties(&self, name: &str) -> Result<Vec<Property>> {
+        self.post("/zfs/properties", name).await
+    }
+
+    pub async fn zfs_destroy(&self, name: &str) -> Result<()> {
+        self.post_empty("/zfs/destroy", name).await
+    }
+
+    pub async fn zfs_pool_status(&self) -> Result<PoolStatus> {
+        self.get("/zfs/pool_status").await
+    }
+
+    pub async fn zfs_pool_capacity(&self) -> Result<PoolCapacityReport> {
+        self.get("/zfs/pool_capacity").await
+    }
+
+    pub async fn zfs_pool_history(&self, pagination: &Pagination) -> Result<Vec<PoolSample>> {
+        self.post("/zfs/pool_history", pagination).await
+    }
+
+    pub async fn zfs_quotas(&self, filter: &str) -> Result<Vec<Quota>> {
+        self.post("/zfs/quotas", filter).await
+    }
+
+    pub async fn zfs_set_quota(&self, request: &QuotaRequest) -> Result<Quota> {
+        self.post("/zfs/set_quota", request).await
+    }
+
+    pub async fn zfs_scrub_start(&self) -> Result<()> {
+        self.post_empty("/zfs/scrub_start", ()).await
+    }
+
+    pub async fn zfs_scrub_pause(&self) -> Result<()> {
+        self.post_empty("/zfs/scrub_pause", ()).await
+    }
+
+    pub async fn zfs_scrub_cancel(&self) -> Result<()> {
+        self.post_empty("/zfs/scrub_cancel", ()).await
+    }
+
+    pub async fn zfs_scrub_history(&self, pagination: &Pagination) -> Result<Vec<ScrubResult>> {
+        self.post("/zfs/scrub_history", pagination).await
+    }
+
+    pub async fn zfs_scrub_schedule(&self) -> Result<Option<ScrubSchedule>> {
+        self.get("/zfs/scrub_schedule").await
+    }
+
+    pub async fn zfs_set_scrub_schedule(
+        &self,
+        request: &ScrubScheduleRequest,
+    ) -> Result<ScrubSchedule> {
+        self.put("/zfs/scrub_schedule", request).await
+    }
+
+    pub async fn zfs_remove_scrub_schedule(&self) -> Result<()> {
+        self.delete_empty("/zfs/scrub_schedule").await
+    }
+
+    pub async fn zfs_backup(&self, request: &BackupRequest) -> Result<Backup> {
+        self.post("/zfs/backup", request).await
+    }
+
+    pub async fn zfs_backups(&self, request: &BackupListRequest) -> Result<Vec<Backup>> {
+        self.post("/zfs/backups", request).await
+    }
+
+    pub async fn zfs_get_backup(&self, id: u32) -> Result<Backup> {
+        self.get(&format!("/zfs/backup/{}", id)).await
+    }
+
+    pub async fn zfs_backup_verify(&self, id: u32) -> Result<Vec<BackupVerification>> {
+        self.post("/zfs/backup_verify", id).await
+    }
+
+    pub async fn zfs_restore(&self, request: &RestoreRequest) -> Result<()> {
+        self.post_empty("/zfs/restore", request).await
+    }
+}
+
+fn encode<I: Serialize>(input: &I) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    ciborium::into_writer(input, &mut buf).map_err(|e| Error::Encoding(e.to_string()))?;
+    Ok(buf)
+}
+
+// an empty body is an error here; endpoints without one are called through the `_empty` methods
+fn decode<O: DeserializeOwned>(body: Vec<u8>) -> Result<O> {
+    if body.is_empty() {
+        return Err(Error::Encoding("the response has no body".into()));
+    }
+
+    ciborium::from_reader(std::io::Cursor::new(body)).map_err(|e| Error::Encoding(e.to_string()))
+}
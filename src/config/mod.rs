@@ -0,0 +1,379 @@
+#[cfg(test)]
+mod tests;
+
+use anyhow::{anyhow, Result};
+use rand::Fill;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use tracing::info;
+use tracing_subscriber::FmtSubscriber;
+
+const DEFAULT_BUCKLE_PATH: &str = "/tmp/buckled.sock";
+const DEFAULT_CHARON_PATH: &str = "/tmp/charond.sock";
+const DEFAULT_DB: &str = "/gild.db";
+const DEFAULT_LISTEN: &str = "0.0.0.0:3000";
+const DEFAULT_POOL: &str = "trunk";
+const DEFAULT_SAMPLE_INTERVAL: u64 = 300;
+const DEFAULT_HISTORY_DAYS: i64 = 90;
+const DEFAULT_BACKUPS: &str = "/var/lib/gild/backups";
+const DEFAULT_REGISTRY: &str = "/var/lib/charon/registry";
+const DEFAULT_SIDELOAD: &str = "/var/lib/gild/sideload";
+const DEFAULT_START_TIMEOUT: u64 = 30;
+const DEFAULT_UPDATE_INTERVAL: u64 = 3600;
+
+fn default_db() -> std::path::PathBuf {
+    DEFAULT_DB.into()
+}
+
+fn default_buckle_socket() -> std::path::PathBuf {
+    DEFAULT_BUCKLE_PATH.into()
+}
+
+fn default_charon_socket() -> std::path::PathBuf {
+    DEFAULT_CHARON_PATH.into()
+}
+
+fn default_listen() -> SocketAddr {
+    DEFAULT_LISTEN.parse().unwrap()
+}
+
+fn default_pool() -> String {
+    DEFAULT_POOL.into()
+}
+
+fn default_capacity_alerts() -> Vec<u8> {
+    vec![80, 90, 95]
+}
+
+fn default_sample_interval() -> u64 {
+    DEFAULT_SAMPLE_INTERVAL
+}
+
+fn default_history_days() -> i64 {
+    DEFAULT_HISTORY_DAYS
+}
+
+fn default_backups() -> std::path::PathBuf {
+    DEFAULT_BACKUPS.into()
+}
+
+fn default_registry() -> std::path::PathBuf {
+    DEFAULT_REGISTRY.into()
+}
+
+fn default_sideload() -> std::path::PathBuf {
+    DEFAULT_SIDELOAD.into()
+}
+
+fn default_start_timeout() -> u64 {
+    DEFAULT_START_TIMEOUT
+}
+
+fn default_update_interval() -> u64 {
+    DEFAULT_UPDATE_INTERVAL
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_log_level() -> buckle::config::LogLevel {
+    buckle::config::LogLevel::Info
+}
+
+fn default_random() -> Vec<u8> {
+    let mut v: [u8; 64] = [0u8; 64];
+    v.fill(&mut rand::rng());
+    v.to_vec()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocketConfig {
+    #[serde(default = "default_buckle_socket")]
+    pub buckle: std::path::PathBuf,
+    #[serde(default = "default_charon_socket")]
+    pub charon: std::path::PathBuf,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            buckle: default_buckle_socket(),
+            charon: default_charon_socket(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZFSConfig {
+    #[serde(default = "default_pool")]
+    pub pool: String,
+    // percentages of pool capacity that generate an alert when crossed
+    #[serde(default = "default_capacity_alerts")]
+    pub capacity_alerts: Vec<u8>,
+    // in seconds
+    #[serde(default = "default_sample_interval")]
+    pub sample_interval: u64,
+    // in days
+    #[serde(default = "default_history_days")]
+    pub history_days: i64,
+    // directory (or mounted disk) that zfs send streams are written to
+    #[serde(default = "default_backups")]
+    pub backups: std::path::PathBuf,
+}
+
+impl Default for ZFSConfig {
+    fn default() -> Self {
+        Self {
+            pool: default_pool(),
+            capacity_alerts: default_capacity_alerts(),
+            sample_interval: default_sample_interval(),
+            history_days: default_history_days(),
+            backups: default_backups(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackagesConfig {
+    // the registry charon installs from; must match charon's configuration, which
+    // `gild check-config` and install plans check
+    #[serde(default = "default_registry")]
+    pub registry: std::path::PathBuf,
+    // registry that uploaded package definitions are written to
+    #[serde(default = "default_sideload")]
+    pub sideload: std::path::PathBuf,
+    // seconds an upgraded package has to start before it is rolled back; 0 skips the check
+    #[serde(default = "default_start_timeout")]
+    pub start_timeout: u64,
+    // in seconds
+    #[serde(default = "default_update_interval")]
+    pub update_interval: u64,
+    // record newly available versions in the audit log
+    #[serde(default = "default_true")]
+    pub update_alerts: bool,
+    // users who may approve packages asking for elevated privileges; anyone may when empty
+    #[serde(default)]
+    pub approvers: Vec<String>,
+}
+
+impl Default for PackagesConfig {
+    fn default() -> Self {
+        Self {
+            registry: default_registry(),
+            sideload: default_sideload(),
+            start_timeout: default_start_timeout(),
+            update_interval: default_update_interval(),
+            update_alerts: true,
+            approvers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    #[serde(default)]
+    pub sockets: SocketConfig,
+    #[serde(default)]
+    pub zfs: ZFSConfig,
+    #[serde(default)]
+    pub packages: PackagesConfig,
+    #[serde(default = "default_db")]
+    pub db: std::path::PathBuf,
+    #[serde(default = "default_random")]
+    pub signing_key: Vec<u8>,
+    #[serde(default = "default_random")]
+    pub signing_key_salt: Vec<u8>,
+    #[serde(default = "default_log_level")]
+    pub log_level: buckle::config::LogLevel,
+}
+
+// Every field that can be set from the environment, as GILD_ followed by its path in upper case,
+// such as GILD_ZFS_POOL. Fields marked true are parsed as YAML, so lists can be given as
+// "[a, b]"; the rest are taken as strings.
+const ENVIRONMENT: &[(&[&str], bool)] = &[
+    (&["listen"], false),
+    (&["sockets", "buckle"], false),
+    (&["sockets", "charon"], false),
+    (&["zfs", "pool"], false),
+    (&["zfs", "capacity_alerts"], true),
+    (&["zfs", "sample_interval"], true),
+    (&["zfs", "history_days"], true),
+    (&["zfs", "backups"], false),
+    (&["packages", "registry"], false),
+    (&["packages", "sideload"], false),
+    (&["packages", "start_timeout"], true),
+    (&["packages", "update_interval"], true),
+    (&["packages", "update_alerts"], true),
+    (&["packages", "approvers"], true),
+    (&["db"], false),
+    (&["signing_key"], true),
+    (&["signing_key_salt"], true),
+    (&["log_level"], false),
+];
+
+/// The environment variable a configuration field is read from.
+pub fn environment_variable(path: &[&str]) -> String {
+    format!("GILD_{}", path.join("_").to_uppercase())
+}
+
+/// Overrides configuration values with any set in the environment, as looked up by `lookup`.
+fn apply_environment(
+    value: &mut serde_yaml_ng::Value,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    use serde_yaml_ng::Value;
+
+    if value.is_null() {
+        *value = Value::Mapping(Default::default());
+    }
+
+    for (path, yaml) in ENVIRONMENT {
+        let name = environment_variable(path);
+        let Some(setting) = lookup(&name) else {
+            continue;
+        };
+        let setting = if *yaml {
+            serde_yaml_ng::from_str(&setting).map_err(|e| anyhow!("{}: {}", name, e))?
+        } else {
+            Value::String(setting)
+        };
+
+        let mut target = &mut *value;
+        for key in &path[..path.len() - 1] {
+            let map =
+                target
+                    .as_mapping_mut()
+                    .ok_or(anyhow!("{}: {} is not a mapping", name, key))?;
+            target = map
+                .entry(Value::String(key.to_string()))
+                .or_insert_with(|| Value::Mapping(Default::default()));
+        }
+        target
+            .as_mapping_mut()
+            .ok_or(anyhow!("{}: configuration is not a mapping", name))?
+            .insert(Value::String(path[path.len() - 1].to_string()), setting);
+    }
+
+    Ok(())
+}
+
+/// A new signing key and salt, as configuration.
+pub fn generate_signing_key() -> (Vec<u8>, Vec<u8>) {
+    (default_random(), default_random())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut this = Self {
+            listen: default_listen(),
+            sockets: Default::default(),
+            zfs: Default::default(),
+            packages: Default::default(),
+            db: default_db(),
+            signing_key: default_random(),
+            signing_key_salt: default_random(),
+            log_level: buckle::config::LogLevel::Info,
+        };
+        this.start_tracing().unwrap();
+        this.convert_signing_key().unwrap();
+        this
+    }
+}
+
+impl Config {
+    fn start_tracing(&self) -> Result<()> {
+        let subscriber = FmtSubscriber::builder()
+            .with_max_level(Into::<tracing::Level>::into(self.log_level.clone()))
+            .finish();
+        tracing::subscriber::set_global_default(subscriber)?;
+
+        info!("Configuration parsed");
+        Ok(())
+    }
+
+    /// Reads the configuration from `file`, with overrides from the environment.
+    pub fn from_file(file: std::path::PathBuf) -> Result<Self> {
+        let file = std::fs::OpenOptions::new().read(true).open(file)?;
+        Self::from_value(serde_yaml_ng::from_reader(file)?)
+    }
+
+    /// Builds the configuration from the environment alone.
+    pub fn from_env() -> Result<Self> {
+        Self::from_value(serde_yaml_ng::Value::Null)
+    }
+
+    fn from_value(mut value: serde_yaml_ng::Value) -> Result<Self> {
+        apply_environment(&mut value, |name| std::env::var(name).ok())?;
+        let mut this: Self = serde_yaml_ng::from_value(value)?;
+        this.start_tracing()?;
+        this.convert_signing_key()?;
+        Ok(this)
+    }
+
+    fn convert_signing_key(&mut self) -> Result<()> {
+        let mut buf: [u8; 64] = [0u8; 64];
+        let kdf = argon2::Argon2::default();
+        kdf.hash_password_into(
+            self.signing_key.as_slice(),
+            self.signing_key_salt.as_slice(),
+            &mut buf,
+        )
+        .map_err(|e| anyhow!(e.to_string()))?;
+
+        // overwrite the keys in the config
+        // we never generate (just parse) this format so this is a safe conversion.
+        self.signing_key = buf.to_vec();
+        self.signing_key_salt = Vec::default();
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_db(&self) -> Result<crate::db::DB> {
+        crate::db::DB::new(self.clone()).await
+    }
+
+    pub(crate) fn buckle(&self) -> Result<buckle::client::Client> {
+        buckle::client::Client::new(self.sockets.buckle.clone())
+    }
+
+    pub(crate) fn charon(&self) -> Result<charon::Client> {
+        charon::Client::new(self.sockets.charon.clone())
+    }
+
+    pub(crate) fn registry(&self) -> crate::packages::Registry {
+        crate::packages::Registry::new(self.packages.registry.clone())
+    }
+
+    /// Checks that `packages.registry` holds the same packages charon lists, as gild reads
+    /// dependencies and volumes from it directly.
+    pub async fn check_registry(&self) -> Result<()> {
+        let ours = self.registry().list()?;
+        let charon = self.charon()?.query().await?.list().await?;
+
+        let missing: Vec<String> = charon
+            .iter()
+            .filter(|title| !ours.contains(title))
+            .map(|title| format!("{} {}", title.name, title.version))
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "registry {} does not have packages charon lists ({}); it must be the registry charon uses",
+                self.packages.registry.display(),
+                missing.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn sideload(&self) -> crate::packages::Registry {
+        crate::packages::Registry::new(self.packages.sideload.clone())
+    }
+
+    pub(crate) fn zpool(&self) -> crate::zfs::Pool {
+        crate::zfs::Pool::new(self.zfs.pool.clone())
+    }
+}
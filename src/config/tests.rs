@@ -0,0 +1,89 @@
+use super::*;
+use std::collections::HashMap;
+
+fn configure(yaml: &str, environment: &[(&str, &str)]) -> Result<Config> {
+    let environment: HashMap<String, String> = environment
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    let mut value: serde_yaml_ng::Value = serde_yaml_ng::from_str(yaml)?;
+    apply_environment(&mut value, |name| environment.get(name).cloned())?;
+    Ok(serde_yaml_ng::from_value(value)?)
+}
+
+#[test]
+fn environment() {
+    let config = configure("", &[]).unwrap();
+    assert_eq!(config.listen, default_listen());
+    assert_eq!(config.zfs.pool, DEFAULT_POOL);
+
+    let config = configure(
+        "zfs:\n  pool: tank\n  history_days: 30\npackages:\n  approvers: [alice]\n",
+        &[
+            ("GILD_LISTEN", "127.0.0.1:8080"),
+            ("GILD_ZFS_POOL", "1234"),
+            ("GILD_ZFS_CAPACITY_ALERTS", "[50, 75]"),
+            ("GILD_PACKAGES_APPROVERS", "[bob, carol]"),
+            ("GILD_PACKAGES_UPDATE_ALERTS", "false"),
+            ("GILD_SOCKETS_CHARON", "/run/charon.sock"),
+            ("GILD_LOG_LEVEL", "debug"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(config.listen, "127.0.0.1:8080".parse().unwrap());
+    // taken as a string even though it looks like a number
+    assert_eq!(config.zfs.pool, "1234");
+    assert_eq!(config.zfs.capacity_alerts, vec![50, 75]);
+    // not overridden
+    assert_eq!(config.zfs.history_days, 30);
+    assert_eq!(config.packages.approvers, vec!["bob", "carol"]);
+    assert!(!config.packages.update_alerts);
+    assert_eq!(
+        config.sockets.charon,
+        std::path::Path::new("/run/charon.sock")
+    );
+    assert_eq!(config.sockets.buckle, default_buckle_socket());
+
+    assert!(configure("", &[("GILD_ZFS_SAMPLE_INTERVAL", "[")]).is_err());
+    assert!(configure("", &[("GILD_ZFS_SAMPLE_INTERVAL", "soon")]).is_err());
+}
+
+#[test]
+fn environment_fields() {
+    // every field of the configuration can be set from the environment, and nothing else can
+    fn fields(value: &serde_yaml_ng::Value, path: Vec<String>, found: &mut Vec<Vec<String>>) {
+        match value.as_mapping() {
+            Some(map) => {
+                for (key, value) in map {
+                    let mut path = path.clone();
+                    path.push(key.as_str().unwrap().to_string());
+                    fields(value, path, found);
+                }
+            }
+            None => found.push(path),
+        }
+    }
+
+    let mut found = Vec::new();
+    let config = serde_yaml_ng::to_value(configure("", &[]).unwrap()).unwrap();
+    fields(&config, Vec::new(), &mut found);
+    found.sort();
+
+    let mut expected: Vec<Vec<String>> = ENVIRONMENT
+        .iter()
+        .map(|(path, _)| path.iter().map(ToString::to_string).collect())
+        .collect();
+    expected.sort();
+
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn environment_variables() {
+    assert_eq!(environment_variable(&["db"]), "GILD_DB");
+    assert_eq!(
+        environment_variable(&["packages", "start_timeout"]),
+        "GILD_PACKAGES_START_TIMEOUT"
+    );
+}
@@ -1,5 +1,5 @@
 pub(crate) mod migrations;
-pub(crate) mod models;
+pub mod models;
 use crate::config::Config;
 use anyhow::Result;
 use sqlx::{migrate::MigrateDatabase, Sqlite};
@@ -21,9 +21,23 @@ impl std::fmt::Debug for DB {
 }
 
 pub async fn migrate(filename: std::path::PathBuf) -> Result<()> {
+    if !filename.exists() {
+        create(&filename).await?;
+    }
     migrations::migrate(filename).await
 }
 
+async fn create(filename: &std::path::Path) -> Result<()> {
+    if let Some(parent) = filename.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    sqlx::sqlite::CREATE_DB_WAL.store(true, std::sync::atomic::Ordering::Release);
+
+    Sqlite::create_database(&format!("sqlite:{}", filename.to_str().unwrap())).await?;
+    Ok(())
+}
+
 impl DB {
     pub async fn new(config: Config) -> Result<Self> {
         let this = match connect(&format!("sqlite:{}", config.db.to_str().unwrap())).await {
@@ -32,7 +46,7 @@ impl DB {
                 filename: config.db.clone(),
             },
             Err(_) => {
-                Self::create(config.clone()).await?;
+                create(&config.db).await?;
                 Self {
                     handle: connect(&format!("sqlite:{}", config.db.to_str().unwrap())).await?,
                     filename: config.db.clone(),
@@ -43,17 +57,6 @@ impl DB {
         Ok(this)
     }
 
-    async fn create(config: Config) -> anyhow::Result<()> {
-        if let Some(parent) = config.db.parent() {
-            std::fs::create_dir_all(&parent)?;
-        }
-
-        sqlx::sqlite::CREATE_DB_WAL.store(true, std::sync::atomic::Ordering::Release);
-
-        Sqlite::create_database(&format!("sqlite:{}", config.db.to_str().unwrap())).await?;
-        Ok(())
-    }
-
     pub fn handle(&self) -> &SqliteClient {
         &self.handle
     }
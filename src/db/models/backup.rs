@@ -0,0 +1,136 @@
+use crate::db::DB;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use validator::Validate;
+use welds::{state::DbState, WeldsModel};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BackupStatus {
+    Running,
+    Complete,
+    Failed,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "backups")]
+pub struct Backup {
+    #[welds(primary_key)]
+    pub id: u32,
+    pub dataset: String,
+    pub snapshot: String,
+    // the backup this one is incremental to; full backups have none
+    pub parent_id: Option<u32>,
+    pub file: String,
+    pub size: i64,
+    // sha256 of the send stream
+    pub checksum: Option<String>,
+    pub status: String,
+    pub error: Option<String>,
+    pub started: chrono::DateTime<chrono::Local>,
+    pub finished: Option<chrono::DateTime<chrono::Local>>,
+}
+
+impl Backup {
+    pub fn new_started(dataset: &str, parent: Option<&Backup>) -> DbState<Self> {
+        let started = chrono::Local::now();
+        let snapshot = format!("gild-{}", started.format("%Y%m%d%H%M%S%3f"));
+
+        DbState::new_uncreated(Self {
+            dataset: dataset.to_string(),
+            file: format!("{}@{}.zfs", dataset.replace('/', "_"), snapshot),
+            snapshot,
+            parent_id: parent.map(|p| p.id),
+            status: format!("{:?}", BackupStatus::Running),
+            started,
+            ..Default::default()
+        })
+    }
+
+    pub fn complete(&mut self, size: u64, checksum: String) {
+        self.size = size as i64;
+        self.checksum = Some(checksum);
+        self.status = format!("{:?}", BackupStatus::Complete);
+        self.finished = Some(chrono::Local::now());
+    }
+
+    pub fn fail(&mut self, error: &str) {
+        self.error = Some(error.to_string());
+        self.status = format!("{:?}", BackupStatus::Failed);
+        self.finished = Some(chrono::Local::now());
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.status == format!("{:?}", BackupStatus::Complete)
+    }
+
+    /// The most recent completed backup of the dataset, which new incrementals are based on.
+    pub async fn latest(db: &DB, dataset: &str) -> Result<Option<Self>> {
+        let complete = format!("{:?}", BackupStatus::Complete);
+        Ok(Self::all()
+            .where_col(|c| c.dataset.equal(dataset))
+            .where_col(|c| c.status.equal(&complete))
+            .order_by_desc(|c| c.id)
+            .limit(1)
+            .run(db.handle())
+            .await?
+            .first()
+            .map(|backup| backup.deref().clone()))
+    }
+
+    /// Fails any backup left running by a previous run of the server.
+    pub async fn fail_interrupted(db: &DB) -> Result<()> {
+        let running = format!("{:?}", BackupStatus::Running);
+        let backups = Self::all()
+            .where_col(|c| c.status.equal(&running))
+            .run(db.handle())
+            .await?;
+
+        for mut backup in backups {
+            backup.fail("interrupted by server restart");
+            backup.save(db.handle()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// The backups needed to restore this one, starting with the full backup.
+    pub async fn chain(db: &DB, id: u32) -> Result<Vec<Self>> {
+        let mut chain = Vec::new();
+        let mut next = Some(id);
+
+        while let Some(id) = next {
+            let backup = Self::find_by_id(db.handle(), id)
+                .await?
+                .ok_or(anyhow!("backup {} does not exist", id))?
+                .into_inner();
+
+            if !backup.is_complete() {
+                return Err(anyhow!(
+                    "backup {} of {} is {}",
+                    backup.id,
+                    backup.dataset,
+                    backup.status
+                ));
+            }
+
+            next = backup.parent_id;
+            chain.push(backup);
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+}
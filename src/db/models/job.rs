@@ -0,0 +1,148 @@
+use crate::db::DB;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use welds::{state::DbState, WeldsModel};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum JobKind {
+    Install,
+    Uninstall,
+    Upgrade,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Complete,
+    Failed,
+    Canceled,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JobPhase {
+    Queued,
+    Checking,
+    Resolving,
+    Installing,
+    Uninstalling,
+    Upgrading,
+    Starting,
+    RollingBack,
+    Done,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "jobs")]
+pub struct Job {
+    #[welds(primary_key)]
+    pub id: u32,
+    pub kind: String,
+    pub package: String,
+    pub version: String,
+    // for upgrades, the version being replaced
+    pub from_version: Option<String>,
+    pub status: String,
+    pub phase: String,
+    // one timestamped line per event
+    pub log: String,
+    pub error: Option<String>,
+    pub user_id: Option<u32>,
+    pub created: chrono::DateTime<chrono::Local>,
+    pub started: Option<chrono::DateTime<chrono::Local>>,
+    pub finished: Option<chrono::DateTime<chrono::Local>>,
+}
+
+impl Job {
+    pub fn new_queued(kind: JobKind, pkg: &charon::PackageTitle, user_id: u32) -> DbState<Self> {
+        let mut job = Self {
+            kind: format!("{:?}", kind),
+            package: pkg.name.clone(),
+            version: pkg.version.clone(),
+            status: format!("{:?}", JobStatus::Queued),
+            phase: format!("{:?}", JobPhase::Queued),
+            user_id: Some(user_id),
+            created: chrono::Local::now(),
+            ..Default::default()
+        };
+        job.log_line(&format!("{:?} {} {} queued", kind, pkg.name, pkg.version));
+
+        DbState::new_uncreated(job)
+    }
+
+    pub fn title(&self) -> charon::PackageTitle {
+        charon::PackageTitle {
+            name: self.package.clone(),
+            version: self.version.clone(),
+        }
+    }
+
+    pub fn log_line(&mut self, line: &str) {
+        self.log += &format!("{} {}\n", chrono::Local::now().to_rfc3339(), line);
+    }
+
+    pub fn start(&mut self) {
+        self.status = format!("{:?}", JobStatus::Running);
+        self.started = Some(chrono::Local::now());
+    }
+
+    pub fn set_phase(&mut self, phase: JobPhase) {
+        self.phase = format!("{:?}", phase);
+        self.log_line(&format!("{:?}", phase));
+    }
+
+    pub fn finish(&mut self, result: std::result::Result<(), String>) {
+        self.phase = format!("{:?}", JobPhase::Done);
+        self.finished = Some(chrono::Local::now());
+
+        match result {
+            Ok(()) => {
+                self.status = format!("{:?}", JobStatus::Complete);
+                self.log_line("Complete");
+            }
+            Err(e) => {
+                self.status = format!("{:?}", JobStatus::Failed);
+                self.log_line(&format!("Failed: {}", e));
+                self.error = Some(e);
+            }
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.status = format!("{:?}", JobStatus::Canceled);
+        self.finished = Some(chrono::Local::now());
+        self.log_line("Canceled");
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    /// Fails any job left unfinished by a previous run of the server.
+    pub async fn fail_interrupted(db: &DB) -> Result<()> {
+        let jobs = Self::all()
+            .where_col(|c| c.finished.equal(None))
+            .run(db.handle())
+            .await?;
+
+        for mut job in jobs {
+            job.finish(Err("interrupted by server restart".into()));
+            job.save(db.handle()).await?;
+        }
+
+        Ok(())
+    }
+}
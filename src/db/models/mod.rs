@@ -1,7 +1,14 @@
+mod backup;
+mod job;
 mod log;
+mod pool;
+mod registry;
+mod scrub;
 mod session;
 #[cfg(test)]
 mod tests;
+mod update;
 mod user;
 
-pub use self::{log::*, session::*, user::*};
+pub(crate) use self::session::*;
+pub use self::{backup::*, job::*, log::*, pool::*, registry::*, scrub::*, update::*, user::*};
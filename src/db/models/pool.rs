@@ -0,0 +1,71 @@
+use crate::{db::DB, zfs::PoolCapacity};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use validator::Validate;
+use welds::WeldsModel;
+
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "pool_samples")]
+pub struct PoolSample {
+    #[welds(primary_key)]
+    pub id: u32,
+    pub pool: String,
+    pub time: chrono::DateTime<chrono::Local>,
+    pub size: i64,
+    pub allocated: i64,
+    pub free: i64,
+    pub capacity: u32,
+    pub health: String,
+}
+
+impl From<PoolCapacity> for PoolSample {
+    fn from(value: PoolCapacity) -> Self {
+        Self {
+            pool: value.name,
+            time: chrono::Local::now(),
+            size: value.size as i64,
+            allocated: value.allocated as i64,
+            free: value.free as i64,
+            capacity: value.capacity.into(),
+            health: value.health,
+            ..Default::default()
+        }
+    }
+}
+
+impl PoolSample {
+    pub async fn latest(db: &DB, pool: &str) -> Result<Option<Self>> {
+        Ok(Self::all()
+            .where_col(|c| c.pool.equal(pool))
+            .order_by_desc(|c| c.time)
+            .limit(1)
+            .run(db.handle())
+            .await?
+            .first()
+            .map(|sample| sample.deref().clone()))
+    }
+
+    pub async fn prune(db: &DB, days: i64) -> Result<()> {
+        Self::all()
+            .where_col(|c| {
+                c.time
+                    .lt(chrono::Local::now() - chrono::Duration::days(days))
+            })
+            .delete(db.handle())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    db::DB,
+    packages::{Registry, RegistryPackage},
+};
+use anyhow::Result;
+use charon::PackageTitle;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use welds::{exts::VecStateExt, state::DbState, WeldsModel};
+
+/// A local registry added alongside the one charon is configured with.
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "registries")]
+pub struct PackageRegistry {
+    #[welds(primary_key)]
+    pub id: u32,
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub path: String,
+    pub created: chrono::DateTime<chrono::Local>,
+}
+
+impl PackageRegistry {
+    pub async fn for_name(db: &DB, name: &str) -> Result<Option<DbState<Self>>> {
+        Ok(Self::all()
+            .where_col(|c| c.name.equal(name))
+            .limit(1)
+            .run(db.handle())
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    pub async fn list(db: &DB) -> Result<Vec<Self>> {
+        Ok(Self::all()
+            .order_by_asc(|c| c.id)
+            .run(db.handle())
+            .await?
+            .into_inners())
+    }
+
+    pub fn registry(&self) -> Registry {
+        Registry::new(self.path.clone().into())
+    }
+}
+
+/// A definition copied from another registry into charon's so that it could be installed.
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "staged_packages")]
+pub struct StagedPackage {
+    #[welds(primary_key)]
+    pub id: u32,
+    // the registry the definition came from
+    pub registry: String,
+    pub name: String,
+    pub version: String,
+    pub created: chrono::DateTime<chrono::Local>,
+}
+
+impl StagedPackage {
+    pub async fn list(db: &DB) -> Result<Vec<Self>> {
+        Ok(Self::all()
+            .order_by_asc(|c| c.id)
+            .run(db.handle())
+            .await?
+            .into_inners())
+    }
+
+    pub async fn for_registry(db: &DB, registry: &str) -> Result<Vec<DbState<Self>>> {
+        Ok(Self::all()
+            .where_col(|c| c.registry.equal(registry))
+            .run(db.handle())
+            .await?)
+    }
+
+    pub async fn record(db: &DB, staged: &RegistryPackage) -> Result<()> {
+        DbState::new_uncreated(Self {
+            registry: staged.registry.clone(),
+            name: staged.title.name.clone(),
+            version: staged.title.version.clone(),
+            created: chrono::Local::now(),
+            ..Default::default()
+        })
+        .save(db.handle())
+        .await?;
+        Ok(())
+    }
+
+    pub fn package(&self) -> RegistryPackage {
+        RegistryPackage {
+            title: PackageTitle {
+                name: self.name.clone(),
+                version: self.version.clone(),
+            },
+            registry: self.registry.clone(),
+        }
+    }
+}
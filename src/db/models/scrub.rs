@@ -0,0 +1,134 @@
+use crate::{
+    db::DB,
+    zfs::{ScanState, ScanStatus},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use welds::{state::DbState, WeldsModel};
+
+/// The state of a scrub that ended without the pool reporting how.
+pub const SCRUB_UNKNOWN: &str = "Unknown";
+
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "scrub_results")]
+pub struct ScrubResult {
+    #[welds(primary_key)]
+    pub id: u32,
+    pub pool: String,
+    pub started: chrono::DateTime<chrono::Local>,
+    pub finished: Option<chrono::DateTime<chrono::Local>>,
+    pub scheduled: bool,
+    pub state: String,
+    // in seconds
+    pub duration: Option<i64>,
+    // in bytes
+    pub repaired: Option<i64>,
+    pub errors: Option<i64>,
+}
+
+impl ScrubResult {
+    pub fn new_started(pool: &str, scheduled: bool) -> DbState<Self> {
+        DbState::new_uncreated(Self {
+            pool: pool.to_string(),
+            started: chrono::Local::now(),
+            scheduled,
+            state: format!("{:?}", ScanState::InProgress),
+            ..Default::default()
+        })
+    }
+
+    pub async fn running(db: &DB, pool: &str) -> Result<Option<DbState<Self>>> {
+        Ok(Self::all()
+            .where_col(|c| c.pool.equal(pool))
+            .where_col(|c| c.finished.equal(None))
+            .order_by_desc(|c| c.started)
+            .limit(1)
+            .run(db.handle())
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Fills in the result from the pool's scan status. Returns false if the scrub has not
+    /// completed yet.
+    pub fn finish(&mut self, scan: &ScanStatus) -> bool {
+        self.state = format!("{:?}", scan.state);
+
+        match scan.state {
+            ScanState::Finished | ScanState::Canceled => {}
+            // the pool no longer reports any scan, so how it ended cannot be known
+            ScanState::None => {
+                self.state = SCRUB_UNKNOWN.into();
+                self.finished = Some(chrono::Local::now());
+                return true;
+            }
+            _ => return false,
+        }
+
+        self.duration = scan.duration.map(|d| d as i64);
+        self.repaired = scan.repaired.map(|r| r as i64);
+        self.errors = scan.errors.map(|e| e as i64);
+        self.finished = Some(match self.duration {
+            Some(duration) => self.started + chrono::Duration::seconds(duration),
+            None => chrono::Local::now(),
+        });
+
+        true
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "scrub_schedules")]
+pub struct ScrubSchedule {
+    #[welds(primary_key)]
+    pub id: u32,
+    pub pool: String,
+    // in days
+    #[validate(range(min = 1, max = 365))]
+    pub interval: i64,
+    pub next_run: chrono::DateTime<chrono::Local>,
+}
+
+impl ScrubSchedule {
+    pub async fn for_pool(db: &DB, pool: &str) -> Result<Option<DbState<Self>>> {
+        Ok(Self::all()
+            .where_col(|c| c.pool.equal(pool))
+            .run(db.handle())
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.next_run <= chrono::Local::now()
+    }
+
+    pub fn advance(&mut self) {
+        self.next_run = chrono::Local::now() + chrono::Duration::days(self.interval);
+    }
+}
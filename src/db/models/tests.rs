@@ -4,7 +4,10 @@ use welds::state::DbState;
 
 use super::User;
 use crate::{
-    db::models::{AuditLog, Session, JWT_EXPIRATION_TIME, JWT_SESSION_ID_KEY},
+    db::models::{
+        AuditLog, Backup, Job, JobKind, JobPhase, PackageUpdate, PoolSample, ScrubResult,
+        ScrubSchedule, Session, JWT_EXPIRATION_TIME, JWT_SESSION_ID_KEY, SCRUB_UNKNOWN,
+    },
     server::messages::Authentication,
     testutil::*,
 };
@@ -47,6 +50,105 @@ async fn audit_log() {
     }
 }
 
+#[tokio::test]
+async fn pool_samples() {
+    let db = make_config(None, None)
+        .await
+        .unwrap()
+        .get_db()
+        .await
+        .unwrap();
+
+    assert!(PoolSample::latest(&db, "trunk").await.unwrap().is_none());
+
+    for capacity in 1..=5 {
+        let mut sample = DbState::new_uncreated(PoolSample::from(crate::zfs::PoolCapacity {
+            name: "trunk".into(),
+            size: 100,
+            allocated: capacity,
+            free: 100 - capacity,
+            capacity: capacity as u8,
+            health: "ONLINE".into(),
+        }));
+        sample.save(db.handle()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let latest = PoolSample::latest(&db, "trunk").await.unwrap().unwrap();
+    assert_eq!(latest.capacity, 5);
+    assert_eq!(latest.allocated, 5);
+    assert!(PoolSample::latest(&db, "other").await.unwrap().is_none());
+
+    PoolSample::prune(&db, 1).await.unwrap();
+    assert_eq!(PoolSample::all().count(db.handle()).await.unwrap(), 5);
+    PoolSample::prune(&db, -1).await.unwrap();
+    assert_eq!(PoolSample::all().count(db.handle()).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn scrubs() {
+    let db = make_config(None, None)
+        .await
+        .unwrap()
+        .get_db()
+        .await
+        .unwrap();
+
+    assert!(ScrubResult::running(&db, "trunk").await.unwrap().is_none());
+
+    let mut result = ScrubResult::new_started("trunk", false);
+    result.save(db.handle()).await.unwrap();
+
+    let mut running = ScrubResult::running(&db, "trunk").await.unwrap().unwrap();
+    assert_eq!(running.id, result.id);
+
+    let mut scan = crate::zfs::ScanStatus {
+        function: Some("scrub".into()),
+        state: crate::zfs::ScanState::InProgress,
+        ..Default::default()
+    };
+    assert!(!running.finish(&scan));
+    assert!(running.finished.is_none());
+
+    scan.state = crate::zfs::ScanState::Finished;
+    scan.duration = Some(65);
+    scan.repaired = Some(1024);
+    scan.errors = Some(0);
+    assert!(running.finish(&scan));
+    assert_eq!(
+        running.finished.unwrap(),
+        running.started + chrono::Duration::seconds(65)
+    );
+    assert_eq!(running.repaired, Some(1024));
+    assert_eq!(running.state, "Finished");
+    running.save(db.handle()).await.unwrap();
+
+    assert!(ScrubResult::running(&db, "trunk").await.unwrap().is_none());
+
+    // a scrub the pool stops reporting is not left running forever
+    let mut vanished = ScrubResult::new_started("trunk", false);
+    assert!(vanished.finish(&Default::default()));
+    assert_eq!(vanished.state, SCRUB_UNKNOWN);
+    assert!(vanished.finished.is_some());
+
+    let mut schedule = DbState::new_uncreated(ScrubSchedule {
+        pool: "trunk".into(),
+        interval: 7,
+        next_run: chrono::Local::now() - chrono::Duration::minutes(1),
+        ..Default::default()
+    });
+    schedule.save(db.handle()).await.unwrap();
+
+    let mut schedule = ScrubSchedule::for_pool(&db, "trunk")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(schedule.is_due());
+    schedule.advance();
+    assert!(!schedule.is_due());
+    assert!(schedule.next_run > chrono::Local::now() + chrono::Duration::days(6));
+}
+
 #[tokio::test]
 async fn session_jwt() {
     let db = make_config(None, None)
@@ -197,3 +299,187 @@ async fn user_basic() {
 
     assert_eq!(User::all().count(&db.handle).await.unwrap(), 0);
 }
+
+#[tokio::test]
+async fn backups() {
+    let db = make_config(None, None)
+        .await
+        .unwrap()
+        .get_db()
+        .await
+        .unwrap();
+
+    assert!(Backup::latest(&db, "dataset").await.unwrap().is_none());
+
+    let mut full = Backup::new_started("dataset", None);
+    full.save(db.handle()).await.unwrap();
+    assert!(full.parent_id.is_none());
+    assert_eq!(full.status, "Running");
+    assert!(Backup::latest(&db, "dataset").await.unwrap().is_none());
+    assert!(Backup::chain(&db, full.id).await.is_err());
+
+    full.complete(1024, "abc".into());
+    full.save(db.handle()).await.unwrap();
+    assert_eq!(
+        Backup::latest(&db, "dataset").await.unwrap().unwrap().id,
+        full.id
+    );
+
+    let mut incremental = Backup::new_started("dataset", Some(full.deref()));
+    incremental.save(db.handle()).await.unwrap();
+    assert_eq!(incremental.parent_id, Some(full.id));
+    incremental.complete(512, "def".into());
+    incremental.save(db.handle()).await.unwrap();
+
+    let mut failed = Backup::new_started("dataset", Some(incremental.deref()));
+    failed.fail("out of space");
+    failed.save(db.handle()).await.unwrap();
+
+    assert_eq!(
+        Backup::latest(&db, "dataset").await.unwrap().unwrap().id,
+        incremental.id
+    );
+    assert!(Backup::latest(&db, "other").await.unwrap().is_none());
+
+    let chain = Backup::chain(&db, incremental.id).await.unwrap();
+    assert_eq!(
+        chain.iter().map(|backup| backup.id).collect::<Vec<_>>(),
+        vec![full.id, incremental.id]
+    );
+    assert_eq!(chain[1].size, 512);
+    assert!(Backup::chain(&db, failed.id).await.is_err());
+    assert!(Backup::chain(&db, failed.id + 1).await.is_err());
+
+    // a backup still running when the server stopped is not left running
+    let mut interrupted = Backup::new_started("dataset", None);
+    interrupted.save(db.handle()).await.unwrap();
+    Backup::fail_interrupted(&db).await.unwrap();
+    let interrupted = Backup::find_by_id(db.handle(), interrupted.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(interrupted.status, "Failed");
+    assert_eq!(
+        interrupted.error.as_deref(),
+        Some("interrupted by server restart")
+    );
+    assert_eq!(
+        Backup::latest(&db, "dataset").await.unwrap().unwrap().id,
+        incremental.id
+    );
+}
+
+#[tokio::test]
+async fn jobs() {
+    let db = make_config(None, None)
+        .await
+        .unwrap()
+        .get_db()
+        .await
+        .unwrap();
+
+    let pkg = charon::PackageTitle {
+        name: "plex".into(),
+        version: "0.0.1".into(),
+    };
+
+    let mut job = Job::new_queued(JobKind::Install, &pkg, 1);
+    job.save(db.handle()).await.unwrap();
+    assert_eq!(job.status, "Queued");
+    assert_eq!(job.phase, "Queued");
+    assert_eq!(job.title(), pkg);
+    assert!(job.log.ends_with("Install plex 0.0.1 queued\n"));
+
+    job.start();
+    job.set_phase(JobPhase::Installing);
+    assert_eq!(job.status, "Running");
+    assert_eq!(job.phase, "Installing");
+    assert!(job.started.is_some());
+    assert!(!job.is_finished());
+
+    job.finish(Ok(()));
+    assert_eq!(job.status, "Complete");
+    assert_eq!(job.phase, "Done");
+    assert!(job.is_finished());
+    job.save(db.handle()).await.unwrap();
+
+    let mut failed = Job::new_queued(JobKind::Uninstall, &pkg, 1);
+    failed.finish(Err("not installed".into()));
+    assert_eq!(failed.status, "Failed");
+    assert_eq!(failed.error, Some("not installed".into()));
+
+    let mut canceled = Job::new_queued(JobKind::Install, &pkg, 1);
+    canceled.cancel();
+    assert_eq!(canceled.status, "Canceled");
+    assert!(canceled.is_finished());
+
+    let mut interrupted = Job::new_queued(JobKind::Install, &pkg, 1);
+    interrupted.save(db.handle()).await.unwrap();
+    Job::fail_interrupted(&db).await.unwrap();
+
+    let interrupted = Job::find_by_id(db.handle(), interrupted.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(interrupted.status, "Failed");
+    assert_eq!(
+        interrupted.error,
+        Some("interrupted by server restart".into())
+    );
+
+    let job = Job::find_by_id(db.handle(), job.id).await.unwrap().unwrap();
+    assert_eq!(job.status, "Complete");
+}
+
+#[tokio::test]
+async fn package_updates() {
+    let db = make_config(None, None)
+        .await
+        .unwrap()
+        .get_db()
+        .await
+        .unwrap();
+
+    let first = chrono::Local::now() - chrono::Duration::hours(1);
+    for name in ["plex", "podman-test"] {
+        DbState::new_uncreated(PackageUpdate {
+            name: name.into(),
+            installed: "0.0.1".into(),
+            available: "0.0.2".into(),
+            checked: first,
+            ..Default::default()
+        })
+        .save(db.handle())
+        .await
+        .unwrap();
+    }
+
+    let mut plex = PackageUpdate::for_name(&db, "plex").await.unwrap().unwrap();
+    assert_eq!(plex.available, "0.0.2");
+    assert!(PackageUpdate::for_name(&db, "other")
+        .await
+        .unwrap()
+        .is_none());
+
+    let second = chrono::Local::now();
+    plex.checked = second;
+    plex.save(db.handle()).await.unwrap();
+
+    // podman-test was not seen by the second check
+    PackageUpdate::prune(&db, second).await.unwrap();
+    let list = PackageUpdate::list(&db).await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].name, "plex");
+}
+
+#[tokio::test]
+async fn migrate_new_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let filename = dir.path().join("new").join("gild.db");
+
+    crate::db::migrate(filename.clone()).await.unwrap();
+    assert!(filename.exists());
+
+    // migrating again finds nothing left to do
+    crate::db::migrate(filename).await.unwrap();
+}
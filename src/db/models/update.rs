@@ -0,0 +1,61 @@
+use crate::db::DB;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use welds::{exts::VecStateExt, state::DbState, WeldsModel};
+
+/// A newer version of an installed package, as of the last update check.
+#[derive(
+    Debug,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    WeldsModel,
+    Default,
+    Serialize,
+    Deserialize,
+    Validate,
+)]
+#[welds(table = "package_updates")]
+pub struct PackageUpdate {
+    #[welds(primary_key)]
+    pub id: u32,
+    pub name: String,
+    pub installed: String,
+    // the newest version in the registry
+    pub available: String,
+    // the new version's changelog, or a diff of the descriptions
+    pub changes: String,
+    pub checked: chrono::DateTime<chrono::Local>,
+}
+
+impl PackageUpdate {
+    pub async fn for_name(db: &DB, name: &str) -> Result<Option<DbState<Self>>> {
+        Ok(Self::all()
+            .where_col(|c| c.name.equal(name))
+            .limit(1)
+            .run(db.handle())
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    pub async fn list(db: &DB) -> Result<Vec<Self>> {
+        Ok(Self::all()
+            .order_by_asc(|c| c.name)
+            .run(db.handle())
+            .await?
+            .into_inners())
+    }
+
+    /// Removes cached updates for packages that are no longer behind.
+    pub async fn prune(db: &DB, checked: chrono::DateTime<chrono::Local>) -> Result<()> {
+        Self::all()
+            .where_col(|c| c.checked.lt(checked))
+            .delete(db.handle())
+            .await?;
+        Ok(())
+    }
+}
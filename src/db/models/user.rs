@@ -25,7 +25,7 @@ use crate::db::DB;
 )]
 #[welds(table = "users")]
 #[welds(HasMany(sessions, super::Session, "user_id"))]
-pub(crate) struct User {
+pub struct User {
     #[welds(primary_key)]
     #[welds(rename = "user_id")]
     #[serde(default = "u32::default")]
@@ -1,6 +1,11 @@
+#[cfg(any(test, feature = "client"))]
+pub mod client;
 pub mod config;
 pub mod db;
+pub mod packages;
 pub mod server;
+pub mod system;
+pub mod zfs;
 
 #[cfg(test)]
 pub mod testutil;
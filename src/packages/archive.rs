@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use charon::{PackageTitle, PromptResponses};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
+
+//
+// A package archive is a tar file holding a manifest and, optionally, a zfs send stream for each
+// of the package's volumes, named after the volume.
+//
+
+const MANIFEST: &str = "manifest.json";
+const VOLUMES: &str = "volumes";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageArchive {
+    pub package: PackageTitle,
+    pub responses: PromptResponses,
+    #[serde(default)]
+    pub volumes: Vec<ArchivedVolume>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedVolume {
+    pub name: String,
+    // relative to the pool
+    pub dataset: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl ArchivedVolume {
+    /// Where the volume's stream lives in a directory of streams. Names come from manifests, so
+    /// one that would lead out of the directory is refused.
+    pub fn stream(&self, dir: &Path) -> Result<std::path::PathBuf> {
+        if !super::path_safe(&self.name) || self.name.contains("..") {
+            return Err(anyhow!("invalid volume name in archive: {}", self.name));
+        }
+
+        Ok(dir.join(format!("{}.zfs", self.name)))
+    }
+}
+
+impl PackageArchive {
+    /// Writes the archive to `file`, taking volume streams from `streams`.
+    pub fn write(&self, file: &Path, streams: &Path) -> Result<()> {
+        let mut builder = tar::Builder::new(std::fs::File::create(file)?);
+
+        let manifest = serde_json::to_vec_pretty(self)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST, manifest.as_slice())?;
+
+        for volume in &self.volumes {
+            builder.append_path_with_name(
+                volume.stream(streams)?,
+                Path::new(VOLUMES).join(format!("{}.zfs", volume.name)),
+            )?;
+        }
+
+        builder.into_inner()?.sync_all()?;
+        Ok(())
+    }
+
+    /// Reads the archive in `file`, unpacking volume streams into `streams` if given.
+    pub fn read(file: &Path, streams: Option<&Path>) -> Result<Self> {
+        let mut archive = tar::Archive::new(std::fs::File::open(file)?);
+        let mut manifest: Option<Self> = None;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+
+            if path == Path::new(MANIFEST) {
+                manifest = Some(serde_json::from_reader(&mut entry)?);
+                // the manifest is written first, so there is nothing else to read
+                if streams.is_none() {
+                    break;
+                }
+            } else if let Some(streams) =
+                streams.filter(|_| path.parent() == Some(Path::new(VOLUMES)))
+            {
+                // only plain files named by a single component are unpacked, so entries can
+                // neither escape the directory nor link out of it
+                let name = path.strip_prefix(VOLUMES)?;
+                let mut components = name.components();
+                if entry.header().entry_type() != tar::EntryType::Regular
+                    || !matches!(
+                        (components.next(), components.next()),
+                        (Some(Component::Normal(_)), None)
+                    )
+                {
+                    return Err(anyhow!("invalid volume in archive: {}", path.display()));
+                }
+                entry.unpack(streams.join(name))?;
+            }
+        }
+
+        let manifest = manifest.ok_or(anyhow!("archive has no {}", MANIFEST))?;
+        if let Some(streams) = streams {
+            for volume in &manifest.volumes {
+                if !volume.stream(streams)?.exists() {
+                    return Err(anyhow!("archive is missing volume {}", volume.name));
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+}
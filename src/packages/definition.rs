@@ -0,0 +1,174 @@
+use super::Package;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+//
+// Checks of package definitions uploaded by hand, against the fields gild reads; charon's own
+// schema is not checked. Every problem points at the part of the document it is about, as a JSON
+// path such as `$.storage.volumes[0].size`.
+//
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefinitionError {
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl DefinitionError {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Whether a name or version can be used as a path component inside a registry.
+pub fn path_safe(value: &str) -> bool {
+    !value.is_empty() && !value.contains('/') && !value.starts_with('.')
+}
+
+/// Parses a package definition, returning every problem found with it. Problems with the shape
+/// of the document stop at the first one, as nothing after it can be read.
+///
+/// charon does not export its definition type, so this reads into gild's [Package], which only
+/// covers the fields gild uses. Fields only charon reads are not checked here, and charon can
+/// still refuse a definition that passes.
+pub fn validate_definition(definition: &[u8]) -> Result<Package, Vec<DefinitionError>> {
+    let value: Value = serde_json::from_slice(definition).map_err(|e| {
+        vec![DefinitionError::new(
+            "$",
+            format!("invalid JSON at line {} column {}", e.line(), e.column()),
+        )]
+    })?;
+
+    let package: Package = serde_path_to_error::deserialize(&value).map_err(|e| {
+        let path = e.path().to_string();
+        vec![DefinitionError::new(
+            match path.as_str() {
+                "." => "$".to_string(),
+                _ => format!("$.{}", path),
+            },
+            e.into_inner().to_string(),
+        )]
+    })?;
+
+    let mut errors = Vec::new();
+
+    for (field, value) in [
+        ("name", &package.title.name),
+        ("version", &package.title.version),
+    ] {
+        if value.is_empty() {
+            errors.push(DefinitionError::new(
+                format!("$.title.{}", field),
+                "must not be empty",
+            ));
+        } else if !path_safe(value) {
+            errors.push(DefinitionError::new(
+                format!("$.title.{}", field),
+                "must not contain / or start with .",
+            ));
+        }
+    }
+
+    for (i, dependency) in package.dependencies.iter().enumerate() {
+        if dependency.name.is_empty() || dependency.version.is_empty() {
+            errors.push(DefinitionError::new(
+                format!("$.dependencies[{}]", i),
+                "needs a name and a version",
+            ));
+        }
+    }
+
+    for (i, prompt) in package.prompts.iter().enumerate() {
+        if package.prompts[..i]
+            .iter()
+            .any(|other| other.template == prompt.template)
+        {
+            errors.push(DefinitionError::new(
+                format!("$.prompts[{}].template", i),
+                format!("{} is already defined", prompt.template),
+            ));
+        }
+    }
+
+    // every @template@ has to be asked for
+    let mut templates = Vec::new();
+    collect_templates(&value, "$".into(), &mut templates);
+    for (path, template) in templates {
+        if !package.prompts.iter().any(|p| p.template == template) {
+            errors.push(DefinitionError::new(
+                path,
+                format!("no prompt for template @{}@", template),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(package)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The @template@ references in a string with the byte range each covers, such as `@port@` in
+/// `localhost:@port@`. An `@` that does not open one, as in an email address, is left alone.
+pub fn templates(s: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+
+    while let Some(start) = s[from..].find('@').map(|i| from + i) {
+        let Some(end) = s[start + 1..].find('@').map(|i| start + 1 + i) else {
+            break;
+        };
+
+        let name = &s[start + 1..end];
+        if !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            found.push((start..end + 1, name));
+            from = end + 1;
+        } else {
+            // the closing @ may open the next one
+            from = end;
+        }
+    }
+
+    found
+}
+
+fn collect_templates(value: &Value, path: String, found: &mut Vec<(String, String)>) {
+    match value {
+        Value::String(s) => {
+            for (_, template) in templates(s) {
+                if !found.iter().any(|(p, t)| *p == path && t == template) {
+                    found.push((path.clone(), template.to_string()));
+                }
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                collect_templates(value, format!("{}[{}]", path, i), found);
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                // prompts describe templates rather than use them
+                if path == "$" && key == "prompts" {
+                    continue;
+                }
+                collect_templates(value, format!("{}.{}", path, key), found);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,1171 @@
+mod archive;
+mod definition;
+mod render;
+#[cfg(test)]
+mod tests;
+
+pub use self::{archive::*, definition::*, render::*};
+
+use anyhow::{anyhow, Result};
+use charon::{Input, InputType, PackageTitle, Prompt, PromptResponse, PromptResponses};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+};
+
+//
+// charon's query API does not expose package definitions, so the parts gild needs (such as
+// dependencies) are read from the registry charon is configured with.
+//
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Package {
+    pub title: PackageTitle,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+    #[serde(default)]
+    pub dependencies: Vec<PackageTitle>,
+    #[serde(default)]
+    pub prompts: Vec<Prompt>,
+    #[serde(default)]
+    pub source: Source,
+    #[serde(default)]
+    pub storage: Storage,
+    #[serde(default)]
+    pub resources: Resources,
+    #[serde(default)]
+    pub system: System,
+    #[serde(default)]
+    pub networking: Networking,
+}
+
+impl Default for Package {
+    fn default() -> Self {
+        Self {
+            title: PackageTitle {
+                name: String::new(),
+                version: String::new(),
+            },
+            description: String::new(),
+            changelog: None,
+            dependencies: Vec::new(),
+            prompts: Vec::new(),
+            source: Source::default(),
+            storage: Storage::default(),
+            resources: Resources::default(),
+            system: System::default(),
+            networking: Networking::default(),
+        }
+    }
+}
+
+impl Package {
+    pub fn privileges(&self) -> Privileges {
+        let enabled = |value: &Option<String>| value.as_deref() == Some("true");
+
+        Privileges {
+            host_pid: enabled(&self.system.host_pid),
+            host_net: enabled(&self.system.host_net),
+            privileged: enabled(&self.system.privileged),
+            capabilities: self.system.capabilities.clone(),
+        }
+    }
+}
+
+/// The access to the host a package asks for beyond an ordinary container.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Privileges {
+    pub host_pid: bool,
+    pub host_net: bool,
+    pub privileged: bool,
+    pub capabilities: Vec<String>,
+}
+
+impl Privileges {
+    pub fn is_elevated(&self) -> bool {
+        !self.describe().is_empty()
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let mut items = Vec::new();
+        for (name, enabled) in [
+            ("host_pid", self.host_pid),
+            ("host_net", self.host_net),
+            ("privileged", self.privileged),
+        ] {
+            if enabled {
+                items.push(name.to_string());
+            }
+        }
+        items.extend(self.capabilities.iter().cloned());
+        items
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SourceKind {
+    Container,
+    // disk images run under QEMU
+    Url,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Source {
+    pub fn kind(&self) -> Option<SourceKind> {
+        if self.container.is_some() {
+            Some(SourceKind::Container)
+        } else if self.url.is_some() {
+            Some(SourceKind::Url)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resources {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<String>,
+    // in megabytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct System {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_pid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_net: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privileged: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Networking {
+    // pairs of host and guest ports
+    #[serde(default)]
+    pub forward_ports: Vec<(String, String)>,
+    #[serde(default)]
+    pub expose_ports: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchQuery {
+    // matches part of the name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // matches part of the description, ignoring case
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceKind>,
+}
+
+impl SearchQuery {
+    pub fn matches(&self, package: &Package) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|name| package.title.name.contains(name.as_str()))
+            && self.text.as_ref().is_none_or(|text| {
+                package
+                    .description
+                    .to_lowercase()
+                    .contains(&text.to_lowercase())
+            })
+            && self
+                .source
+                .is_none_or(|source| package.source.kind() == Some(source))
+    }
+}
+
+/// Every version of a package, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackageVersions {
+    pub name: String,
+    pub versions: Vec<Package>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub packages: Vec<PackageVersions>,
+    // package definitions that could not be read, and so were not searched
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Storage {
+    #[serde(default)]
+    pub volumes: Vec<Volume>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Volume {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mountpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private: Option<String>,
+    // "true", "false" or a prompt template such as "@private_recreate@"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recreate: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    pub package: PackageTitle,
+    // the package that pulled this one in; none for the package being installed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_by: Option<PackageTitle>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conflict {
+    pub package: PackageTitle,
+    pub conflicts_with: PackageTitle,
+    // whether the other version is installed, or also required by the plan
+    pub installed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub package: PackageTitle,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_by: Option<PackageTitle>,
+    pub installed: bool,
+    pub unanswered: Vec<Prompt>,
+    #[serde(default)]
+    pub privileges: Privileges,
+}
+
+/// Everything installing a package involves, dependencies first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstallPlan {
+    pub steps: Vec<PlanStep>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl InstallPlan {
+    /// Explains why the plan cannot be installed, if it can't.
+    pub fn check(&self) -> Result<()> {
+        if let Some(conflict) = self.conflicts.first() {
+            return Err(anyhow!(
+                "{} {} conflicts with {} {}",
+                conflict.package.name,
+                conflict.package.version,
+                conflict.conflicts_with.name,
+                conflict.conflicts_with.version
+            ));
+        }
+
+        if let Some(step) = self.steps.iter().find(|step| !step.unanswered.is_empty()) {
+            return Err(anyhow!(
+                "{} {} has unanswered prompts: {}",
+                step.package.name,
+                step.package.version,
+                step.unanswered
+                    .iter()
+                    .map(|prompt| prompt.template.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Steps that would install a package asking for elevated privileges.
+    pub fn privileged(&self) -> Vec<&PlanStep> {
+        self.steps
+            .iter()
+            .filter(|step| !step.installed && step.privileges.is_elevated())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Registry {
+    path: PathBuf,
+}
+
+impl Registry {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self, title: &PackageTitle) -> Result<Package> {
+        let file = std::fs::File::open(self.file(title)?)
+            .map_err(|_| anyhow!("package {} {} does not exist", title.name, title.version))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn resolve(&self, title: &PackageTitle) -> Result<Vec<Dependency>> {
+        resolve(title, |title| Ok(self.load(title)?.dependencies))
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    fn file(&self, title: &PackageTitle) -> Result<PathBuf> {
+        // both end up in the path, so neither may lead out of the registry
+        if !path_safe(&title.name) || !path_safe(&title.version) {
+            return Err(anyhow!(
+                "invalid package {} {}: names and versions must not contain / or start with .",
+                title.name,
+                title.version
+            ));
+        }
+
+        Ok(self
+            .path
+            .join("packages")
+            .join(&title.name)
+            .join(format!("{}.json", title.version)))
+    }
+
+    /// Every package in the registry, sorted by name and then version.
+    pub fn list(&self) -> Result<Vec<PackageTitle>> {
+        let mut titles = Vec::new();
+
+        let dirs = match std::fs::read_dir(self.path.join("packages")) {
+            Ok(dirs) => dirs,
+            // nothing has been written to it yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(titles),
+            Err(e) => return Err(e.into()),
+        };
+
+        for dir in dirs {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+
+            for file in std::fs::read_dir(dir.path())? {
+                let path = file?.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+
+                if let Some(version) = path.file_stem() {
+                    titles.push(PackageTitle {
+                        name: dir.file_name().to_string_lossy().to_string(),
+                        version: version.to_string_lossy().to_string(),
+                    });
+                }
+            }
+        }
+
+        titles.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| compare_versions(&a.version, &b.version))
+        });
+        Ok(titles)
+    }
+
+    /// Writes a package definition into the registry, replacing any with the same title.
+    pub fn write(&self, title: &PackageTitle, definition: &[u8]) -> Result<()> {
+        let file = self.file(title)?;
+        std::fs::create_dir_all(file.parent().unwrap())?;
+        Ok(std::fs::write(file, definition)?)
+    }
+
+    /// Loads every package in the registry, returning the ones that fail to parse as errors.
+    pub fn refresh(&self) -> Result<Vec<String>> {
+        Ok(self
+            .list()?
+            .iter()
+            .filter_map(|title| {
+                self.load(title)
+                    .err()
+                    .map(|e| format!("{} {}: {}", title.name, title.version, e))
+            })
+            .collect())
+    }
+}
+
+//
+// Registries beyond the one charon is configured with are only read by gild. Installing a package
+// from one copies its definition into charon's registry first; those copies are recorded, so they
+// are listed under the registry they came from and removed along with it.
+//
+
+pub const DEFAULT_REGISTRY: &str = "default";
+// where hand-written packages are uploaded to
+pub const SIDELOAD_REGISTRY: &str = "sideload";
+
+/// A package and the registry it came from. The name is qualified as `registry/name` when an
+/// earlier registry ships a package of the same name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryPackage {
+    #[serde(flatten)]
+    pub title: PackageTitle,
+    pub registry: String,
+}
+
+/// Registries in search order; charon's registry is always first.
+#[derive(Debug, Clone)]
+pub struct Registries {
+    registries: Vec<(String, Registry)>,
+    // definitions copied into charon's registry, with the registry they came from
+    staged: Vec<RegistryPackage>,
+}
+
+impl Registries {
+    pub fn new(default: Registry) -> Self {
+        Self {
+            registries: vec![(DEFAULT_REGISTRY.into(), default)],
+            staged: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, registry: Registry) {
+        self.registries.push((name.into(), registry));
+    }
+
+    pub fn add_staged(&mut self, staged: RegistryPackage) {
+        self.staged.push(staged);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Registry> {
+        self.registries
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, registry)| registry)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.registries.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Every package in every registry, in search order. Staged copies are only listed under the
+    /// registry they came from.
+    pub fn list(&self) -> Result<Vec<RegistryPackage>> {
+        let mut packages: Vec<RegistryPackage> = Vec::new();
+
+        for (name, registry) in &self.registries {
+            for mut title in registry.list()? {
+                if name == DEFAULT_REGISTRY && self.staged.iter().any(|s| s.title == title) {
+                    continue;
+                }
+
+                if packages
+                    .iter()
+                    .any(|p| p.title.name == title.name && p.registry != *name)
+                {
+                    title.name = format!("{}/{}", name, title.name);
+                }
+                packages.push(RegistryPackage {
+                    title,
+                    registry: name.clone(),
+                });
+            }
+        }
+
+        Ok(packages)
+    }
+
+    /// Finds the registry a possibly qualified title refers to, returning it with the bare title.
+    /// Unqualified names are looked up in search order.
+    pub fn find(&self, title: &PackageTitle) -> Result<(&str, &Registry, PackageTitle)> {
+        if let Some((registry, name)) = title.name.split_once('/') {
+            let bare = PackageTitle {
+                name: name.into(),
+                version: title.version.clone(),
+            };
+            return match self.registries.iter().find(|(n, _)| n == registry) {
+                Some((n, r)) if r.file(&bare)?.exists() => Ok((n, r, bare)),
+                Some(_) => Err(anyhow!(
+                    "package {} {} does not exist",
+                    title.name,
+                    title.version
+                )),
+                None => Err(anyhow!("no registry named {}", registry)),
+            };
+        }
+
+        self.registries
+            .iter()
+            .find(|(_, registry)| registry.file(title).is_ok_and(|file| file.exists()))
+            .map(|(name, registry)| (name.as_str(), registry, title.clone()))
+            .ok_or(anyhow!(
+                "package {} {} does not exist",
+                title.name,
+                title.version
+            ))
+    }
+
+    pub fn load(&self, title: &PackageTitle) -> Result<Package> {
+        let (_, registry, title) = self.find(title)?;
+        registry.load(&title)
+    }
+
+    /// A package's definition as it is in its registry, including the fields only charon reads.
+    pub fn definition(&self, title: &PackageTitle) -> Result<serde_json::Value> {
+        let (_, registry, title) = self.find(title)?;
+        Ok(serde_json::from_reader(std::fs::File::open(
+            registry.file(&title)?,
+        )?)?)
+    }
+
+    /// The install order of a possibly qualified title, with the registry each package comes from
+    /// and its bare title. Dependencies come from the package's own registry when it has them.
+    pub fn resolve(&self, title: &PackageTitle) -> Result<Vec<(&str, &Registry, Dependency)>> {
+        let (name, registry, bare) = self.find(title)?;
+        let order = resolve(&bare, |title| {
+            Ok(match registry.load(title) {
+                Ok(package) => package.dependencies,
+                Err(_) => self.load(title)?.dependencies,
+            })
+        })?;
+
+        order
+            .into_iter()
+            .map(|dep| match registry.file(&dep.package)?.exists() {
+                true => Ok((name, registry, dep)),
+                false => {
+                    let (name, registry, _) = self.find(&dep.package)?;
+                    Ok((name, registry, dep))
+                }
+            })
+            .collect()
+    }
+
+    /// Copies a package and its dependencies into charon's registry so they can be installed,
+    /// returning the bare title and the definitions that were copied. Definitions already there
+    /// are left alone if they are the same.
+    pub fn stage(&self, title: &PackageTitle) -> Result<(PackageTitle, Vec<RegistryPackage>)> {
+        let (_, _, bare) = self.find(title)?;
+        let default = &self.registries[0].1;
+
+        let mut copied = Vec::new();
+        for (name, source, dep) in self.resolve(title)? {
+            let from = source.file(&dep.package)?;
+            let to = default.file(&dep.package)?;
+            if name == DEFAULT_REGISTRY || from == to {
+                continue;
+            }
+
+            if to.exists() {
+                if std::fs::read(&to)? != std::fs::read(&from)? {
+                    return Err(anyhow!(
+                        "a different {} {} is already in registry {}",
+                        dep.package.name,
+                        dep.package.version,
+                        DEFAULT_REGISTRY
+                    ));
+                }
+                continue;
+            }
+
+            std::fs::create_dir_all(to.parent().unwrap())?;
+            std::fs::copy(&from, &to)?;
+            copied.push(RegistryPackage {
+                title: dep.package,
+                registry: name.into(),
+            });
+        }
+
+        Ok((bare, copied))
+    }
+
+    /// Removes a staged copy from charon's registry.
+    pub fn unstage(&self, title: &PackageTitle) -> Result<()> {
+        let file = self.registries[0].1.file(title)?;
+        if file.exists() {
+            std::fs::remove_file(&file)?;
+            // only goes when no other version is left
+            let _ = std::fs::remove_dir(file.parent().unwrap());
+        }
+        Ok(())
+    }
+}
+
+/// Walks the dependency tree of `root`, returning each package once, after everything it depends
+/// on. `root` is last.
+pub fn resolve(
+    root: &PackageTitle,
+    dependencies: impl Fn(&PackageTitle) -> Result<Vec<PackageTitle>>,
+) -> Result<Vec<Dependency>> {
+    let mut order = Vec::new();
+    let mut path = Vec::new();
+    visit(root, None, &dependencies, &mut path, &mut order)?;
+    Ok(order)
+}
+
+fn visit(
+    title: &PackageTitle,
+    required_by: Option<&PackageTitle>,
+    dependencies: &impl Fn(&PackageTitle) -> Result<Vec<PackageTitle>>,
+    path: &mut Vec<PackageTitle>,
+    order: &mut Vec<Dependency>,
+) -> Result<()> {
+    if path.contains(title) {
+        return Err(anyhow!(
+            "dependency cycle: {} {} requires itself",
+            title.name,
+            title.version
+        ));
+    }
+
+    if order.iter().any(|dep| &dep.package == title) {
+        return Ok(());
+    }
+
+    path.push(title.clone());
+    for dependency in dependencies(title)? {
+        visit(&dependency, Some(title), dependencies, path, order)?;
+    }
+    path.pop();
+
+    order.push(Dependency {
+        package: title.clone(),
+        required_by: required_by.cloned(),
+    });
+
+    Ok(())
+}
+
+/// Groups packages by name, sorting names and then versions.
+pub fn group(mut packages: Vec<Package>) -> Vec<PackageVersions> {
+    packages.sort_by(|a, b| {
+        a.title
+            .name
+            .cmp(&b.title.name)
+            .then_with(|| compare_versions(&a.title.version, &b.title.version))
+    });
+
+    let mut groups: Vec<PackageVersions> = Vec::new();
+    for package in packages {
+        match groups.last_mut() {
+            Some(group) if group.name == package.title.name => group.versions.push(package),
+            _ => groups.push(PackageVersions {
+                name: package.title.name.clone(),
+                versions: vec![package],
+            }),
+        }
+    }
+
+    groups
+}
+
+/// Orders versions by their dot-separated parts, numerically where both parts are numbers.
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            },
+        };
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// What changed in `new`: its changelog if it has one, otherwise the description lines that were
+/// removed (-) and added (+).
+pub fn changes(old: &Package, new: &Package) -> String {
+    if let Some(changelog) = &new.changelog {
+        return changelog.clone();
+    }
+
+    let old_lines: Vec<&str> = old.description.lines().collect();
+    let new_lines: Vec<&str> = new.description.lines().collect();
+
+    old_lines
+        .iter()
+        .filter(|line| !new_lines.contains(line))
+        .map(|line| format!("-{}", line))
+        .chain(
+            new_lines
+                .iter()
+                .filter(|line| !old_lines.contains(line))
+                .map(|line| format!("+{}", line)),
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prompts that have no response yet.
+pub fn unanswered(prompts: Vec<Prompt>, responses: &PromptResponses) -> Vec<Prompt> {
+    prompts
+        .into_iter()
+        .filter(|prompt| !responses.0.iter().any(|response| answers(prompt, response)))
+        .collect()
+}
+
+fn answers(prompt: &Prompt, response: &PromptResponse) -> bool {
+    response.template == prompt.template
+        && matches!(
+            (&prompt.input_type, &response.input),
+            (InputType::String, Input::String(_))
+                | (InputType::Integer, Input::Integer(_))
+                | (InputType::Boolean, Input::Boolean(_))
+        )
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ResponseProblem {
+    Missing,
+    Unknown,
+    WrongType(InputType),
+}
+
+/// A response that does not fit the package's prompts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub template: String,
+    pub problem: ResponseProblem,
+}
+
+impl std::fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.problem {
+            ResponseProblem::Missing => write!(f, "{}: no response given", self.template),
+            ResponseProblem::Unknown => write!(f, "{}: no such prompt", self.template),
+            ResponseProblem::WrongType(input_type) => write!(
+                f,
+                "{}: expected {}",
+                self.template,
+                match input_type {
+                    InputType::String => "a string",
+                    InputType::Integer => "an integer",
+                    InputType::Boolean => "a boolean",
+                }
+            ),
+        }
+    }
+}
+
+/// Checks responses against the prompts they answer, one error per offending template.
+pub fn validate_responses(prompts: &[Prompt], responses: &PromptResponses) -> Vec<ResponseError> {
+    let mut errors = Vec::new();
+
+    for response in &responses.0 {
+        let problem = match prompts
+            .iter()
+            .find(|prompt| prompt.template == response.template)
+        {
+            None => ResponseProblem::Unknown,
+            Some(prompt) if !answers(prompt, response) => {
+                ResponseProblem::WrongType(prompt.input_type.clone())
+            }
+            Some(_) => continue,
+        };
+
+        errors.push(ResponseError {
+            template: response.template.clone(),
+            problem,
+        });
+    }
+
+    for prompt in prompts {
+        if !responses
+            .0
+            .iter()
+            .any(|response| response.template == prompt.template)
+        {
+            errors.push(ResponseError {
+                template: prompt.template.clone(),
+                problem: ResponseProblem::Missing,
+            });
+        }
+    }
+
+    errors
+}
+
+/// Volumes kept from `old` that installing `new` would recreate, losing their data.
+pub fn recreated_volumes(old: &Package, new: &Package, responses: &PromptResponses) -> Vec<String> {
+    new.storage
+        .volumes
+        .iter()
+        .filter(|volume| old.storage.volumes.iter().any(|v| v.name == volume.name))
+        .filter(|volume| match volume.recreate.as_deref() {
+            Some("true") => true,
+            Some(recreate) => template_response(recreate, responses) == Some(&Input::Boolean(true)),
+            None => false,
+        })
+        .map(|volume| volume.name.clone())
+        .collect()
+}
+
+/// The response a value such as "@private_size@" stands for.
+fn template_response<'a>(value: &str, responses: &'a PromptResponses) -> Option<&'a Input> {
+    let template = value.strip_prefix('@')?.strip_suffix('@')?;
+    responses
+        .0
+        .iter()
+        .find(|response| response.template == template)
+        .map(|response| &response.input)
+}
+
+/// The size a volume was declared with, in bytes, filled in from responses if templated.
+pub fn volume_size(volume: &Volume, responses: &PromptResponses) -> Option<u64> {
+    let size = volume.size.as_deref()?;
+    match template_response(size, responses) {
+        Some(Input::Integer(size)) => Some(*size),
+        Some(_) => None,
+        None => size.parse().ok(),
+    }
+}
+
+/// The journals of a package's units, merged into one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageLog {
+    pub entries: Vec<buckle::systemd::LogMessage>,
+    // by unit name, where each unit's journal was left; passed back to read the next page
+    pub cursors: HashMap<String, String>,
+}
+
+/// Merges the journals of several units, each already in the order it was read in, keeping the
+/// first `count` entries. Entries go oldest first, or newest first when reading backward. The
+/// cursors start as the ones the journals were read from and move past every entry kept.
+pub fn merge_logs(
+    logs: Vec<(String, Vec<buckle::systemd::LogMessage>)>,
+    count: usize,
+    backward: bool,
+    mut cursors: HashMap<String, String>,
+) -> PackageLog {
+    let mut logs: Vec<(String, VecDeque<_>)> = logs
+        .into_iter()
+        .map(|(unit, entries)| (unit, entries.into()))
+        .collect();
+
+    let mut entries = Vec::with_capacity(count);
+    while entries.len() < count {
+        // ties go to the unit listed first
+        let Some(next) = logs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, log))| log.front().map(|entry| (i, entry.time)))
+            .min_by(|(_, a), (_, b)| match backward {
+                true => b.cmp(a),
+                false => a.cmp(b),
+            })
+            .map(|(i, _)| i)
+        else {
+            break;
+        };
+
+        let (unit, log) = &mut logs[next];
+        let entry = log.pop_front().unwrap();
+        cursors.insert(unit.clone(), entry.cursor.clone());
+        entries.push(entry);
+    }
+
+    PackageLog { entries, cursors }
+}
+
+/// The dataset charon creates for a volume; private volumes belong to a single package.
+pub fn volume_dataset(title: &PackageTitle, volume: &Volume) -> String {
+    if volume.private.as_deref() == Some("true") {
+        format!("{}/{}", title.name, volume.name)
+    } else {
+        volume.name.clone()
+    }
+}
+
+/// What happens to a volume when its package is uninstalled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Retention {
+    #[default]
+    Keep,
+    // kept, with a snapshot to return to
+    Snapshot,
+    Destroy,
+}
+
+/// A volume of a package being uninstalled, and the other installed packages still using it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeRetention {
+    pub name: String,
+    pub dataset: String,
+    pub private: bool,
+    pub shared_with: Vec<PackageTitle>,
+}
+
+/// The volumes of `package`, given the other installed packages.
+pub fn volume_retention(package: &Package, others: &[Package]) -> Vec<VolumeRetention> {
+    package
+        .storage
+        .volumes
+        .iter()
+        .map(|volume| {
+            let dataset = volume_dataset(&package.title, volume);
+            VolumeRetention {
+                name: volume.name.clone(),
+                private: volume.private.as_deref() == Some("true"),
+                shared_with: others
+                    .iter()
+                    .filter(|other| other.title.name != package.title.name)
+                    .filter(|other| {
+                        other
+                            .storage
+                            .volumes
+                            .iter()
+                            .any(|v| volume_dataset(&other.title, v) == dataset)
+                    })
+                    .map(|other| other.title.clone())
+                    .collect(),
+                dataset,
+            }
+        })
+        .collect()
+}
+
+/// Refuses choices for volumes the package does not have, or that would destroy a volume another
+/// package still uses.
+pub fn check_retention(
+    volumes: &[VolumeRetention],
+    choices: &std::collections::HashMap<String, Retention>,
+) -> Result<()> {
+    for (name, retention) in choices {
+        let volume = volumes
+            .iter()
+            .find(|volume| &volume.name == name)
+            .ok_or(anyhow!("no volume named {}", name))?;
+
+        if *retention == Retention::Destroy && !volume.shared_with.is_empty() {
+            return Err(anyhow!(
+                "volume {} is still used by {}",
+                name,
+                volume
+                    .shared_with
+                    .iter()
+                    .map(|title| format!("{} {}", title.name, title.version))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The units buckle reports for a package. charon names each one after the package and version,
+/// so "plex-0.0.1.service" belongs to plex 0.0.1 but not to plex 0.0.10.
+pub async fn package_units(
+    buckle: &buckle::client::Client,
+    title: &PackageTitle,
+) -> Result<Vec<buckle::systemd::Unit>> {
+    let prefix = format!("{}-{}", title.name, title.version);
+    Ok(buckle
+        .systemd()
+        .await?
+        .list(Some(prefix.clone()))
+        .await?
+        .into_iter()
+        .filter(|unit| {
+            unit.name
+                .strip_prefix(&prefix)
+                .is_some_and(|rest| rest.starts_with(['.', '@', '-']))
+        })
+        .collect())
+}
+
+/// Sums up what buckle reports about a unit as "active", "failed" or "inactive".
+pub fn unit_state(unit: &buckle::systemd::Unit) -> &'static str {
+    use buckle::systemd::{LastRunState, LoadState};
+
+    if unit.status.load_state != LoadState::Loaded {
+        return "inactive";
+    }
+
+    match unit.status.last_run_state {
+        LastRunState::Failed => "failed",
+        LastRunState::Dead | LastRunState::Exited => "inactive",
+        LastRunState::Running
+        | LastRunState::Listening
+        | LastRunState::Mounted
+        | LastRunState::Plugged
+        | LastRunState::Active
+        | LastRunState::Waiting => "active",
+    }
+}
+
+/// What buckle reports about a unit and the resources it is using.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitStatus {
+    pub name: String,
+    // as summed up by [unit_state]
+    pub active_state: String,
+    // in nanoseconds, since the unit started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_usage: Option<u64>,
+    // in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u64>,
+}
+
+// buckle does not report accounting, so it is read from the unit's cgroup
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/system.slice";
+
+pub fn unit_status(unit: &buckle::systemd::Unit) -> UnitStatus {
+    let cgroup = std::path::Path::new(CGROUP_ROOT).join(&unit.name);
+    let read = |file| std::fs::read_to_string(cgroup.join(file)).ok();
+
+    UnitStatus {
+        name: unit.name.clone(),
+        active_state: unit_state(unit).into(),
+        cpu_usage: read("cpu.stat").and_then(|stat| parse_cpu_stat(&stat)),
+        memory: read("memory.current").and_then(|memory| memory.trim().parse().ok()),
+    }
+}
+
+/// The status of every unit belonging to a package. A package without units has either not
+/// been started by charon or lost its units, and is an error either way.
+pub async fn package_unit_status(
+    buckle: &buckle::client::Client,
+    title: &PackageTitle,
+) -> Result<Vec<UnitStatus>> {
+    let units = package_units(buckle, title).await?;
+    if units.is_empty() {
+        return Err(anyhow!(
+            "systemd has no units for {} {}",
+            title.name,
+            title.version
+        ));
+    }
+
+    Ok(units.iter().map(unit_status).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnitAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+/// Starts, stops or restarts every unit of a package, returning the states they are left in.
+/// Units stay enabled or disabled as they were.
+pub async fn control_units(
+    buckle: &buckle::client::Client,
+    title: &PackageTitle,
+    action: UnitAction,
+) -> Result<Vec<UnitStatus>> {
+    use buckle::systemd::{RuntimeState, UnitSettings};
+
+    let runtime_state = match action {
+        UnitAction::Start => RuntimeState::Started,
+        UnitAction::Stop => RuntimeState::Stopped,
+        UnitAction::Restart => RuntimeState::Restarted,
+    };
+
+    let units = package_units(buckle, title).await?;
+    if units.is_empty() {
+        return Err(anyhow!(
+            "systemd has no units for {} {}",
+            title.name,
+            title.version
+        ));
+    }
+
+    for unit in &units {
+        buckle
+            .systemd()
+            .await?
+            .set_unit(UnitSettings {
+                name: unit.name.clone(),
+                enabled_state: unit.enabled_state,
+                runtime_state,
+            })
+            .await?;
+    }
+
+    package_unit_status(buckle, title).await
+}
+
+/// Reads the CPU time from a cgroup's `cpu.stat`, which counts in microseconds, as nanoseconds.
+pub(crate) fn parse_cpu_stat(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .and_then(|usec| usec.trim().parse::<u64>().ok())
+        .map(|usec| usec * 1000)
+}
+
+/// The storage a volume is using.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeUsage {
+    pub name: String,
+    pub dataset: String,
+    // declared, in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    // in bytes; missing when the dataset could not be found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<u64>,
+}
+
+/// The runtime state of an installed package.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageStatus {
+    pub package: PackageTitle,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceKind>,
+    pub running: bool,
+    pub units: Vec<UnitStatus>,
+    // declared by the package
+    pub resources: Resources,
+    pub volumes: Vec<VolumeUsage>,
+    // why the package or its units could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Packages in the resolved tree whose name matches a different version, either installed or
+/// elsewhere in the tree.
+pub fn conflicts(order: &[Dependency], installed: &[PackageTitle]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+
+    for (i, dep) in order.iter().enumerate() {
+        let package = &dep.package;
+
+        for other in installed {
+            if other.name == package.name && other.version != package.version {
+                conflicts.push(Conflict {
+                    package: package.clone(),
+                    conflicts_with: other.clone(),
+                    installed: true,
+                });
+            }
+        }
+
+        for other in &order[..i] {
+            if other.package.name == package.name && other.package.version != package.version {
+                conflicts.push(Conflict {
+                    package: package.clone(),
+                    conflicts_with: other.package.clone(),
+                    installed: false,
+                });
+            }
+        }
+    }
+
+    conflicts
+}
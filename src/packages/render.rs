@@ -0,0 +1,121 @@
+use super::{templates, volume_dataset, volume_size, Package};
+use anyhow::Result;
+use charon::{Input, PromptResponses};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+//
+// Rendering fills a package's @template@ values in from prompt responses, the way charon does at
+// install time, so what an install would do can be seen without doing it.
+//
+
+/// What installing a package would do. charon writes a package's units at install time and has no
+/// call to render them beforehand, so they are not part of it; the rendered `source`, `resources`
+/// and `networking` are what they are made from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DryRun {
+    pub package: Package,
+    pub volumes: Vec<PlannedVolume>,
+    // templates with no response, left as they were
+    pub unresolved: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedVolume {
+    pub name: String,
+    pub dataset: String,
+    pub size: Option<u64>,
+    pub mountpoint: Option<String>,
+    // the dataset is already there, so it is not created
+    pub exists: bool,
+}
+
+/// Renders `package` with `responses`. `exists` says whether a dataset is already in the pool.
+pub fn dry_run(
+    package: &Package,
+    responses: &PromptResponses,
+    exists: impl Fn(&str) -> bool,
+) -> Result<DryRun> {
+    let (package, unresolved) = render(package, responses)?;
+
+    let volumes = package
+        .storage
+        .volumes
+        .iter()
+        .map(|volume| {
+            let dataset = volume_dataset(&package.title, volume);
+            PlannedVolume {
+                name: volume.name.clone(),
+                size: volume_size(volume, responses),
+                mountpoint: volume.mountpoint.clone(),
+                exists: exists(&dataset),
+                dataset,
+            }
+        })
+        .collect();
+
+    Ok(DryRun {
+        volumes,
+        package,
+        unresolved,
+    })
+}
+
+/// Substitutes responses for templates, returning the rendered package and the templates that had
+/// no response.
+pub fn render(package: &Package, responses: &PromptResponses) -> Result<(Package, Vec<String>)> {
+    let mut value = serde_json::to_value(package)?;
+    let mut unresolved = Vec::new();
+
+    if let Value::Object(map) = &mut value {
+        for (key, value) in map.iter_mut() {
+            // prompts describe templates rather than use them
+            if key != "prompts" {
+                substitute(value, responses, &mut unresolved);
+            }
+        }
+    }
+
+    Ok((serde_json::from_value(value)?, unresolved))
+}
+
+fn substitute(value: &mut Value, responses: &PromptResponses, unresolved: &mut Vec<String>) {
+    match value {
+        Value::String(s) => {
+            let mut rendered = String::new();
+            let mut from = 0;
+
+            for (range, template) in templates(s) {
+                rendered.push_str(&s[from..range.start]);
+                match responses.0.iter().find(|r| r.template == template) {
+                    Some(response) => match &response.input {
+                        Input::String(s) => rendered.push_str(s),
+                        Input::Integer(i) => rendered.push_str(&i.to_string()),
+                        Input::Boolean(b) => rendered.push_str(&b.to_string()),
+                    },
+                    None => {
+                        rendered.push_str(&s[range.clone()]);
+                        if !unresolved.iter().any(|t| t == template) {
+                            unresolved.push(template.to_string());
+                        }
+                    }
+                }
+                from = range.end;
+            }
+
+            rendered.push_str(&s[from..]);
+            *s = rendered;
+        }
+        Value::Array(values) => {
+            for value in values {
+                substitute(value, responses, unresolved);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                substitute(value, responses, unresolved);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,810 @@
+use super::*;
+
+fn title(name: &str, version: &str) -> PackageTitle {
+    PackageTitle {
+        name: name.into(),
+        version: version.into(),
+    }
+}
+
+#[test]
+fn load() {
+    let registry = Registry::new("testdata/charon".into());
+
+    let package = registry.load(&title("with-dependencies", "0.0.1")).unwrap();
+    assert_eq!(package.dependencies, vec![title("plex", "0.0.2")]);
+    assert!(package.prompts.is_empty());
+
+    let package = registry.load(&title("with-prompts", "0.0.1")).unwrap();
+    assert_eq!(package.prompts.len(), 3);
+    assert_eq!(package.prompts[0].template, "private_path");
+
+    assert!(registry.load(&title("plex", "9.9.9")).is_err());
+
+    // titles cannot reach outside the registry
+    let err = registry
+        .load(&title("..", "charon/packages/plex/0.0.1"))
+        .unwrap_err();
+    assert!(err.to_string().starts_with("invalid package"), "{}", err);
+    assert!(registry.load(&title("plex", "../plex/0.0.1")).is_err());
+}
+
+#[test]
+fn resolve_registry() {
+    let registry = Registry::new("testdata/charon".into());
+
+    assert_eq!(
+        registry
+            .resolve(&title("with-dependencies", "0.0.1"))
+            .unwrap(),
+        vec![
+            Dependency {
+                package: title("plex", "0.0.2"),
+                required_by: Some(title("with-dependencies", "0.0.1")),
+            },
+            Dependency {
+                package: title("with-dependencies", "0.0.1"),
+                required_by: None,
+            },
+        ]
+    );
+
+    assert_eq!(registry.resolve(&title("plex", "0.0.1")).unwrap().len(), 1);
+}
+
+#[test]
+fn resolve_tree() {
+    // a -> (b, c), b -> d, c -> d
+    let deps = |t: &PackageTitle| {
+        Ok(match t.name.as_str() {
+            "a" => vec![title("b", "1"), title("c", "1")],
+            "b" | "c" => vec![title("d", "1")],
+            _ => vec![],
+        })
+    };
+
+    let order: Vec<String> = resolve(&title("a", "1"), deps)
+        .unwrap()
+        .into_iter()
+        .map(|dep| dep.package.name)
+        .collect();
+    assert_eq!(order, vec!["d", "b", "c", "a"]);
+
+    let cycle = |t: &PackageTitle| {
+        Ok(match t.name.as_str() {
+            "a" => vec![title("b", "1")],
+            _ => vec![title("a", "1")],
+        })
+    };
+    assert!(resolve(&title("a", "1"), cycle).is_err());
+}
+
+#[test]
+fn find_conflicts() {
+    let order = vec![
+        Dependency {
+            package: title("plex", "0.0.2"),
+            required_by: Some(title("a", "1")),
+        },
+        Dependency {
+            package: title("plex", "0.0.1"),
+            required_by: Some(title("b", "1")),
+        },
+        Dependency {
+            package: title("a", "1"),
+            required_by: None,
+        },
+    ];
+
+    let conflicts = conflicts(&order, &[title("plex", "0.0.2"), title("other", "1")]);
+    assert_eq!(
+        conflicts,
+        vec![
+            Conflict {
+                package: title("plex", "0.0.1"),
+                conflicts_with: title("plex", "0.0.2"),
+                installed: true,
+            },
+            Conflict {
+                package: title("plex", "0.0.1"),
+                conflicts_with: title("plex", "0.0.2"),
+                installed: false,
+            },
+        ]
+    );
+
+    assert!(super::conflicts(&order[..1], &[title("plex", "0.0.2")]).is_empty());
+
+    let plan = InstallPlan {
+        conflicts,
+        ..Default::default()
+    };
+    assert_eq!(
+        plan.check().unwrap_err().to_string(),
+        "plex 0.0.1 conflicts with plex 0.0.2"
+    );
+}
+
+#[test]
+fn prompts_and_volumes() {
+    use charon::{Input, PromptResponse};
+
+    let registry = Registry::new("testdata/charon".into());
+    let package = registry.load(&title("with-prompts", "0.0.1")).unwrap();
+
+    let responses = PromptResponses(vec![PromptResponse {
+        template: "private_path".into(),
+        input: Input::String("/tmp".into()),
+    }]);
+    let missing: Vec<String> = unanswered(package.prompts.clone(), &responses)
+        .into_iter()
+        .map(|prompt| prompt.template)
+        .collect();
+    assert_eq!(missing, vec!["private_size", "private_recreate"]);
+
+    // a response of the wrong type does not answer the prompt
+    let wrong = PromptResponses(vec![PromptResponse {
+        template: "private_size".into(),
+        input: Input::String("big".into()),
+    }]);
+    assert_eq!(unanswered(package.prompts.clone(), &wrong).len(), 3);
+
+    // the same volume, recreated depending on the response
+    assert_eq!(package.storage.volumes.len(), 1);
+    assert!(recreated_volumes(&package, &package, &responses).is_empty());
+
+    let recreate = PromptResponses(vec![PromptResponse {
+        template: "private_recreate".into(),
+        input: Input::Boolean(true),
+    }]);
+    assert_eq!(
+        recreated_volumes(&package, &package, &recreate),
+        vec!["private"]
+    );
+
+    let mut new = package.clone();
+    new.storage.volumes[0].recreate = Some("true".into());
+    assert_eq!(
+        recreated_volumes(&package, &new, &responses),
+        vec!["private"]
+    );
+
+    // volumes that only exist in the new version have nothing to lose
+    let old = registry.load(&title("plex", "0.0.1")).unwrap();
+    assert!(recreated_volumes(&old, &new, &recreate).is_empty());
+}
+
+#[test]
+fn merged_logs() {
+    use buckle::systemd::LogMessage;
+    use chrono::TimeZone;
+
+    let entry = |unit: &str, second: u32| LogMessage {
+        time: Some(
+            chrono::Local
+                .with_ymd_and_hms(2024, 1, 1, 0, 0, second)
+                .unwrap(),
+        ),
+        cursor: format!("{}-{}", unit, second),
+        message: format!("{} {}", unit, second),
+    };
+    let logs = |backward: bool| {
+        let mut web = vec![entry("web", 1), entry("web", 4), entry("web", 5)];
+        let mut db = vec![entry("db", 2), entry("db", 3)];
+        if backward {
+            web.reverse();
+            db.reverse();
+        }
+        vec![("web".to_string(), web), ("db".to_string(), db)]
+    };
+
+    let log = merge_logs(logs(false), 4, false, Default::default());
+    assert_eq!(
+        log.entries
+            .iter()
+            .map(|entry| entry.message.as_str())
+            .collect::<Vec<_>>(),
+        vec!["web 1", "db 2", "db 3", "web 4"]
+    );
+    assert_eq!(log.cursors["web"], "web-4");
+    assert_eq!(log.cursors["db"], "db-3");
+
+    // units that contribute nothing keep the cursor they were read from
+    let cursors: HashMap<String, String> = [("db".to_string(), "db-0".to_string())].into();
+    let log = merge_logs(logs(true), 2, true, cursors);
+    assert_eq!(
+        log.entries
+            .iter()
+            .map(|entry| entry.message.as_str())
+            .collect::<Vec<_>>(),
+        vec!["web 5", "web 4"]
+    );
+    assert_eq!(log.cursors["web"], "web-4");
+    assert_eq!(log.cursors["db"], "db-0");
+}
+
+#[test]
+fn unit_states() {
+    use buckle::systemd::{LastRunState, LoadState, Unit, UnitStatus};
+
+    let unit = |load_state, last_run_state| Unit {
+        name: "plex-0.0.1.service".into(),
+        status: UnitStatus {
+            load_state,
+            last_run_state,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert_eq!(
+        unit_state(&unit(LoadState::Loaded, LastRunState::Running)),
+        "active"
+    );
+    assert_eq!(
+        unit_state(&unit(LoadState::Loaded, LastRunState::Failed)),
+        "failed"
+    );
+    assert_eq!(
+        unit_state(&unit(LoadState::Loaded, LastRunState::Dead)),
+        "inactive"
+    );
+    // a unit systemd has not loaded has not run, whatever its last state was
+    assert_eq!(
+        unit_state(&unit(LoadState::Unloaded, LastRunState::Failed)),
+        "inactive"
+    );
+}
+
+#[test]
+fn versions() {
+    use std::cmp::Ordering;
+
+    assert_eq!(compare_versions("0.0.1", "0.0.2"), Ordering::Less);
+    assert_eq!(compare_versions("0.0.10", "0.0.9"), Ordering::Greater);
+    assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Less);
+    assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    assert_eq!(
+        compare_versions("1.0.0-beta", "1.0.0-alpha"),
+        Ordering::Greater
+    );
+}
+
+#[test]
+fn package_changes() {
+    let registry = Registry::new("testdata/charon".into());
+    let mut old = registry.load(&title("plex", "0.0.1")).unwrap();
+    let mut new = registry.load(&title("plex", "0.0.2")).unwrap();
+
+    old.description = "A media server\nRuns in a container".into();
+    new.description = "A media server\nRuns in a VM".into();
+    assert_eq!(changes(&old, &new), "-Runs in a container\n+Runs in a VM");
+
+    new.changelog = Some("Moved to a VM".into());
+    assert_eq!(changes(&old, &new), "Moved to a VM");
+}
+
+#[test]
+fn search() {
+    let registry = Registry::new("testdata/charon".into());
+    let packages: Vec<Package> = [
+        ("plex", "0.0.2"),
+        ("plex-qemu", "0.0.1"),
+        ("plex", "0.0.1"),
+        ("podman-test", "0.0.3"),
+    ]
+    .into_iter()
+    .map(|(name, version)| registry.load(&title(name, version)).unwrap())
+    .collect();
+
+    let qemu = &packages[1];
+    assert_eq!(qemu.source.kind(), Some(SourceKind::Url));
+    assert_eq!(qemu.resources.cpus, Some("8".into()));
+    assert_eq!(
+        qemu.networking.forward_ports,
+        vec![("1234".to_string(), "5678".to_string())]
+    );
+    assert_eq!(packages[0].source.kind(), Some(SourceKind::Container));
+
+    let query = SearchQuery {
+        name: Some("plex".into()),
+        ..Default::default()
+    };
+    assert_eq!(packages.iter().filter(|p| query.matches(p)).count(), 3);
+
+    let query = SearchQuery {
+        name: Some("plex".into()),
+        source: Some(SourceKind::Container),
+        ..Default::default()
+    };
+    assert_eq!(packages.iter().filter(|p| query.matches(p)).count(), 2);
+
+    let query = SearchQuery {
+        text: Some("MODIFY this".into()),
+        ..Default::default()
+    };
+    assert_eq!(packages.iter().filter(|p| query.matches(p)).count(), 4);
+
+    let query = SearchQuery {
+        text: Some("nothing like this".into()),
+        ..Default::default()
+    };
+    assert!(!packages.iter().any(|p| query.matches(p)));
+
+    let groups = group(packages);
+    assert_eq!(
+        groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>(),
+        vec!["plex", "plex-qemu", "podman-test"]
+    );
+    assert_eq!(
+        groups[0]
+            .versions
+            .iter()
+            .map(|p| p.title.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["0.0.1", "0.0.2"]
+    );
+}
+
+#[test]
+fn validate() {
+    use charon::{Input, PromptResponse};
+
+    let registry = Registry::new("testdata/charon".into());
+    let package = registry.load(&title("with-prompts", "0.0.1")).unwrap();
+
+    let mut responses = PromptResponses(vec![
+        PromptResponse {
+            template: "private_path".into(),
+            input: Input::String("/tmp".into()),
+        },
+        PromptResponse {
+            template: "private_size".into(),
+            input: Input::Integer(10),
+        },
+        PromptResponse {
+            template: "private_recreate".into(),
+            input: Input::Boolean(false),
+        },
+    ]);
+    assert!(validate_responses(&package.prompts, &responses).is_empty());
+
+    responses.0[1].input = Input::String("10".into());
+    responses.0.remove(2);
+    responses.0.push(PromptResponse {
+        template: "private_color".into(),
+        input: Input::String("blue".into()),
+    });
+
+    let errors = validate_responses(&package.prompts, &responses);
+    assert_eq!(
+        errors,
+        vec![
+            ResponseError {
+                template: "private_size".into(),
+                problem: ResponseProblem::WrongType(InputType::Integer),
+            },
+            ResponseError {
+                template: "private_color".into(),
+                problem: ResponseProblem::Unknown,
+            },
+            ResponseError {
+                template: "private_recreate".into(),
+                problem: ResponseProblem::Missing,
+            },
+        ]
+    );
+    assert_eq!(errors[0].to_string(), "private_size: expected an integer");
+    assert_eq!(errors[1].to_string(), "private_color: no such prompt");
+    assert_eq!(errors[2].to_string(), "private_recreate: no response given");
+}
+
+#[test]
+fn privileges() {
+    let registry = Registry::new("testdata/charon".into());
+
+    let privileges = registry
+        .load(&title("podman-test", "0.0.1"))
+        .unwrap()
+        .privileges();
+    assert!(privileges.is_elevated());
+    assert_eq!(
+        privileges.describe(),
+        vec!["host_pid", "host_net", "privileged", "SYS_ADMIN"]
+    );
+
+    let privileges = registry
+        .load(&title("podman-test", "0.0.3"))
+        .unwrap()
+        .privileges();
+    assert!(!privileges.is_elevated());
+    assert_eq!(privileges, Privileges::default());
+}
+
+#[test]
+fn status() {
+    use charon::{Input, PromptResponse};
+
+    let registry = Registry::new("testdata/charon".into());
+
+    let package = registry.load(&title("podman-test", "0.0.1")).unwrap();
+    let responses = PromptResponses::default();
+    assert_eq!(
+        volume_dataset(&package.title, &package.storage.volumes[0]),
+        "podman-test/private"
+    );
+    assert_eq!(
+        volume_dataset(&package.title, &package.storage.volumes[1]),
+        "shared"
+    );
+    assert_eq!(
+        volume_size(&package.storage.volumes[0], &responses),
+        Some(1234567890)
+    );
+
+    let package = registry.load(&title("with-prompts", "0.0.1")).unwrap();
+    assert_eq!(volume_size(&package.storage.volumes[0], &responses), None);
+    let responses = PromptResponses(vec![PromptResponse {
+        template: "private_size".into(),
+        input: Input::Integer(4096),
+    }]);
+    assert_eq!(
+        volume_size(&package.storage.volumes[0], &responses),
+        Some(4096)
+    );
+
+    assert_eq!(
+        parse_cpu_stat("usage_usec 1500000\nuser_usec 1000000\nsystem_usec 500000\n"),
+        Some(1500000000)
+    );
+    assert_eq!(parse_cpu_stat("nr_periods 0\n"), None);
+}
+
+#[test]
+fn archive() {
+    use charon::{Input, PromptResponse};
+
+    let dir = tempfile::tempdir().unwrap();
+    let streams = dir.path().join("streams");
+    std::fs::create_dir(&streams).unwrap();
+
+    let volume = ArchivedVolume {
+        name: "private".into(),
+        dataset: "podman-test/private".into(),
+        size: 6,
+        sha256: "unchecked".into(),
+    };
+    std::fs::write(volume.stream(&streams).unwrap(), b"stream").unwrap();
+
+    let archive = PackageArchive {
+        package: title("podman-test", "0.0.1"),
+        responses: PromptResponses(vec![PromptResponse {
+            template: "private_size".into(),
+            input: Input::Integer(10),
+        }]),
+        volumes: vec![volume.clone()],
+    };
+    let file = dir.path().join("podman-test.tar");
+    archive.write(&file, &streams).unwrap();
+
+    // the manifest can be read without unpacking anything
+    assert_eq!(PackageArchive::read(&file, None).unwrap(), archive);
+
+    let unpacked = dir.path().join("unpacked");
+    std::fs::create_dir(&unpacked).unwrap();
+    assert_eq!(
+        PackageArchive::read(&file, Some(&unpacked)).unwrap(),
+        archive
+    );
+    assert_eq!(
+        std::fs::read(volume.stream(&unpacked).unwrap()).unwrap(),
+        b"stream"
+    );
+
+    // manifests name the streams, and cannot point outside the directory
+    for name in ["../private", "..", "a/b"] {
+        let volume = ArchivedVolume {
+            name: name.into(),
+            ..volume.clone()
+        };
+        assert!(volume.stream(&unpacked).is_err(), "{}", name);
+    }
+
+    // volume entries are unpacked only if they are plain files
+    let linked = dir.path().join("linked.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&linked).unwrap());
+    let manifest = serde_json::to_vec(&archive).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "manifest.json", manifest.as_slice())
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "volumes/link", "/etc/passwd")
+        .unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+    assert_eq!(PackageArchive::read(&linked, None).unwrap(), archive);
+    assert!(PackageArchive::read(&linked, Some(&unpacked)).is_err());
+    assert!(!unpacked.join("link").exists());
+
+    std::fs::write(&file, b"not an archive").unwrap();
+    assert!(PackageArchive::read(&file, None).is_err());
+}
+
+#[test]
+fn volume_datasets() {
+    let registry = Registry::new("testdata/charon".into());
+    let package = registry.load(&title("podman-test", "0.0.1")).unwrap();
+    let upgraded = registry.load(&title("podman-test", "0.0.2")).unwrap();
+    let mut other = registry.load(&title("plex", "0.0.1")).unwrap();
+    other.storage.volumes = package.storage.volumes.clone();
+
+    // private volumes live under the package's name, shared ones at the top of the pool
+    let private = &package.storage.volumes[0];
+    let shared = &package.storage.volumes[1];
+    assert_eq!(
+        volume_dataset(&package.title, private),
+        "podman-test/private"
+    );
+    assert_eq!(volume_dataset(&package.title, shared), "shared");
+
+    // the version is not part of the name, so upgrades keep their data
+    assert_eq!(
+        volume_dataset(&upgraded.title, private),
+        volume_dataset(&package.title, private)
+    );
+
+    // another package gets its own private volume, but the same shared one
+    assert_eq!(volume_dataset(&other.title, private), "plex/private");
+    assert_eq!(
+        volume_dataset(&other.title, shared),
+        volume_dataset(&package.title, shared)
+    );
+}
+
+#[test]
+fn retention() {
+    use std::collections::HashMap;
+
+    let registry = Registry::new("testdata/charon".into());
+    let package = registry.load(&title("podman-test", "0.0.1")).unwrap();
+
+    let mut other = registry.load(&title("plex", "0.0.1")).unwrap();
+    other.storage.volumes = vec![package.storage.volumes[1].clone()];
+
+    let volumes = volume_retention(&package, &[package.clone(), other]);
+    assert_eq!(volumes.len(), 2);
+    assert_eq!(volumes[0].dataset, "podman-test/private");
+    assert!(volumes[0].private);
+    assert!(volumes[0].shared_with.is_empty());
+    assert_eq!(volumes[1].dataset, "shared");
+    assert_eq!(volumes[1].shared_with, vec![title("plex", "0.0.1")]);
+
+    let mut choices = HashMap::new();
+    choices.insert("private".to_string(), Retention::Destroy);
+    choices.insert("shared".to_string(), Retention::Snapshot);
+    assert!(check_retention(&volumes, &choices).is_ok());
+
+    choices.insert("shared".to_string(), Retention::Destroy);
+    assert_eq!(
+        check_retention(&volumes, &choices).unwrap_err().to_string(),
+        "volume shared is still used by plex 0.0.1"
+    );
+
+    let mut choices = HashMap::new();
+    choices.insert("missing".to_string(), Retention::Keep);
+    assert!(check_retention(&volumes, &choices).is_err());
+}
+
+#[test]
+fn registries() {
+    let default = tempfile::tempdir().unwrap();
+    std::fs::create_dir(default.path().join("packages")).unwrap();
+
+    let mut registries = Registries::new(Registry::new(default.path().into()));
+    registries.add("charon", Registry::new("testdata/charon".into()));
+    registries.add("extra", Registry::new("testdata/extra".into()));
+
+    let listed = registries.list().unwrap();
+    assert!(listed.contains(&RegistryPackage {
+        title: title("plex", "0.0.1"),
+        registry: "charon".into(),
+    }));
+    // plex is shipped by an earlier registry, hello is not
+    assert!(listed.contains(&RegistryPackage {
+        title: title("extra/plex", "0.0.3"),
+        registry: "extra".into(),
+    }));
+    assert!(listed.contains(&RegistryPackage {
+        title: title("hello", "0.0.1"),
+        registry: "extra".into(),
+    }));
+
+    assert_eq!(
+        registries
+            .load(&title("extra/plex", "0.0.3"))
+            .unwrap()
+            .title,
+        title("plex", "0.0.3")
+    );
+    assert!(registries.load(&title("extra/plex", "0.0.1")).is_err());
+    assert!(registries.load(&title("missing/plex", "0.0.1")).is_err());
+
+    // nothing is copied until the package is staged for an install
+    let resolved = registries.resolve(&title("hello", "0.0.1")).unwrap();
+    assert_eq!(
+        resolved
+            .iter()
+            .map(|(name, _, dep)| (*name, dep.package.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("extra", title("plex", "0.0.3")),
+            ("extra", title("hello", "0.0.1"))
+        ]
+    );
+    let staged = Registry::new(default.path().into());
+    assert!(staged.list().unwrap().is_empty());
+
+    // the dependency comes along from the same registry
+    let (bare, copied) = registries.stage(&title("hello", "0.0.1")).unwrap();
+    assert_eq!(bare, title("hello", "0.0.1"));
+    assert_eq!(
+        copied,
+        vec![
+            RegistryPackage {
+                title: title("plex", "0.0.3"),
+                registry: "extra".into(),
+            },
+            RegistryPackage {
+                title: title("hello", "0.0.1"),
+                registry: "extra".into(),
+            },
+        ]
+    );
+    assert_eq!(
+        staged.list().unwrap(),
+        vec![title("hello", "0.0.1"), title("plex", "0.0.3")]
+    );
+
+    // staging again is harmless, and copies nothing
+    assert!(registries
+        .stage(&title("hello", "0.0.1"))
+        .unwrap()
+        .1
+        .is_empty());
+
+    // once recorded, the copies are listed under the registry they came from
+    for staged in copied {
+        registries.add_staged(staged);
+    }
+    let listed = registries.list().unwrap();
+    assert!(listed.iter().all(|p| p.registry != DEFAULT_REGISTRY));
+    assert!(listed.contains(&RegistryPackage {
+        title: title("hello", "0.0.1"),
+        registry: "extra".into(),
+    }));
+
+    registries.unstage(&title("hello", "0.0.1")).unwrap();
+    registries.unstage(&title("plex", "0.0.3")).unwrap();
+    assert!(staged.list().unwrap().is_empty());
+}
+
+#[test]
+fn definitions() {
+    let valid = std::fs::read("testdata/charon/packages/with-prompts/0.0.1.json").unwrap();
+    let package = validate_definition(&valid).unwrap();
+    assert_eq!(package.title, title("with-prompts", "0.0.1"));
+
+    let paths = |definition: &str| -> Vec<String> {
+        validate_definition(definition.as_bytes())
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.path)
+            .collect()
+    };
+
+    assert_eq!(paths("{"), vec!["$"]);
+    assert_eq!(paths(r#"{"title": {"name": "x"}}"#), vec!["$.title"]);
+    assert_eq!(
+        paths(r#"{"title": {"name": "x", "version": 1}}"#),
+        vec!["$.title.version"]
+    );
+    assert_eq!(
+        paths(
+            r#"{
+                "title": {"name": "../x", "version": "1"},
+                "storage": {"volumes": [{"name": "data", "size": "@size@"}]},
+                "prompts": [
+                    {"template": "path", "question": "?", "input_type": "string"},
+                    {"template": "path", "question": "?", "input_type": "string"}
+                ]
+            }"#
+        ),
+        vec![
+            "$.title.name",
+            "$.prompts[1].template",
+            "$.storage.volumes[0].size"
+        ]
+    );
+
+    let errors = validate_definition(
+        br#"{"title": {"name": "x", "version": "1"}, "source": {"url": "@where@"}}"#,
+    )
+    .unwrap_err();
+    assert_eq!(
+        errors[0].to_string(),
+        "$.source.url: no prompt for template @where@"
+    );
+
+    // templates inside longer strings count too, but an address is not one
+    assert_eq!(
+        paths(
+            r#"{
+                "title": {"name": "x", "version": "1"},
+                "description": "mail admin@example.com",
+                "source": {"url": "http://@host@:@port@/x.img"},
+                "prompts": [{"template": "host", "question": "?", "input_type": "string"}]
+            }"#
+        ),
+        vec!["$.source.url"]
+    );
+    assert_eq!(
+        templates("a@b.c @x@@y@ @not one@"),
+        vec![(6..9, "x"), (9..12, "y")]
+    );
+}
+
+#[test]
+fn dry_run() {
+    let registry = Registry::new("testdata/charon".into());
+    let package = registry.load(&title("with-prompts", "0.0.1")).unwrap();
+
+    let responses = PromptResponses(vec![
+        PromptResponse {
+            template: "private_path".into(),
+            input: Input::String("/srv/data".into()),
+        },
+        PromptResponse {
+            template: "private_size".into(),
+            input: Input::Integer(1024),
+        },
+    ]);
+
+    let run = super::dry_run(&package, &responses, |dataset| {
+        dataset == "with-prompts/private"
+    })
+    .unwrap();
+    let volume = &run.package.storage.volumes[0];
+    assert_eq!(volume.mountpoint.as_deref(), Some("/srv/data"));
+    assert_eq!(volume.size.as_deref(), Some("1024"));
+    assert_eq!(volume.recreate.as_deref(), Some("@private_recreate@"));
+    assert_eq!(run.unresolved, vec!["private_recreate"]);
+    // the prompts themselves are left alone
+    assert_eq!(run.package.prompts, package.prompts);
+
+    assert_eq!(
+        run.volumes,
+        vec![PlannedVolume {
+            name: "private".into(),
+            dataset: "with-prompts/private".into(),
+            size: Some(1024),
+            mountpoint: Some("/srv/data".into()),
+            exists: true,
+        }]
+    );
+
+    // templates inside longer strings are filled in where they stand
+    let mut embedded = package.clone();
+    embedded.description = "data in @private_path@ (@private_size@ bytes), @missing@".into();
+    let (rendered, unresolved) = render(&embedded, &responses).unwrap();
+    assert_eq!(
+        rendered.description,
+        "data in /srv/data (1024 bytes), @missing@"
+    );
+    assert!(unresolved.contains(&"missing".to_string()));
+}
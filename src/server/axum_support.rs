@@ -18,7 +18,33 @@ use tracing::error;
 pub(crate) type Result<T> = core::result::Result<T, AppError>;
 
 #[derive(Debug, Clone, Default)]
-pub(crate) struct AppError(pub ProblemDetails);
+pub(crate) struct AppError {
+    pub problem: ProblemDetails,
+    // problems with individual parts of the request, listed in the body as `errors`
+    pub errors: Vec<serde_json::Value>,
+}
+
+impl AppError {
+    pub(crate) fn new(problem: ProblemDetails) -> Self {
+        Self {
+            problem,
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_errors<T: serde::Serialize>(mut self, errors: &[T]) -> Self {
+        self.errors = errors
+            .iter()
+            .filter_map(|error| serde_json::to_value(error).ok())
+            .collect();
+        self
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ErrorList {
+    errors: Vec<serde_json::Value>,
+}
 
 impl<E> From<E> for AppError
 where
@@ -27,13 +53,13 @@ where
     fn from(value: E) -> Self {
         // hack around type specialization
         if TypeId::of::<E>() == TypeId::of::<ProblemDetails>() {
-            Self(
+            Self::new(
                 <(dyn Any + 'static)>::downcast_ref::<ProblemDetails>(&value)
                     .unwrap()
                     .clone(),
             )
         } else if TypeId::of::<E>() == TypeId::of::<tonic::Status>() {
-            Self(
+            Self::new(
                 ProblemDetails::new()
                     .with_detail(
                         <(dyn Any + 'static)>::downcast_ref::<tonic::Status>(&value)
@@ -43,7 +69,7 @@ where
                     .with_title("Uncategorized Error"),
             )
         } else {
-            Self(
+            Self::new(
                 ProblemDetails::new()
                     .with_detail(value.into().to_string())
                     .with_title("Uncategorized Error"),
@@ -54,7 +80,15 @@ where
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        self.0.into_response()
+        if self.errors.is_empty() {
+            self.problem.into_response()
+        } else {
+            self.problem
+                .with_extensions(ErrorList {
+                    errors: self.errors,
+                })
+                .into_response()
+        }
     }
 }
 
@@ -85,7 +119,7 @@ pub(crate) struct Account<T>(pub T);
 async fn read_jwt(parts: &mut Parts, state: &Arc<ServerState>) -> Result<Option<User>> {
     // FIXME: we want to hide the error from the end user to avoid giving them information about this
     // process. We should, however, log the errors for debugging purposes, which isn't done yet.
-    let err = AppError(
+    let err = AppError::new(
         ProblemDetails::new()
             .with_detail("Please enter correct credentials")
             .with_status(http::StatusCode::UNAUTHORIZED)
@@ -210,7 +244,7 @@ where
     fn into_response(self) -> Response {
         let mut log = self.1;
         if let Err(ref e) = self.0 {
-            log = log.with_error(&e.0.to_string()).clone();
+            log = log.with_error(&e.problem.to_string()).clone();
         }
 
         let db = self.2.db.clone();
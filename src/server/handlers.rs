@@ -1,12 +1,25 @@
-use super::{axum_support::*, messages::*, ServerState};
-use crate::db::models::{AuditLog, Session, User};
+use super::{axum_support::*, jobs, messages::*, tasks, ServerState};
+use crate::{
+    db::models::{
+        AuditLog, Backup, Job, JobKind, PackageRegistry, PackageUpdate, PoolSample, ScrubResult,
+        ScrubSchedule, Session, StagedPackage, User,
+    },
+    packages::{
+        self, check_retention, compare_versions, group, validate_definition, validate_responses,
+        DefinitionError, DryRun, InstallPlan, PackageLog, PackageStatus, PlanStep, Privileges,
+        RegistryPackage, ResponseError, ResponseProblem, SearchQuery, SearchResults, UnitAction,
+        UnitStatus, VolumeRetention, VolumeUsage,
+    },
+    system::{self, ImportReport, SystemExport},
+    zfs::{check_overcommit, EncryptionKey, PoolStatus, Property, Quota},
+};
 use anyhow::anyhow;
 use axum::extract::{Path, State};
 use axum_serde::Cbor;
-use buckle::client::ZFSStat;
 use charon::PackageTitle;
 use hmac::{Hmac, Mac};
 use jwt::SignWithKey;
+use problem_details::ProblemDetails;
 use std::{collections::HashMap, ops::Deref, sync::Arc};
 use tokio_stream::StreamExt;
 use validator::Validate;
@@ -69,10 +82,12 @@ pub(crate) async fn log(
         selector = selector.where_col(|c| c.time.gt(since));
     }
 
+    // pages are counted in pages, as everywhere else, not in entries
+    let per_page = pagination.per_page.unwrap_or(20);
     if let Some(page) = pagination.page {
         selector = selector
-            .offset(page.into())
-            .limit(pagination.per_page.unwrap_or(20).into());
+            .offset((page as i64) * (per_page as i64))
+            .limit(per_page.into());
     } else if let Some(per_page) = pagination.per_page {
         selector = selector.limit(per_page.into())
     }
@@ -90,14 +105,24 @@ pub(crate) async fn zfs_list(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
     Cbor(filter): Cbor<String>,
-) -> Result<CborOut<Vec<ZFSStat>>> {
+) -> Result<CborOut<Vec<ZFSListEntry>>> {
     let filter = if filter.is_empty() {
         None
     } else {
         Some(filter)
     };
 
-    Ok(CborOut(state.buckle.zfs().await?.list(filter).await?))
+    let list = state.buckle.zfs().await?.list(filter).await?;
+    let key_status = state.zpool.key_status().await?;
+
+    Ok(CborOut(
+        list.into_iter()
+            .map(|stat| ZFSListEntry {
+                key_status: key_status.get(&stat.full_name).copied().unwrap_or_default(),
+                stat,
+            })
+            .collect(),
+    ))
 }
 
 pub(crate) async fn zfs_create_dataset(
@@ -118,13 +143,31 @@ pub(crate) async fn zfs_modify_dataset(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
     Log(mut log): Log,
-    Cbor(dataset): Cbor<buckle::client::ModifyDataset>,
+    Cbor(request): Cbor<ModifyDatasetRequest>,
 ) -> Result<WithLog<()>> {
+    request.properties.validate(false)?;
+
     let log = log
         .with_entry("Modifying dataset")
-        .with_data(&dataset)?
+        .with_data(&request)?
         .clone();
-    state.buckle.zfs().await?.modify_dataset(dataset).await?;
+
+    // the modifications may rename the dataset
+    let name = request.dataset.modifications.name.clone();
+    state
+        .buckle
+        .zfs()
+        .await?
+        .modify_dataset(request.dataset)
+        .await?;
+
+    if !request.properties.is_empty() {
+        state
+            .zpool
+            .set_properties(&name, &request.properties)
+            .await?;
+    }
+
     Ok(state.with_log(Ok(()), log))
 }
 
@@ -146,16 +189,120 @@ pub(crate) async fn zfs_modify_volume(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
     Log(mut log): Log,
-    Cbor(volume): Cbor<buckle::client::ModifyVolume>,
+    Cbor(request): Cbor<ModifyVolumeRequest>,
 ) -> Result<WithLog<()>> {
+    request.properties.validate(true)?;
+
     let log = log
         .with_entry("Modifying volume")
-        .with_data(&volume)?
+        .with_data(&request)?
         .clone();
-    state.buckle.zfs().await?.modify_volume(volume).await?;
+
+    // the modifications may rename the volume
+    let name = request.volume.modifications.name.clone();
+    state
+        .buckle
+        .zfs()
+        .await?
+        .modify_volume(request.volume)
+        .await?;
+
+    if !request.properties.is_empty() {
+        state
+            .zpool
+            .set_properties(&name, &request.properties)
+            .await?;
+    }
+
     Ok(state.with_log(Ok(()), log))
 }
 
+pub(crate) async fn zfs_create_encrypted_dataset(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<EncryptedDatasetRequest>,
+) -> Result<WithLog<()>> {
+    request.key.validate()?;
+
+    let log = log
+        .with_entry("Creating encrypted dataset")
+        .with_data(key_log(&request.dataset.name, &request.key))?
+        .clone();
+
+    state
+        .zpool
+        .create_encrypted(&request.dataset.name, request.dataset.quota, &request.key)
+        .await?;
+    Ok(state.with_log(Ok(()), log))
+}
+
+pub(crate) async fn zfs_unlock(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<UnlockRequest>,
+) -> Result<WithLog<()>> {
+    let mut map: HashMap<&str, &str> = HashMap::default();
+    map.insert("name", &request.name);
+
+    let log = log.with_entry("Unlocking dataset").with_data(&map)?.clone();
+
+    // failed attempts are recorded too
+    let res = state
+        .zpool
+        .unlock(&request.name, request.passphrase.as_deref())
+        .await;
+    Ok(state.with_log(res.map_err(Into::into), log))
+}
+
+pub(crate) async fn zfs_lock(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(name): Cbor<String>,
+) -> Result<WithLog<()>> {
+    let mut map: HashMap<&str, &str> = HashMap::default();
+    map.insert("name", &name);
+
+    let log = log.with_entry("Locking dataset").with_data(&map)?.clone();
+    let res = state.zpool.lock(&name).await;
+    Ok(state.with_log(res.map_err(Into::into), log))
+}
+
+pub(crate) async fn zfs_change_key(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<ChangeKeyRequest>,
+) -> Result<WithLog<()>> {
+    request.key.validate()?;
+
+    let log = log
+        .with_entry("Changing encryption key")
+        .with_data(key_log(&request.name, &request.key))?
+        .clone();
+
+    let res = state.zpool.change_key(&request.name, &request.key).await;
+    Ok(state.with_log(res.map_err(Into::into), log))
+}
+
+// audit log data for key operations; never includes the key itself
+fn key_log<'a>(name: &'a str, key: &EncryptionKey) -> HashMap<&'static str, &'a str> {
+    let mut map = HashMap::default();
+    map.insert("name", name);
+    map.insert("keyformat", key.format());
+    map
+}
+
+pub(crate) async fn zfs_properties(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(name): Cbor<String>,
+) -> Result<CborOut<Vec<Property>>> {
+    Ok(CborOut(state.zpool.properties(&name).await?))
+}
+
 pub(crate) async fn zfs_destroy(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
@@ -170,8 +317,594 @@ pub(crate) async fn zfs_destroy(
         .with_data(&map)?
         .clone();
 
-    state.buckle.zfs().await?.destroy(name).await?;
-    Ok(state.with_log(Ok(()), log))
+    state.buckle.zfs().await?.destroy(name).await?;
+    Ok(state.with_log(Ok(()), log))
+}
+
+pub(crate) async fn zfs_pool_status(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<PoolStatus>> {
+    Ok(CborOut(state.zpool.status().await?))
+}
+
+pub(crate) async fn zfs_pool_capacity(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<PoolCapacityReport>> {
+    let capacity = state.zpool.capacity().await?;
+    let alerts = state
+        .config
+        .zfs
+        .capacity_alerts
+        .iter()
+        .filter(|threshold| capacity.capacity >= **threshold)
+        .copied()
+        .collect();
+
+    Ok(CborOut(PoolCapacityReport { capacity, alerts }))
+}
+
+pub(crate) async fn zfs_pool_history(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pagination): Cbor<Pagination>,
+) -> Result<CborOut<Vec<PoolSample>>> {
+    let pool = state.zpool.name().to_string();
+    let mut selector = PoolSample::all()
+        .where_col(|c| c.pool.equal(&pool))
+        .order_by_asc(|c| c.time);
+
+    if let Some(since) = pagination.since {
+        selector = selector.where_col(|c| c.time.gt(since));
+    }
+
+    let per_page = pagination.per_page.unwrap_or(100);
+    if let Some(page) = pagination.page {
+        selector = selector
+            .offset((page as i64) * (per_page as i64))
+            .limit(per_page.into());
+    } else if let Some(per_page) = pagination.per_page {
+        selector = selector.limit(per_page.into())
+    }
+
+    Ok(CborOut(
+        selector.run(state.db.handle()).await?.into_inners(),
+    ))
+}
+
+pub(crate) async fn zfs_quotas(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(filter): Cbor<String>,
+) -> Result<CborOut<Vec<Quota>>> {
+    let filter = if filter.is_empty() {
+        None
+    } else {
+        Some(filter.as_str())
+    };
+
+    Ok(CborOut(state.zpool.quotas(filter).await?))
+}
+
+pub(crate) async fn zfs_set_quota(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<QuotaRequest>,
+) -> Result<WithLog<CborOut<Quota>>> {
+    let log = log
+        .with_entry("Setting quota and reservation")
+        .with_data(&request)?
+        .clone();
+
+    let quota = request.quota()?;
+    let reservation = request.reservation()?;
+
+    let current = state
+        .zpool
+        .quotas(Some(&request.name))
+        .await?
+        .into_iter()
+        .find(|q| q.name == request.name)
+        .ok_or(anyhow!("{} does not exist", request.name))?;
+
+    if quota.is_some() && current.volume {
+        return Err(anyhow!("{} is a volume; volumes cannot have quotas", request.name).into());
+    }
+
+    check_overcommit(&current, state.zpool.available().await?, quota, reservation)?;
+
+    if let Some(quota) = quota {
+        state
+            .buckle
+            .zfs()
+            .await?
+            .modify_dataset(buckle::client::ModifyDataset {
+                name: request.name.clone(),
+                modifications: buckle::client::Dataset {
+                    name: request.name.clone(),
+                    quota: Some(quota),
+                },
+            })
+            .await?;
+    }
+
+    if let Some(reservation) = reservation {
+        state
+            .zpool
+            .set_reservation(&request.name, reservation)
+            .await?;
+    }
+
+    let updated = state
+        .zpool
+        .quotas(Some(&request.name))
+        .await?
+        .into_iter()
+        .find(|q| q.name == request.name)
+        .ok_or(anyhow!("{} does not exist", request.name))?;
+
+    Ok(state.with_log(Ok(CborOut(updated)), log))
+}
+
+pub(crate) async fn zfs_scrub_start(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+) -> Result<WithLog<()>> {
+    let log = log.with_entry("Starting scrub").clone();
+    Ok(state.with_log(Ok(tasks::start_scrub(&state, false).await?), log))
+}
+
+pub(crate) async fn zfs_scrub_pause(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+) -> Result<WithLog<()>> {
+    let log = log.with_entry("Pausing scrub").clone();
+    state.zpool.pause_scrub().await?;
+    tasks::reconcile_scrubs(&state).await?;
+    Ok(state.with_log(Ok(()), log))
+}
+
+pub(crate) async fn zfs_scrub_cancel(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+) -> Result<WithLog<()>> {
+    let log = log.with_entry("Canceling scrub").clone();
+    state.zpool.cancel_scrub().await?;
+    tasks::reconcile_scrubs(&state).await?;
+    Ok(state.with_log(Ok(()), log))
+}
+
+pub(crate) async fn zfs_scrub_history(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pagination): Cbor<Pagination>,
+) -> Result<CborOut<Vec<ScrubResult>>> {
+    tasks::reconcile_scrubs(&state).await?;
+
+    let pool = state.zpool.name().to_string();
+    let mut selector = ScrubResult::all()
+        .where_col(|c| c.pool.equal(&pool))
+        .order_by_desc(|c| c.started);
+
+    if let Some(since) = pagination.since {
+        selector = selector.where_col(|c| c.started.gt(since));
+    }
+
+    let per_page = pagination.per_page.unwrap_or(20);
+    if let Some(page) = pagination.page {
+        selector = selector
+            .offset((page as i64) * (per_page as i64))
+            .limit(per_page.into());
+    } else if let Some(per_page) = pagination.per_page {
+        selector = selector.limit(per_page.into())
+    }
+
+    Ok(CborOut(
+        selector.run(state.db.handle()).await?.into_inners(),
+    ))
+}
+
+pub(crate) async fn zfs_get_scrub_schedule(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Option<ScrubSchedule>>> {
+    Ok(CborOut(
+        ScrubSchedule::for_pool(&state.db, state.zpool.name())
+            .await?
+            .map(|schedule| schedule.into_inner()),
+    ))
+}
+
+pub(crate) async fn zfs_set_scrub_schedule(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<ScrubScheduleRequest>,
+) -> Result<WithLog<CborOut<ScrubSchedule>>> {
+    let mut schedule = match ScrubSchedule::for_pool(&state.db, state.zpool.name()).await? {
+        Some(schedule) => schedule,
+        None => DbState::new_uncreated(ScrubSchedule {
+            pool: state.zpool.name().to_string(),
+            ..Default::default()
+        }),
+    };
+
+    let interval: i64 = request.interval.into();
+    schedule.interval = interval;
+    schedule.next_run = request
+        .next_run
+        .unwrap_or_else(|| chrono::Local::now() + chrono::Duration::days(interval));
+    schedule.validate()?;
+
+    let log = log
+        .with_entry("Setting scrub schedule")
+        .with_data(schedule.deref())?
+        .clone();
+
+    schedule.save(state.db.handle()).await?;
+    Ok(state.with_log(Ok(CborOut(schedule.into_inner())), log))
+}
+
+pub(crate) async fn zfs_remove_scrub_schedule(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+) -> Result<WithLog<()>> {
+    if let Some(mut schedule) = ScrubSchedule::for_pool(&state.db, state.zpool.name()).await? {
+        let log = log
+            .with_entry("Removing scrub schedule")
+            .with_data(schedule.deref())?
+            .clone();
+        schedule.delete(state.db.handle()).await?;
+        Ok(state.with_log(Ok(()), log))
+    } else {
+        Err(anyhow!("no scrub schedule is set").into())
+    }
+}
+
+pub(crate) async fn zfs_backup(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<BackupRequest>,
+) -> Result<WithLog<CborOut<Backup>>> {
+    if !state.datasets().await?.contains(&request.dataset) {
+        return Err(AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!("no dataset named {} in the pool", request.dataset))
+                .with_status(http::StatusCode::NOT_FOUND)
+                .with_title("Dataset Not Found"),
+        ));
+    }
+
+    let parent = if request.incremental {
+        Some(
+            Backup::latest(&state.db, &request.dataset)
+                .await?
+                .ok_or(anyhow!(
+                    "no complete backup of {} to base an incremental backup on",
+                    request.dataset
+                ))?,
+        )
+    } else {
+        None
+    };
+
+    tokio::fs::create_dir_all(&state.config.zfs.backups).await?;
+
+    let mut backup = Backup::new_started(&request.dataset, parent.as_ref());
+    state
+        .zpool
+        .snapshot(&backup.dataset, &backup.snapshot)
+        .await?;
+    backup.save(state.db.handle()).await?;
+
+    let log = log
+        .with_entry("Starting backup")
+        .with_data(backup.deref())?
+        .clone();
+
+    let out = backup.deref().clone();
+    tokio::spawn(tasks::send_backup(
+        state.clone(),
+        backup,
+        parent.map(|parent| parent.snapshot),
+    ));
+
+    Ok(state.with_log(Ok(CborOut(out)), log))
+}
+
+pub(crate) async fn zfs_backups(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(request): Cbor<BackupListRequest>,
+) -> Result<CborOut<Vec<Backup>>> {
+    let mut selector = Backup::all().order_by_desc(|c| c.id);
+
+    if let Some(dataset) = &request.dataset {
+        selector = selector.where_col(|c| c.dataset.equal(dataset));
+    }
+
+    let per_page = request.per_page.unwrap_or(20);
+    if let Some(page) = request.page {
+        selector = selector
+            .offset((page as i64) * (per_page as i64))
+            .limit(per_page.into());
+    } else if let Some(per_page) = request.per_page {
+        selector = selector.limit(per_page.into())
+    }
+
+    Ok(CborOut(
+        selector.run(state.db.handle()).await?.into_inners(),
+    ))
+}
+
+pub(crate) async fn zfs_get_backup(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Path(id): Path<u32>,
+) -> Result<CborOut<Backup>> {
+    Ok(CborOut(
+        Backup::find_by_id(state.db.handle(), id)
+            .await?
+            .ok_or(anyhow!("invalid backup"))?
+            .into_inner(),
+    ))
+}
+
+pub(crate) async fn zfs_backup_verify(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(id): Cbor<u32>,
+) -> Result<CborOut<Vec<BackupVerification>>> {
+    let mut results = Vec::new();
+
+    for backup in Backup::chain(&state.db, id).await? {
+        let error = tasks::verify_backup(&state, &backup).await.err();
+        results.push(BackupVerification {
+            id: backup.id,
+            valid: error.is_none(),
+            error: error.map(|e| e.to_string()),
+        });
+    }
+
+    Ok(CborOut(results))
+}
+
+pub(crate) async fn zfs_restore(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<RestoreRequest>,
+) -> Result<WithLog<()>> {
+    let chain = Backup::chain(&state.db, request.id).await?;
+
+    for backup in &chain {
+        tasks::verify_backup(&state, backup).await?;
+    }
+
+    if state.datasets().await?.contains(&request.target) {
+        return Err(AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!(
+                    "{} already exists; restore into a new dataset",
+                    request.target
+                ))
+                .with_status(http::StatusCode::CONFLICT)
+                .with_title("Restore Target Exists"),
+        ));
+    }
+
+    let log = log
+        .with_entry("Restoring backup")
+        .with_data(&request)?
+        .clone();
+
+    let res: anyhow::Result<()> = async {
+        for backup in &chain {
+            state
+                .zpool
+                .receive(
+                    &request.target,
+                    &state.config.zfs.backups.join(&backup.file),
+                )
+                .await?;
+        }
+        state.zpool.mount(&request.target).await
+    }
+    .await;
+
+    Ok(state.with_log(res.map_err(Into::into), log))
+}
+
+//
+// Registries
+//
+
+pub(crate) async fn list_registries(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Vec<RegistryInfo>>> {
+    let mut registries = vec![
+        RegistryInfo {
+            name: packages::DEFAULT_REGISTRY.into(),
+            path: state.registry.path().to_path_buf(),
+            default: true,
+        },
+        RegistryInfo {
+            name: packages::SIDELOAD_REGISTRY.into(),
+            path: state.config.packages.sideload.clone(),
+            default: false,
+        },
+    ];
+
+    for registry in PackageRegistry::list(&state.db).await? {
+        registries.push(RegistryInfo {
+            name: registry.name,
+            path: registry.path.into(),
+            default: false,
+        });
+    }
+
+    Ok(CborOut(registries))
+}
+
+pub(crate) async fn add_registry(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<RegistryRequest>,
+) -> Result<WithLog<CborOut<PackageRegistry>>> {
+    if request.name.contains('/') {
+        return Err(anyhow!("registry names cannot contain /").into());
+    }
+
+    if state.registries().await?.get(&request.name).is_some() {
+        return Err(anyhow!("registry {} already exists", request.name).into());
+    }
+
+    if !request.path.join("packages").is_dir() {
+        return Err(anyhow!(
+            "{} does not contain a packages directory",
+            request.path.display()
+        )
+        .into());
+    }
+
+    let mut registry = DbState::new_uncreated(PackageRegistry {
+        name: request.name,
+        path: request.path.to_string_lossy().to_string(),
+        created: chrono::Local::now(),
+        ..Default::default()
+    });
+    registry.validate()?;
+
+    let log = log
+        .with_entry("Adding registry")
+        .with_data(registry.deref())?
+        .clone();
+
+    registry.save(state.db.handle()).await?;
+    Ok(state.with_log(Ok(CborOut(registry.into_inner())), log))
+}
+
+pub(crate) async fn remove_registry(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Path(name): Path<String>,
+) -> Result<WithLog<()>> {
+    if let Some(mut registry) = PackageRegistry::for_name(&state.db, &name).await? {
+        let log = log
+            .with_entry("Removing registry")
+            .with_data(registry.deref())?
+            .clone();
+
+        // copies staged from it go too, unless charon still needs them for an installed package
+        let registries = state.registries().await?;
+        let installed = state.charon.query().await?.list_installed().await?;
+        let res: anyhow::Result<()> = async {
+            for mut staged in StagedPackage::for_registry(&state.db, &name).await? {
+                let title = staged.package().title;
+                if !installed.contains(&title) {
+                    registries.unstage(&title)?;
+                }
+                staged.delete(state.db.handle()).await?;
+            }
+            registry.delete(state.db.handle()).await?;
+            Ok(())
+        }
+        .await;
+        Ok(state.with_log(res.map_err(Into::into), log))
+    } else if name == packages::DEFAULT_REGISTRY || name == packages::SIDELOAD_REGISTRY {
+        Err(anyhow!("registry {} cannot be removed", name).into())
+    } else {
+        Err(anyhow!("no registry named {}", name).into())
+    }
+}
+
+pub(crate) async fn list_registry_packages(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Vec<RegistryPackage>>> {
+    Ok(CborOut(state.registries().await?.list()?))
+}
+
+pub(crate) async fn refresh_registries(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Vec<RegistryRefresh>>> {
+    let registries = state.registries().await?;
+
+    let mut refreshed = Vec::new();
+    for name in registries.names() {
+        let registry = registries.get(name).unwrap();
+        refreshed.push(RegistryRefresh {
+            name: name.to_string(),
+            packages: registry.list()?.len(),
+            errors: registry.refresh()?,
+        });
+    }
+
+    Ok(CborOut(refreshed))
+}
+
+//
+// Jobs
+//
+
+pub(crate) async fn get_job(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Path(id): Path<u32>,
+) -> Result<CborOut<Job>> {
+    Ok(CborOut(
+        Job::find_by_id(state.db.handle(), id)
+            .await?
+            .ok_or(anyhow!("invalid job"))?
+            .into_inner(),
+    ))
+}
+
+pub(crate) async fn list_jobs(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pagination): Cbor<Pagination>,
+) -> Result<CborOut<Vec<Job>>> {
+    let mut selector = Job::all().order_by_desc(|c| c.id);
+
+    if let Some(since) = pagination.since {
+        selector = selector.where_col(|c| c.created.gt(since));
+    }
+
+    let per_page = pagination.per_page.unwrap_or(20);
+    if let Some(page) = pagination.page {
+        selector = selector
+            .offset((page as i64) * (per_page as i64))
+            .limit(per_page.into());
+    } else if let Some(per_page) = pagination.per_page {
+        selector = selector.limit(per_page.into())
+    }
+
+    Ok(CborOut(
+        selector.run(state.db.handle()).await?.into_inners(),
+    ))
+}
+
+pub(crate) async fn cancel_job(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Path(id): Path<u32>,
+) -> Result<WithLog<CborOut<Job>>> {
+    let job = jobs::cancel(&state, id).await?;
+    let log = log.with_entry("Canceling job").with_data(&job)?.clone();
+    Ok(state.with_log(Ok(CborOut(job)), log))
 }
 
 //
@@ -389,14 +1122,19 @@ pub(crate) async fn unit_log(
     Account(_): Account<User>,
     Cbor(params): Cbor<LogParameters>,
 ) -> Result<CborOut<Vec<buckle::systemd::LogMessage>>> {
+    Ok(CborOut(read_log(&state, params).await?))
+}
+
+async fn read_log(
+    state: &ServerState,
+    params: LogParameters,
+) -> anyhow::Result<Vec<buckle::systemd::LogMessage>> {
     let mut log = state
         .buckle
         .systemd()
-        .await
-        .unwrap()
+        .await?
         .unit_log(&params.name, params.count, params.cursor, params.direction)
-        .await
-        .unwrap();
+        .await?;
 
     // NOTE: this value can get very large and potentially cause a lot of memory usage if the count
     // is too high.
@@ -406,7 +1144,7 @@ pub(crate) async fn unit_log(
         v.push(entry.into())
     }
 
-    Ok(CborOut(v))
+    Ok(v)
 }
 
 //
@@ -433,6 +1171,22 @@ pub(crate) async fn set_responses(
     Account(_): Account<User>,
     Cbor(responses): Cbor<PromptResponsesWithName>,
 ) -> Result<CborOut<()>> {
+    let version = match responses.version {
+        Some(version) => version,
+        None => prompt_version(&state, &responses.name).await?,
+    };
+    let prompts = state
+        .charon
+        .query()
+        .await?
+        .get_prompts(&responses.name, &version)
+        .await?;
+
+    let errors = validate_responses(&prompts.0, &responses.responses);
+    if !errors.is_empty() {
+        return Err(invalid_responses(&errors));
+    }
+
     state
         .charon
         .query()
@@ -442,6 +1196,102 @@ pub(crate) async fn set_responses(
     Ok(CborOut(()))
 }
 
+/// The version of a package responses are checked against when none is given.
+async fn prompt_version(state: &ServerState, name: &str) -> anyhow::Result<String> {
+    let mut query = state.charon.query().await?;
+
+    if let Some(installed) = query
+        .list_installed()
+        .await?
+        .into_iter()
+        .find(|title| title.name == name)
+    {
+        return Ok(installed.version);
+    }
+
+    query
+        .list()
+        .await?
+        .into_iter()
+        .filter(|title| title.name == name)
+        .max_by(|a, b| compare_versions(&a.version, &b.version))
+        .map(|title| title.version)
+        .ok_or(anyhow!("package {} does not exist", name))
+}
+
+pub(crate) async fn validate_package(
+    Account(_): Account<User>,
+    Cbor(request): Cbor<SideloadRequest>,
+) -> Result<CborOut<Vec<DefinitionError>>> {
+    Ok(CborOut(
+        validate_definition(request.definition.as_bytes())
+            .err()
+            .unwrap_or_default(),
+    ))
+}
+
+pub(crate) async fn sideload_package(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<SideloadRequest>,
+) -> Result<WithLog<CborOut<RegistryPackage>>> {
+    let package = validate_definition(request.definition.as_bytes()).map_err(|errors| {
+        AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!(
+                    "{} problem(s) with the fields gild reads from the package definition",
+                    errors.len()
+                ))
+                .with_status(http::StatusCode::UNPROCESSABLE_ENTITY)
+                .with_title("Invalid Package Definition"),
+        )
+        .with_errors(&errors)
+    })?;
+
+    let log = log
+        .with_entry("Sideloading package")
+        .with_data(&package.title)?
+        .clone();
+
+    let res: Result<RegistryPackage> = async {
+        state
+            .config
+            .sideload()
+            .write(&package.title, request.definition.as_bytes())?;
+
+        // the name is qualified if another registry ships the same package
+        let qualified = format!("{}/{}", packages::SIDELOAD_REGISTRY, package.title.name);
+        Ok(state
+            .registries()
+            .await?
+            .list()?
+            .into_iter()
+            .find(|listed| {
+                listed.registry == packages::SIDELOAD_REGISTRY
+                    && listed.title.version == package.title.version
+                    && (listed.title.name == package.title.name || listed.title.name == qualified)
+            })
+            .ok_or(anyhow!("sideloaded package could not be listed"))?)
+    }
+    .await;
+
+    Ok(state.with_log(res.map(CborOut), log))
+}
+
+fn invalid_responses(errors: &[ResponseError]) -> AppError {
+    AppError::new(
+        ProblemDetails::new()
+            .with_detail(format!(
+                "{} response(s) do not fit the package's prompts",
+                errors.len()
+            ))
+            .with_status(http::StatusCode::UNPROCESSABLE_ENTITY)
+            .with_title("Invalid Responses"),
+    )
+    .with_errors(errors)
+}
+
 pub(crate) async fn get_responses(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
@@ -487,30 +1337,674 @@ pub(crate) async fn installed(
     ))
 }
 
+pub(crate) async fn upgrade_package(
+    State(state): State<Arc<ServerState>>,
+    Account(user): Account<User>,
+    Cbor(request): Cbor<UpgradeRequest>,
+) -> Result<CborOut<Job>> {
+    let pkg = charon::PackageTitle {
+        name: request.name,
+        version: request.version,
+    };
+    let registries = state.registries().await?;
+    let (registry_name, registry, bare) = registries.find(&pkg)?;
+
+    let mut merged = None;
+    if let Some(new) = request.responses {
+        // charon does not know the prompts of a package from another registry until it is staged
+        let prompts = match registry_name {
+            packages::DEFAULT_REGISTRY => {
+                state
+                    .charon
+                    .query()
+                    .await?
+                    .get_prompts(&bare.name, &bare.version)
+                    .await?
+                    .0
+            }
+            _ => registry.load(&bare)?.prompts,
+        };
+
+        // earlier responses fill in whatever is missing, and the plan checks for the rest
+        let errors: Vec<_> = validate_responses(&prompts, &new)
+            .into_iter()
+            .filter(|error| error.problem != ResponseProblem::Missing)
+            .collect();
+        if !errors.is_empty() {
+            return Err(invalid_responses(&errors));
+        }
+
+        // saved by the job, so that a failed upgrade leaves the old responses in place
+        let mut responses = jobs::responses(&state, &bare.name).await?;
+        responses
+            .0
+            .retain(|response| !new.0.iter().any(|new| new.template == response.template));
+        responses.0.extend(new.0);
+        merged = Some(responses);
+    }
+
+    // refuse up front what the job would fail on, as installs do
+    let mut plan = jobs::plan_with(&state, &pkg, merged.as_ref()).await?;
+    if let Some(current) = state
+        .charon
+        .query()
+        .await?
+        .list_installed()
+        .await?
+        .into_iter()
+        .find(|title| title.name == bare.name)
+    {
+        // the installed version is about to be replaced
+        plan.conflicts
+            .retain(|conflict| conflict.conflicts_with != current);
+    }
+    plan.check()?;
+
+    let privileged = plan.privileged();
+    if !privileged.is_empty() {
+        approve_privileges(&state, &user, &privileged, request.approve_privileges).await?;
+    }
+
+    // charon can only install what is in its own registry
+    let (package, copied) = registries.stage(&pkg)?;
+    for staged in &copied {
+        StagedPackage::record(&state.db, staged).await?;
+    }
+
+    Ok(CborOut(
+        jobs::spawn_with(
+            &state,
+            JobKind::Upgrade,
+            &package,
+            user.id,
+            jobs::JobOptions {
+                responses: merged,
+                ..Default::default()
+            },
+        )
+        .await?,
+    ))
+}
+
+pub(crate) async fn package_updates(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Vec<PackageUpdate>>> {
+    Ok(CborOut(PackageUpdate::list(&state.db).await?))
+}
+
+pub(crate) async fn check_updates(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Vec<PackageUpdate>>> {
+    Ok(CborOut(tasks::check_updates(&state).await?))
+}
+
+pub(crate) async fn package_detail(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<CborOut<serde_json::Value>> {
+    // gild's Package only covers the fields it reads, so the definition is returned as it is
+    Ok(CborOut(state.registries().await?.definition(&pkg)?))
+}
+
+pub(crate) async fn search_packages(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(query): Cbor<SearchQuery>,
+) -> Result<CborOut<SearchResults>> {
+    let registries = state.registries().await?;
+    let mut packages = Vec::new();
+    let mut errors = Vec::new();
+    for listed in registries.list()? {
+        let mut package = match registries.load(&listed.title) {
+            Ok(package) => package,
+            Err(e) => {
+                errors.push(format!(
+                    "{} {}: {}",
+                    listed.title.name, listed.title.version, e
+                ));
+                continue;
+            }
+        };
+        // keep qualified names apart when grouping
+        package.title = listed.title;
+        if query.matches(&package) {
+            packages.push(package);
+        }
+    }
+
+    Ok(CborOut(SearchResults {
+        packages: group(packages),
+        errors,
+    }))
+}
+
+pub(crate) async fn plan_install(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<CborOut<InstallPlan>> {
+    Ok(CborOut(jobs::plan(&state, &pkg).await?))
+}
+
 pub(crate) async fn install_package(
+    State(state): State<Arc<ServerState>>,
+    Account(user): Account<User>,
+    Cbor(mut request): Cbor<InstallRequest>,
+) -> Result<CborOut<Job>> {
+    // refuse up front what the job would fail on, such as unanswered prompts
+    let plan = jobs::plan(&state, &request.package).await?;
+    plan.check()?;
+
+    let privileged = plan.privileged();
+    if !privileged.is_empty() {
+        approve_privileges(&state, &user, &privileged, request.approve_privileges).await?;
+    }
+
+    // charon can only install what is in its own registry
+    let (package, copied) = state.registries().await?.stage(&request.package)?;
+    for staged in &copied {
+        StagedPackage::record(&state.db, staged).await?;
+    }
+    request.package = package;
+
+    Ok(CborOut(
+        jobs::spawn(&state, JobKind::Install, &request.package, user.id).await?,
+    ))
+}
+
+pub(crate) async fn dry_run_install(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(request): Cbor<DryRunRequest>,
+) -> Result<CborOut<DryRun>> {
+    let registries = state.registries().await?;
+    let (_, registry, title) = registries.find(&request.package)?;
+    let package = registry.load(&title)?;
+
+    let responses = match request.responses {
+        Some(responses) => responses,
+        None => jobs::responses(&state, &title.name).await?,
+    };
+
+    let datasets = state.datasets().await?;
+
+    Ok(CborOut(packages::dry_run(
+        &package,
+        &responses,
+        |dataset| datasets.iter().any(|name| name == dataset),
+    )?))
+}
+
+/// Records the user's approval of elevated privileges, if they gave it and may give it.
+async fn approve_privileges(
+    state: &ServerState,
+    user: &User,
+    steps: &[&PlanStep],
+    approved: bool,
+) -> Result<()> {
+    if !approved {
+        return Err(AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!(
+                    "elevated privileges must be approved: {}",
+                    steps
+                        .iter()
+                        .map(|step| format!(
+                            "{} {} ({})",
+                            step.package.name,
+                            step.package.version,
+                            step.privileges.describe().join(", ")
+                        ))
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ))
+                .with_status(http::StatusCode::FORBIDDEN)
+                .with_title("Approval Required"),
+        ));
+    }
+
+    let approvers = &state.config.packages.approvers;
+    if !approvers.is_empty() && !approvers.contains(&user.username) {
+        return Err(AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!(
+                    "{} may not approve elevated privileges",
+                    user.username
+                ))
+                .with_status(http::StatusCode::FORBIDDEN)
+                .with_title("Approval Required"),
+        ));
+    }
+
+    let approved: HashMap<String, Privileges> = steps
+        .iter()
+        .map(|step| {
+            (
+                format!("{} {}", step.package.name, step.package.version),
+                step.privileges.clone(),
+            )
+        })
+        .collect();
+
+    AuditLog::builder()
+        .from_user(user)
+        .with_entry("Approved elevated privileges")
+        .with_data(&approved)?
+        .complete(&state.db)
+        .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn package_status(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+) -> Result<CborOut<Vec<PackageStatus>>> {
+    let installed = state.charon.query().await?.list_installed().await?;
+    let used: HashMap<String, u64> = state
+        .buckle
+        .zfs()
+        .await?
+        .list(None)
+        .await?
+        .into_iter()
+        .map(|stat| (stat.name, stat.used))
+        .collect();
+
+    let registries = state.registries().await?;
+    let mut statuses = Vec::new();
+    for title in installed {
+        // one package that cannot be read should not hide the others
+        let package = match registries.load(&title) {
+            Ok(package) => package,
+            Err(e) => {
+                statuses.push(PackageStatus {
+                    package: title,
+                    source: None,
+                    running: false,
+                    units: Vec::new(),
+                    resources: Default::default(),
+                    volumes: Vec::new(),
+                    error: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+        let responses = jobs::responses(&state, &title.name).await?;
+
+        let (units, error) = match packages::package_unit_status(&state.buckle, &title).await {
+            Ok(units) => (units, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+
+        let volumes = package
+            .storage
+            .volumes
+            .iter()
+            .map(|volume| {
+                let dataset = packages::volume_dataset(&title, volume);
+                VolumeUsage {
+                    name: volume.name.clone(),
+                    size: packages::volume_size(volume, &responses),
+                    used: used.get(&dataset).copied(),
+                    dataset,
+                }
+            })
+            .collect();
+
+        statuses.push(PackageStatus {
+            source: package.source.kind(),
+            running: !units.is_empty() && units.iter().all(|unit| unit.active_state == "active"),
+            units,
+            resources: package.resources,
+            volumes,
+            error,
+            package: title,
+        });
+    }
+
+    Ok(CborOut(statuses))
+}
+
+pub(crate) async fn start_package(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
+    Log(mut log): Log,
     Cbor(pkg): Cbor<charon::PackageTitle>,
-) -> Result<CborOut<()>> {
-    state
+) -> Result<WithLog<CborOut<Vec<UnitStatus>>>> {
+    let log = log.with_entry("Starting package").with_data(&pkg)?.clone();
+    let res = control_package(&state, &pkg, UnitAction::Start).await;
+    Ok(state.with_log(res, log))
+}
+
+pub(crate) async fn stop_package(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<WithLog<CborOut<Vec<UnitStatus>>>> {
+    let log = log.with_entry("Stopping package").with_data(&pkg)?.clone();
+    let res = control_package(&state, &pkg, UnitAction::Stop).await;
+    Ok(state.with_log(res, log))
+}
+
+pub(crate) async fn restart_package(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<WithLog<CborOut<Vec<UnitStatus>>>> {
+    let log = log
+        .with_entry("Restarting package")
+        .with_data(&pkg)?
+        .clone();
+    let res = control_package(&state, &pkg, UnitAction::Restart).await;
+    Ok(state.with_log(res, log))
+}
+
+async fn control_package(
+    state: &ServerState,
+    pkg: &PackageTitle,
+    action: UnitAction,
+) -> Result<CborOut<Vec<UnitStatus>>> {
+    if !state
         .charon
-        .control()
+        .query()
         .await?
-        .install(&pkg.name, &pkg.version)
-        .await?;
-    Ok(CborOut(()))
+        .list_installed()
+        .await?
+        .contains(pkg)
+    {
+        return Err(AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!("{} {} is not installed", pkg.name, pkg.version))
+                .with_status(http::StatusCode::NOT_FOUND)
+                .with_title("Package Not Installed"),
+        ));
+    }
+
+    Ok(CborOut(
+        packages::control_units(&state.buckle, pkg, action).await?,
+    ))
 }
 
-pub(crate) async fn uninstall_package(
+pub(crate) async fn package_log(
     State(state): State<Arc<ServerState>>,
     Account(_): Account<User>,
-    Cbor(pkg): Cbor<charon::PackageTitle>,
-) -> Result<CborOut<()>> {
-    state
+    Cbor(params): Cbor<PackageLogParameters>,
+) -> Result<CborOut<PackageLog>> {
+    if !state
         .charon
-        .control()
+        .query()
+        .await?
+        .list_installed()
         .await?
-        .uninstall(&pkg.name, &pkg.version)
+        .contains(&params.package)
+    {
+        return Err(anyhow!(
+            "{} {} is not installed",
+            params.package.name,
+            params.package.version
+        )
+        .into());
+    }
+
+    // each unit gives up to a page, as the merge cannot know which will fill it
+    let mut logs = Vec::new();
+    for unit in packages::package_units(&state.buckle, &params.package).await? {
+        let entries = read_log(
+            &state,
+            LogParameters {
+                name: unit.name.clone(),
+                count: params.count,
+                cursor: params.cursors.get(&unit.name).cloned(),
+                direction: params.direction.clone(),
+            },
+        )
         .await?;
-    Ok(CborOut(()))
+        logs.push((unit.name, entries));
+    }
+
+    Ok(CborOut(packages::merge_logs(
+        logs,
+        params.count,
+        matches!(
+            params.direction,
+            Some(buckle::systemd::LogDirection::Backward)
+        ),
+        params.cursors,
+    )))
+}
+
+pub(crate) async fn export_package(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<PackageExportRequest>,
+) -> Result<WithLog<CborOut<PackageExport>>> {
+    let log = log
+        .with_entry("Exporting package")
+        .with_data(&request)?
+        .clone();
+
+    let res = tasks::export_package(&state, &request.package, request.volumes)
+        .await
+        .map(|(file, archive)| {
+            CborOut(PackageExport {
+                file,
+                volumes: archive.volumes,
+            })
+        });
+    Ok(state.with_log(res.map_err(Into::into), log))
+}
+
+pub(crate) async fn import_package(
+    State(state): State<Arc<ServerState>>,
+    Account(user): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<PackageImportRequest>,
+) -> Result<WithLog<CborOut<Job>>> {
+    let log = log
+        .with_entry("Importing package")
+        .with_data(&request)?
+        .clone();
+
+    let res: Result<CborOut<Job>> = async {
+        // approval comes before anything is restored
+        let archive = tasks::archive_manifest(&state, &request.file).await?;
+        let plan = jobs::plan(&state, &archive.package).await?;
+        let privileged = plan.privileged();
+        if !privileged.is_empty() {
+            approve_privileges(&state, &user, &privileged, request.approve_privileges).await?;
+        }
+
+        let archive = tasks::import_package(&state, &request.file).await?;
+        Ok(CborOut(
+            jobs::spawn(&state, JobKind::Install, &archive.package, user.id).await?,
+        ))
+    }
+    .await;
+    Ok(state.with_log(res, log))
+}
+
+/// Sends an archive from the backups directory, such as one made by an export.
+pub(crate) async fn download_archive(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Path(file): Path<String>,
+) -> Result<axum::response::Response> {
+    use tokio::io::AsyncReadExt;
+
+    let archive = tokio::fs::File::open(tasks::archive_path(&state, &file)?)
+        .await
+        .map_err(|_| {
+            AppError::new(
+                ProblemDetails::new()
+                    .with_detail(format!("no archive named {}", file))
+                    .with_status(http::StatusCode::NOT_FOUND)
+                    .with_title("Archive Not Found"),
+            )
+        })?;
+
+    // archives hold volume streams, so they are sent a piece at a time
+    let body = futures_util::stream::try_unfold(archive, |mut archive| async move {
+        let mut buf = vec![0; 64 * 1024];
+        let n = archive.read(&mut buf).await?;
+        if n == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        buf.truncate(n);
+        Ok(Some((buf, archive)))
+    });
+
+    Ok(axum::response::Response::builder()
+        .header("Content-Type", "application/x-tar")
+        .body(axum::body::Body::from_stream(body))
+        .map_err(anyhow::Error::from)?)
+}
+
+/// Stores an archive in the backups directory, so that it can be imported.
+pub(crate) async fn upload_archive(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Path(file): Path<String>,
+    body: axum::body::Body,
+) -> Result<WithLog<CborOut<()>>> {
+    use tokio::io::AsyncWriteExt;
+
+    let path = tasks::archive_path(&state, &file)?;
+    let exists = || {
+        AppError::new(
+            ProblemDetails::new()
+                .with_detail(format!("{} already exists", file))
+                .with_status(http::StatusCode::CONFLICT)
+                .with_title("Archive Exists"),
+        )
+    };
+    if tokio::fs::try_exists(&path).await? {
+        return Err(exists());
+    }
+
+    let log = log
+        .with_entry("Uploading package archive")
+        .with_data(&file)?
+        .clone();
+
+    // written aside and linked into place, so a failed upload leaves nothing to import and
+    // neither an upload in progress nor a finished archive is ever overwritten
+    let partial = path.with_file_name(format!(".{}.partial", file));
+    tokio::fs::create_dir_all(&state.config.zfs.backups).await?;
+    let mut out = match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&partial)
+        .await
+    {
+        Ok(out) => out,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(exists()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let res: Result<()> = async {
+        let mut body = body.into_data_stream();
+        while let Some(chunk) = body.next().await {
+            out.write_all(&chunk?).await?;
+        }
+        out.sync_all().await?;
+        match tokio::fs::hard_link(&partial, &path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(exists()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    .await;
+
+    let _ = tokio::fs::remove_file(&partial).await;
+    Ok(state.with_log(res.map(CborOut), log))
+}
+
+pub(crate) async fn package_privileges(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<CborOut<Privileges>> {
+    Ok(CborOut(state.registries().await?.load(&pkg)?.privileges()))
+}
+
+pub(crate) async fn uninstall_package(
+    State(state): State<Arc<ServerState>>,
+    Account(user): Account<User>,
+    Cbor(request): Cbor<UninstallRequest>,
+) -> Result<CborOut<Job>> {
+    if !request.volumes.is_empty() {
+        check_retention(
+            &jobs::uninstall_volumes(&state, &request.package).await?,
+            &request.volumes,
+        )?;
+    }
+
+    Ok(CborOut(
+        jobs::spawn_with(
+            &state,
+            JobKind::Uninstall,
+            &request.package,
+            user.id,
+            jobs::JobOptions {
+                volumes: request.volumes,
+                ..Default::default()
+            },
+        )
+        .await?,
+    ))
+}
+
+pub(crate) async fn uninstall_volumes(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Cbor(pkg): Cbor<charon::PackageTitle>,
+) -> Result<CborOut<Vec<VolumeRetention>>> {
+    Ok(CborOut(jobs::uninstall_volumes(&state, &pkg).await?))
+}
+
+//
+// System export and import
+//
+
+pub(crate) async fn export_system(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(request): Cbor<SystemExportRequest>,
+) -> Result<WithLog<CborOut<SystemExport>>> {
+    let log = log
+        .with_entry("Exporting system")
+        .with_data(&request)?
+        .clone();
+    let res = system::export(&state.db, &state.charon, &state.config, request.passwords)
+        .await
+        .map(CborOut);
+    Ok(state.with_log(res.map_err(Into::into), log))
+}
+
+pub(crate) async fn import_system(
+    State(state): State<Arc<ServerState>>,
+    Account(_): Account<User>,
+    Log(mut log): Log,
+    Cbor(export): Cbor<SystemExport>,
+) -> Result<WithLog<CborOut<ImportReport>>> {
+    let log = log
+        .with_entry("Importing system")
+        .with_data(serde_json::json!({
+            "version": export.version,
+            "exported": export.exported,
+        }))?
+        .clone();
+    let res = system::import(&state.db, &state.charon, &state.config, export)
+        .await
+        .map(CborOut);
+    Ok(state.with_log(res.map_err(Into::into), log))
 }
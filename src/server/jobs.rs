@@ -0,0 +1,622 @@
+use super::ServerState;
+use crate::{
+    db::models::{Backup, Job, JobKind, JobPhase},
+    packages::{
+        self, check_retention, conflicts, package_units, recreated_volumes, unanswered, unit_state,
+        volume_retention, Dependency, InstallPlan, PlanStep, Retention, VolumeRetention,
+    },
+};
+use anyhow::{anyhow, Result};
+use charon::{PackageTitle, PromptResponses};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::task::AbortHandle;
+use tracing::{error, warn};
+use welds::state::DbState;
+
+//
+// package operations run as background jobs, one at a time, so that long installs do not hold
+// the request open
+//
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Jobs {
+    running: Arc<Mutex<HashMap<u32, Running>>>,
+    // charon operations touch shared state, so jobs run one after another
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// A job that has not finished. Queued jobs are aborted outright; once a job has started, charon
+/// may be partway through a change, so it is only asked to stop at its next step.
+#[derive(Debug, Clone)]
+struct Running {
+    abort: AbortHandle,
+    started: bool,
+    cancel: Cancel,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    /// Fails with `Canceled` if cancellation was asked for. Called between steps.
+    fn check(&self) -> Result<()> {
+        if self.0.load(Ordering::SeqCst) {
+            return Err(Canceled.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Canceled;
+
+impl std::fmt::Display for Canceled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "canceled")
+    }
+}
+
+impl std::error::Error for Canceled {}
+
+/// Choices made when a job was requested that only apply to some kinds of job.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JobOptions {
+    // uninstalls: what to do with each volume, by name; unlisted volumes are kept
+    pub volumes: HashMap<String, Retention>,
+    // upgrades: the saved responses merged with the request's, kept only if the upgrade succeeds
+    pub responses: Option<PromptResponses>,
+}
+
+/// Records a new job and starts it in the background.
+pub(crate) async fn spawn(
+    state: &Arc<ServerState>,
+    kind: JobKind,
+    pkg: &PackageTitle,
+    user_id: u32,
+) -> Result<Job> {
+    spawn_with(state, kind, pkg, user_id, JobOptions::default()).await
+}
+
+pub(crate) async fn spawn_with(
+    state: &Arc<ServerState>,
+    kind: JobKind,
+    pkg: &PackageTitle,
+    user_id: u32,
+    options: JobOptions,
+) -> Result<Job> {
+    let mut job = Job::new_queued(kind, pkg, user_id);
+    job.save(state.db.handle()).await?;
+
+    let out = job.deref().clone();
+    let id = job.id;
+
+    // hold the map while spawning so the job cannot finish before its handle is stored
+    let mut running = state.jobs.running.lock().unwrap();
+    let cancel = Cancel::default();
+    let handle = tokio::spawn(run(state.clone(), job, kind, options, cancel.clone()));
+    running.insert(
+        id,
+        Running {
+            abort: handle.abort_handle(),
+            started: false,
+            cancel,
+        },
+    );
+
+    Ok(out)
+}
+
+/// Stops a queued job and marks it canceled. A job that has started is asked to stop at its next
+/// step instead, rolling back if it needs to, and is marked canceled once it has.
+pub(crate) async fn cancel(state: &ServerState, id: u32) -> Result<Job> {
+    let queued = {
+        let mut running = state.jobs.running.lock().unwrap();
+        match running.get(&id) {
+            Some(job) if !job.started => {
+                job.abort.abort();
+                running.remove(&id);
+                Some(true)
+            }
+            Some(job) => {
+                job.cancel.0.store(true, Ordering::SeqCst);
+                Some(false)
+            }
+            None => None,
+        }
+    };
+
+    let mut job = Job::find_by_id(state.db.handle(), id)
+        .await?
+        .ok_or(anyhow!("invalid job"))?;
+
+    match queued {
+        Some(true) => {
+            job.cancel();
+            job.save(state.db.handle()).await?;
+        }
+        Some(false) => {}
+        None if job.is_finished() => return Err(anyhow!("job {} has already finished", id)),
+        None => return Err(anyhow!("job {} is not running", id)),
+    }
+
+    Ok(job.into_inner())
+}
+
+async fn run(
+    state: Arc<ServerState>,
+    mut job: DbState<Job>,
+    kind: JobKind,
+    options: JobOptions,
+    cancel: Cancel,
+) {
+    let res = execute(&state, &mut job, kind, options, &cancel).await;
+    state.jobs.running.lock().unwrap().remove(&job.id);
+
+    match res {
+        Err(e) if e.is::<Canceled>() => job.cancel(),
+        res => job.finish(res.map_err(|e| e.to_string())),
+    }
+    if let Err(e) = job.save(state.db.handle()).await {
+        error!("Error recording job {}: {}", job.id, e);
+    }
+}
+
+async fn execute(
+    state: &ServerState,
+    job: &mut DbState<Job>,
+    kind: JobKind,
+    options: JobOptions,
+    cancel: &Cancel,
+) -> Result<()> {
+    let _lock = state.jobs.lock.lock().await;
+    let pkg = job.title();
+
+    // from here on the job is only stopped between steps
+    match state.jobs.running.lock().unwrap().get_mut(&job.id) {
+        Some(running) => running.started = true,
+        None => return Err(Canceled.into()),
+    }
+
+    job.start();
+    job.set_phase(JobPhase::Checking);
+    job.save(state.db.handle()).await?;
+
+    let installed = state
+        .charon
+        .control()
+        .await?
+        .installed(&pkg.name, &pkg.version)
+        .await?
+        .is_some();
+
+    match kind {
+        JobKind::Install => {
+            if installed {
+                return Err(anyhow!("{} {} is already installed", pkg.name, pkg.version));
+            }
+
+            job.set_phase(JobPhase::Resolving);
+            job.save(state.db.handle()).await?;
+            let plan = plan(state, &pkg).await?;
+            plan.check()?;
+
+            job.set_phase(JobPhase::Installing);
+            for step in plan.steps.iter().filter(|step| !step.installed) {
+                cancel.check()?;
+                job.log_line(&match &step.required_by {
+                    Some(parent) => format!(
+                        "Installing {} {} for {} {}",
+                        step.package.name, step.package.version, parent.name, parent.version
+                    ),
+                    None => format!("Installing {} {}", step.package.name, step.package.version),
+                });
+                job.save(state.db.handle()).await?;
+
+                state
+                    .charon
+                    .control()
+                    .await?
+                    .install(&step.package.name, &step.package.version)
+                    .await?;
+            }
+        }
+        JobKind::Upgrade => {
+            if installed {
+                return Err(anyhow!("{} {} is already installed", pkg.name, pkg.version));
+            }
+
+            upgrade(state, job, &pkg, options.responses, cancel).await?;
+        }
+        JobKind::Uninstall => {
+            if !installed {
+                return Err(anyhow!("{} {} is not installed", pkg.name, pkg.version));
+            }
+
+            // another package may have started sharing a volume since the request was made
+            let volumes = uninstall_volumes(state, &pkg).await?;
+            check_retention(&volumes, &options.volumes)?;
+            let chosen = &options.volumes;
+            let retained = |retention| {
+                volumes
+                    .iter()
+                    .filter(move |volume| chosen.get(&volume.name) == Some(&retention))
+            };
+
+            job.set_phase(JobPhase::Uninstalling);
+            job.save(state.db.handle()).await?;
+            let snapshot = format!(
+                "gild-uninstall-{}",
+                chrono::Local::now().format("%Y%m%d%H%M%S%3f")
+            );
+            for volume in retained(Retention::Snapshot) {
+                cancel.check()?;
+                job.log_line(&format!(
+                    "Snapshotting volume {} as {}@{}",
+                    volume.name, volume.dataset, snapshot
+                ));
+                job.save(state.db.handle()).await?;
+                state.zpool.snapshot(&volume.dataset, &snapshot).await?;
+            }
+
+            cancel.check()?;
+            state
+                .charon
+                .control()
+                .await?
+                .uninstall(&pkg.name, &pkg.version)
+                .await?;
+
+            // the package is gone now, so a volume that will not go is left for the operator
+            for volume in retained(Retention::Destroy) {
+                job.log_line(&format!("Destroying volume {}", volume.name));
+                let res: anyhow::Result<()> = async {
+                    // buckle will not destroy a dataset with snapshots, and the only one gild
+                    // keeps of a destroyed volume is the base for its next incremental backup
+                    if let Some(backup) = Backup::latest(&state.db, &volume.dataset).await? {
+                        state
+                            .zpool
+                            .destroy_snapshot(&volume.dataset, &backup.snapshot)
+                            .await?;
+                    }
+                    state
+                        .buckle
+                        .zfs()
+                        .await?
+                        .destroy(volume.dataset.clone())
+                        .await?;
+                    Ok(())
+                }
+                .await;
+                if let Err(e) = res {
+                    warn!("could not destroy {}: {}", volume.dataset, e);
+                    job.log_line(&format!(
+                        "Warning: could not destroy volume {} ({}): {}",
+                        volume.name, volume.dataset, e
+                    ));
+                }
+                job.save(state.db.handle()).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The volumes of an installed package, and which other installed packages share them.
+pub(crate) async fn uninstall_volumes(
+    state: &ServerState,
+    pkg: &PackageTitle,
+) -> Result<Vec<VolumeRetention>> {
+    let registries = state.registries().await?;
+    let mut others = Vec::new();
+    for title in state.charon.query().await?.list_installed().await? {
+        others.push(registries.load(&title)?);
+    }
+
+    Ok(volume_retention(&registries.load(pkg)?, &others))
+}
+
+/// Resolves everything installing `pkg` involves, with what is already installed, prompts that
+/// still need responses and version conflicts.
+pub(crate) async fn plan(state: &ServerState, pkg: &PackageTitle) -> Result<InstallPlan> {
+    plan_with(state, pkg, None).await
+}
+
+/// Like [plan], judging `pkg`'s prompts by `responses` instead of the saved ones if given.
+pub(crate) async fn plan_with(
+    state: &ServerState,
+    pkg: &PackageTitle,
+    responses: Option<&PromptResponses>,
+) -> Result<InstallPlan> {
+    // charon has no call for dependencies, so they are read from the definitions; anything in its
+    // registry that charon does not list means `packages.registry` is not the registry it uses
+    let registries = state.registries().await?;
+    let resolved = registries.resolve(pkg)?;
+    let available = state.charon.query().await?.list().await?;
+    if let Some((_, _, dep)) = resolved.iter().find(|(name, _, dep)| {
+        *name == packages::DEFAULT_REGISTRY && !available.contains(&dep.package)
+    }) {
+        return Err(anyhow!(
+            "charon does not have {} {}; packages.registry ({}) must be the registry charon uses",
+            dep.package.name,
+            dep.package.version,
+            state.registry.path().display()
+        ));
+    }
+
+    let installed = state.charon.query().await?.list_installed().await?;
+    let order: Vec<Dependency> = resolved.iter().map(|(_, _, dep)| dep.clone()).collect();
+    let root = &order.last().unwrap().package;
+
+    let mut steps = Vec::new();
+    for (name, registry, dep) in &resolved {
+        let package = registry.load(&dep.package)?;
+        // packages from other registries are not staged until they are installed, so charon
+        // does not know their prompts yet
+        let prompts = match *name {
+            packages::DEFAULT_REGISTRY => {
+                state
+                    .charon
+                    .query()
+                    .await?
+                    .get_prompts(&dep.package.name, &dep.package.version)
+                    .await?
+                    .0
+            }
+            _ => package.prompts.clone(),
+        };
+
+        let responses = match responses {
+            _ if prompts.is_empty() => Default::default(),
+            Some(responses) if &dep.package == root => responses.clone(),
+            _ => self::responses(state, &dep.package.name).await?,
+        };
+
+        steps.push(PlanStep {
+            package: dep.package.clone(),
+            required_by: dep.required_by.clone(),
+            installed: installed.contains(&dep.package),
+            unanswered: unanswered(prompts, &responses),
+            privileges: package.privileges(),
+        });
+    }
+
+    Ok(InstallPlan {
+        conflicts: conflicts(&order, &installed),
+        steps,
+    })
+}
+
+/// Responses to a package's prompts. Packages that have never been configured have none.
+pub(crate) async fn responses(state: &ServerState, name: &str) -> Result<PromptResponses> {
+    Ok(state
+        .charon
+        .query()
+        .await?
+        .get_responses(name)
+        .await
+        .unwrap_or_default())
+}
+
+/// Replaces the installed version of a package with `pkg`, using `merged` as its responses if
+/// given. The saved responses are put back if the upgrade fails.
+async fn upgrade(
+    state: &ServerState,
+    job: &mut DbState<Job>,
+    pkg: &PackageTitle,
+    merged: Option<PromptResponses>,
+    cancel: &Cancel,
+) -> Result<()> {
+    let Some(merged) = merged else {
+        return replace(state, job, pkg, cancel).await;
+    };
+
+    let saved = responses(state, &pkg.name).await?;
+    state
+        .charon
+        .query()
+        .await?
+        .set_responses(&pkg.name, merged)
+        .await?;
+
+    let res = replace(state, job, pkg, cancel).await;
+    if res.is_err() {
+        let restored: Result<()> = async {
+            state
+                .charon
+                .query()
+                .await?
+                .set_responses(&pkg.name, saved)
+                .await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = restored {
+            job.log_line(&format!("Could not restore the previous responses: {}", e));
+        }
+    }
+
+    res
+}
+
+/// Swaps the installed version of a package for `pkg`. Responses are kept, as charon stores them
+/// by package name, and the previous version is restored if the new one does not start.
+async fn replace(
+    state: &ServerState,
+    job: &mut DbState<Job>,
+    pkg: &PackageTitle,
+    cancel: &Cancel,
+) -> Result<()> {
+    let current = state
+        .charon
+        .query()
+        .await?
+        .list_installed()
+        .await?
+        .into_iter()
+        .find(|title| title.name == pkg.name)
+        .ok_or(anyhow!("{} is not installed", pkg.name))?;
+    job.from_version = Some(current.version.clone());
+
+    job.set_phase(JobPhase::Resolving);
+    job.save(state.db.handle()).await?;
+
+    let registries = state.registries().await?;
+    let old = registries.load(&current)?;
+    let new = registries.load(pkg)?;
+
+    let recreated = recreated_volumes(&old, &new, &responses(state, &pkg.name).await?);
+    if !recreated.is_empty() {
+        return Err(anyhow!(
+            "upgrading would recreate volumes: {}",
+            recreated.join(", ")
+        ));
+    }
+
+    let mut plan = plan(state, pkg).await?;
+    // the installed version is about to be replaced
+    plan.conflicts
+        .retain(|conflict| conflict.conflicts_with != current);
+    plan.check()?;
+
+    job.set_phase(JobPhase::Installing);
+    for step in plan
+        .steps
+        .iter()
+        .filter(|step| !step.installed && &step.package != pkg)
+    {
+        cancel.check()?;
+        job.log_line(&format!(
+            "Installing {} {}",
+            step.package.name, step.package.version
+        ));
+        job.save(state.db.handle()).await?;
+        state
+            .charon
+            .control()
+            .await?
+            .install(&step.package.name, &step.package.version)
+            .await?;
+    }
+
+    job.set_phase(JobPhase::Upgrading);
+    job.log_line(&format!(
+        "Replacing {} {} with {}",
+        pkg.name, current.version, pkg.version
+    ));
+    job.save(state.db.handle()).await?;
+
+    cancel.check()?;
+    state
+        .charon
+        .control()
+        .await?
+        .uninstall(&current.name, &current.version)
+        .await?;
+
+    // from here a cancellation rolls back to the version that was installed
+    let res: Result<()> = async {
+        cancel.check()?;
+        state
+            .charon
+            .control()
+            .await?
+            .install(&pkg.name, &pkg.version)
+            .await?;
+
+        job.set_phase(JobPhase::Starting);
+        job.save(state.db.handle()).await?;
+        wait_for_start(state, pkg, cancel).await
+    }
+    .await;
+
+    if let Err(e) = res {
+        job.set_phase(JobPhase::RollingBack);
+        job.log_line(&format!("Upgrade failed: {}", e));
+        job.save(state.db.handle()).await?;
+
+        let rollback: Result<()> = async {
+            // the new version may be partially installed
+            let _ = state
+                .charon
+                .control()
+                .await?
+                .uninstall(&pkg.name, &pkg.version)
+                .await;
+            state
+                .charon
+                .control()
+                .await?
+                .install(&current.name, &current.version)
+                .await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(rollback) = rollback {
+            return Err(anyhow!(
+                "upgrade of {} to {} failed: {}; rolling back to {} also failed: {}",
+                pkg.name,
+                pkg.version,
+                e,
+                current.version,
+                rollback
+            ));
+        }
+
+        if e.is::<Canceled>() {
+            return Err(e);
+        }
+
+        return Err(anyhow!(
+            "upgrade of {} to {} failed and was rolled back to {}: {}",
+            pkg.name,
+            pkg.version,
+            current.version,
+            e
+        ));
+    }
+
+    Ok(())
+}
+
+async fn wait_for_start(state: &ServerState, pkg: &PackageTitle, cancel: &Cancel) -> Result<()> {
+    let timeout = state.config.packages.start_timeout;
+    if timeout == 0 {
+        return Ok(());
+    }
+
+    let mut last = String::from("none of its units are loaded");
+    for _ in 0..timeout {
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        cancel.check()?;
+
+        // charon may not have loaded the units yet
+        let units = package_units(&state.buckle, pkg).await?;
+        if let Some(unit) = units.iter().find(|unit| unit_state(unit) == "failed") {
+            return Err(anyhow!("{} failed to start", unit.name));
+        }
+
+        match units.iter().find(|unit| unit_state(unit) != "active") {
+            Some(unit) => last = format!("{} is {}", unit.name, unit_state(unit)),
+            None if !units.is_empty() => return Ok(()),
+            None => {}
+        }
+    }
+
+    Err(anyhow!(
+        "{} {} did not start within {} seconds: {}",
+        pkg.name,
+        pkg.version,
+        timeout,
+        last
+    ))
+}
@@ -20,9 +20,20 @@ pub struct LogParameters {
     pub direction: Option<buckle::systemd::LogDirection>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackageLogParameters {
+    #[serde(flatten)]
+    pub package: charon::PackageTitle,
+    pub count: usize,
+    // by unit name, as returned with the previous page; units left out start from the beginning
+    #[serde(default)]
+    pub cursors: std::collections::HashMap<String, String>,
+    pub direction: Option<buckle::systemd::LogDirection>,
+}
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Token {
-    pub(crate) token: String,
+    pub token: String,
 }
 
 #[derive(Debug, Clone, Default, Validate, Serialize, Deserialize)]
@@ -58,5 +69,228 @@ pub struct Health {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PromptResponsesWithName {
     pub name: String,
+    // the version whose prompts are answered; defaults to the installed or newest version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
     pub responses: charon::PromptResponses,
 }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoolCapacityReport {
+    pub capacity: crate::zfs::PoolCapacity,
+    // configured alert thresholds the pool is currently at or above
+    pub alerts: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrubScheduleRequest {
+    // in days
+    pub interval: u32,
+    // defaults to one interval from now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<chrono::DateTime<chrono::Local>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaRequest {
+    pub name: String,
+    // sizes such as "20G"; "none" removes the quota or reservation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservation: Option<String>,
+}
+
+impl QuotaRequest {
+    fn parse(size: &Option<String>) -> anyhow::Result<Option<u64>> {
+        Ok(match size.as_deref() {
+            Some("none") => Some(0),
+            Some(size) => Some(crate::zfs::parse_size(size)?),
+            None => None,
+        })
+    }
+
+    pub fn quota(&self) -> anyhow::Result<Option<u64>> {
+        Self::parse(&self.quota)
+    }
+
+    pub fn reservation(&self) -> anyhow::Result<Option<u64>> {
+        Self::parse(&self.reservation)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupRequest {
+    pub dataset: String,
+    // send only the changes since the last complete backup of the dataset
+    pub incremental: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupListRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u8>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreRequest {
+    pub id: u32,
+    // dataset to receive into; must not already exist
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupVerification {
+    pub id: u32,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModifyDatasetRequest {
+    #[serde(flatten)]
+    pub dataset: buckle::client::ModifyDataset,
+    #[serde(default, skip_serializing_if = "crate::zfs::PropertyChanges::is_empty")]
+    pub properties: crate::zfs::PropertyChanges,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModifyVolumeRequest {
+    #[serde(flatten)]
+    pub volume: buckle::client::ModifyVolume,
+    #[serde(default, skip_serializing_if = "crate::zfs::PropertyChanges::is_empty")]
+    pub properties: crate::zfs::PropertyChanges,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZFSListEntry {
+    #[serde(flatten)]
+    pub stat: buckle::client::ZFSStat,
+    pub key_status: crate::zfs::KeyStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedDatasetRequest {
+    #[serde(flatten)]
+    pub dataset: buckle::client::Dataset,
+    pub key: crate::zfs::EncryptionKey,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnlockRequest {
+    pub name: String,
+    // not needed for keyfiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeKeyRequest {
+    pub name: String,
+    pub key: crate::zfs::EncryptionKey,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpgradeRequest {
+    pub name: String,
+    // the version to move to; older versions are allowed
+    pub version: String,
+    // responses to prompts the new version adds; existing responses are kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub responses: Option<charon::PromptResponses>,
+    // acknowledges the elevated privileges the upgrade plan lists
+    #[serde(default)]
+    pub approve_privileges: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallRequest {
+    #[serde(flatten)]
+    pub package: charon::PackageTitle,
+    // acknowledges the elevated privileges the install plan lists
+    #[serde(default)]
+    pub approve_privileges: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageExportRequest {
+    #[serde(flatten)]
+    pub package: charon::PackageTitle,
+    // also snapshot and send the package's volumes
+    #[serde(default)]
+    pub volumes: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageExport {
+    // in the backups directory
+    pub file: String,
+    pub volumes: Vec<crate::packages::ArchivedVolume>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageImportRequest {
+    // an archive in the backups directory
+    pub file: String,
+    #[serde(default)]
+    pub approve_privileges: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemExportRequest {
+    // include password hashes, so users can log in after an import
+    #[serde(default)]
+    pub passwords: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UninstallRequest {
+    #[serde(flatten)]
+    pub package: charon::PackageTitle,
+    // what to do with each volume, by name; unlisted volumes are kept
+    #[serde(default)]
+    pub volumes: std::collections::HashMap<String, crate::packages::Retention>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegistryRequest {
+    pub name: String,
+    // a local directory laid out like charon's registry
+    pub path: std::path::PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryInfo {
+    pub name: String,
+    pub path: std::path::PathBuf,
+    // charon's own registry, which cannot be removed
+    pub default: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryRefresh {
+    pub name: String,
+    pub packages: usize,
+    // package definitions that could not be read
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SideloadRequest {
+    // a package definition, as JSON
+    pub definition: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunRequest {
+    #[serde(flatten)]
+    pub package: charon::PackageTitle,
+    // tried in place of the saved responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub responses: Option<charon::PromptResponses>,
+}
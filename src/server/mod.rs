@@ -1,12 +1,19 @@
 mod axum_support;
 mod handlers;
+mod jobs;
 pub mod messages;
+mod tasks;
 #[cfg(test)]
 mod tests;
 
 use self::handlers::*;
 use crate::db::DB;
-use crate::{config::Config, db::models::AuditLog};
+use crate::packages::{Registries, Registry, SIDELOAD_REGISTRY};
+use crate::zfs::Pool;
+use crate::{
+    config::Config,
+    db::models::{AuditLog, Backup, Job, PackageRegistry, StagedPackage},
+};
 use anyhow::Result;
 use axum::{
     routing::{delete, get, post, put},
@@ -27,6 +34,11 @@ pub struct ServerState {
     buckle: BuckleClient,
     charon: CharonClient,
     db: DB,
+    zpool: Pool,
+    jobs: jobs::Jobs,
+    // held while checking for updates, which the endpoint and the periodic task can both start
+    updates: Arc<tokio::sync::Mutex<()>>,
+    registry: Registry,
     config: Config,
 }
 
@@ -34,37 +46,138 @@ impl ServerState {
     pub(crate) fn with_log<T>(&self, resp: axum_support::Result<T>, log: AuditLog) -> WithLog<T> {
         WithLog(resp, log, self.clone().into())
     }
+
+    /// charon's registry and the sideload registry, followed by any that have been added.
+    pub(crate) async fn registries(&self) -> Result<Registries> {
+        let mut registries = Registries::new(self.registry.clone());
+        registries.add(SIDELOAD_REGISTRY, self.config.sideload());
+        for registry in PackageRegistry::list(&self.db).await? {
+            registries.add(&registry.name, registry.registry());
+        }
+        for staged in StagedPackage::list(&self.db).await? {
+            registries.add_staged(staged.package());
+        }
+        Ok(registries)
+    }
+
+    /// Names of the datasets and volumes in the pool, relative to it.
+    pub(crate) async fn datasets(&self) -> Result<Vec<String>> {
+        Ok(self
+            .buckle
+            .zfs()
+            .await?
+            .list(None)
+            .await?
+            .into_iter()
+            .map(|stat| stat.name)
+            .collect())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Server {
     config: Config,
     router: Router,
+    state: Arc<ServerState>,
 }
 
 impl Server {
     pub async fn new(config: Config) -> Result<Self> {
+        let db = config.get_db().await?;
+        Job::fail_interrupted(&db).await?;
+        Backup::fail_interrupted(&db).await?;
+
+        let state = Arc::new(ServerState {
+            buckle: config.buckle()?,
+            charon: config.charon()?,
+            db,
+            zpool: config.zpool(),
+            jobs: Default::default(),
+            updates: Default::default(),
+            registry: config.registry(),
+            config: config.clone(),
+        });
+
         Ok(Self {
             router: Router::new()
                 .route("/packages/uninstall", post(uninstall_package))
+                .route("/packages/uninstall_volumes", post(uninstall_volumes))
                 .route("/packages/install", post(install_package))
+                .route("/packages/dry_run", post(dry_run_install))
+                .route("/packages/upgrade", post(upgrade_package))
                 .route("/packages/prompts", post(get_prompts))
                 .route("/packages/get_responses", post(get_responses))
                 .route("/packages/set_responses", post(set_responses))
                 .route("/packages/installed", post(installed))
+                .route("/packages/plan", post(plan_install))
+                .route("/packages/updates", get(package_updates))
+                .route("/packages/check_updates", post(check_updates))
                 .route("/packages/list_installed", get(list_installed))
                 .route("/packages/list", get(list_packages))
+                .route("/packages/detail", post(package_detail))
+                .route("/packages/search", post(search_packages))
+                .route("/packages/privileges", post(package_privileges))
+                .route("/packages/status", get(package_status))
+                .route("/packages/start", post(start_package))
+                .route("/packages/stop", post(stop_package))
+                .route("/packages/restart", post(restart_package))
+                .route("/packages/log", post(package_log))
+                .route("/packages/export", post(export_package))
+                .route("/packages/import", post(import_package))
+                .route(
+                    "/packages/archives/{file}",
+                    get(download_archive).put(upload_archive),
+                )
+                .route("/packages/validate", post(validate_package))
+                .route("/packages/sideload", post(sideload_package))
+                .route("/registries", get(list_registries).put(add_registry))
+                .route("/registries/{name}", delete(remove_registry))
+                .route("/registries/refresh", post(refresh_registries))
+                .route("/registries/packages", get(list_registry_packages))
+                .route("/jobs", post(list_jobs))
+                .route("/jobs/{id}", get(get_job))
+                .route("/jobs/{id}/cancel", post(cancel_job))
                 .route("/systemd/log", post(unit_log))
                 .route("/systemd/list", post(list_units))
                 .route("/systemd/set_unit", post(set_unit))
                 .route("/status/ping", get(ping))
                 .route("/status/log", post(log))
+                .route("/system/export", post(export_system))
+                .route("/system/import", post(import_system))
                 .route("/zfs/list", post(zfs_list))
                 .route("/zfs/create_volume", post(zfs_create_volume))
                 .route("/zfs/create_dataset", post(zfs_create_dataset))
                 .route("/zfs/modify_dataset", post(zfs_modify_dataset))
                 .route("/zfs/modify_volume", post(zfs_modify_volume))
                 .route("/zfs/destroy", post(zfs_destroy))
+                .route("/zfs/properties", post(zfs_properties))
+                .route(
+                    "/zfs/create_encrypted_dataset",
+                    post(zfs_create_encrypted_dataset),
+                )
+                .route("/zfs/unlock", post(zfs_unlock))
+                .route("/zfs/lock", post(zfs_lock))
+                .route("/zfs/change_key", post(zfs_change_key))
+                .route("/zfs/pool_status", get(zfs_pool_status))
+                .route("/zfs/pool_capacity", get(zfs_pool_capacity))
+                .route("/zfs/pool_history", post(zfs_pool_history))
+                .route("/zfs/quotas", post(zfs_quotas))
+                .route("/zfs/set_quota", post(zfs_set_quota))
+                .route("/zfs/scrub_start", post(zfs_scrub_start))
+                .route("/zfs/scrub_pause", post(zfs_scrub_pause))
+                .route("/zfs/scrub_cancel", post(zfs_scrub_cancel))
+                .route("/zfs/scrub_history", post(zfs_scrub_history))
+                .route("/zfs/backup", post(zfs_backup))
+                .route("/zfs/backup/{id}", get(zfs_get_backup))
+                .route("/zfs/backups", post(zfs_backups))
+                .route("/zfs/backup_verify", post(zfs_backup_verify))
+                .route("/zfs/restore", post(zfs_restore))
+                .route(
+                    "/zfs/scrub_schedule",
+                    get(zfs_get_scrub_schedule)
+                        .put(zfs_set_scrub_schedule)
+                        .delete(zfs_remove_scrub_schedule),
+                )
                 .route("/users", put(create_user).post(list_users))
                 .route(
                     "/user/{id}",
@@ -72,12 +185,7 @@ impl Server {
                 )
                 .route("/session/login", post(login))
                 .route("/session/me", get(me))
-                .with_state(Arc::new(ServerState {
-                    buckle: config.buckle()?,
-                    charon: config.charon()?,
-                    db: config.get_db().await?,
-                    config: config.clone(),
-                }))
+                .with_state(state.clone())
                 .layer(
                     ServiceBuilder::new()
                         .layer(
@@ -104,10 +212,13 @@ impl Server {
                         ),
                 ),
             config: config.clone(),
+            state,
         })
     }
 
     pub async fn start(&self) -> Result<()> {
+        tasks::start(self.state.clone());
+
         let handle = axum_server::Handle::new();
         tokio::spawn(shutdown_signal(handle.clone()));
         Ok(axum_server::bind(self.config.listen)
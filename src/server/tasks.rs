@@ -0,0 +1,496 @@
+use super::{jobs, ServerState};
+use crate::{
+    db::models::{AuditLog, Backup, PackageUpdate, PoolSample, ScrubResult, ScrubSchedule},
+    packages::{
+        changes, compare_versions, validate_responses, volume_dataset, ArchivedVolume,
+        PackageArchive,
+    },
+    zfs::ScanState,
+};
+use anyhow::{anyhow, Result};
+use charon::PackageTitle;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{error, warn};
+use welds::state::DbState;
+
+//
+// periodic tasks that run alongside the server
+//
+
+const SCRUB_CHECK_INTERVAL: u64 = 60;
+
+pub(crate) fn start(state: Arc<ServerState>) {
+    tokio::spawn(sample_pool(state.clone()));
+    tokio::spawn(monitor_scrubs(state.clone()));
+    tokio::spawn(monitor_updates(state.clone()));
+}
+
+async fn sample_pool(state: Arc<ServerState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.config.zfs.sample_interval.max(1),
+    ));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = record_pool_sample(&state).await {
+            error!("Error sampling pool capacity: {}", e);
+        }
+    }
+}
+
+async fn record_pool_sample(state: &ServerState) -> Result<()> {
+    // with no history yet, every threshold the pool is already over counts as crossed
+    let previous = PoolSample::latest(&state.db, state.zpool.name())
+        .await?
+        .map_or(0, |previous| previous.capacity);
+    let sample: PoolSample = state.zpool.capacity().await?.into();
+
+    for threshold in &state.config.zfs.capacity_alerts {
+        let threshold = u32::from(*threshold);
+        let entry = if previous < threshold && sample.capacity >= threshold {
+            "Pool capacity exceeded threshold"
+        } else if previous >= threshold && sample.capacity < threshold {
+            "Pool capacity fell below threshold"
+        } else {
+            continue;
+        };
+
+        let mut map: HashMap<&str, String> = HashMap::default();
+        map.insert("pool", sample.pool.clone());
+        map.insert("threshold", threshold.to_string());
+        map.insert("capacity", sample.capacity.to_string());
+
+        AuditLog::builder()
+            .with_entry(entry)
+            .with_data(&map)?
+            .complete(&state.db)
+            .await?;
+    }
+
+    DbState::new_uncreated(sample)
+        .save(state.db.handle())
+        .await?;
+    PoolSample::prune(&state.db, state.config.zfs.history_days).await
+}
+
+async fn monitor_scrubs(state: Arc<ServerState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(SCRUB_CHECK_INTERVAL));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = run_scheduled_scrub(&state).await {
+            error!("Error running scheduled scrub: {}", e);
+        }
+    }
+}
+
+async fn run_scheduled_scrub(state: &ServerState) -> Result<()> {
+    reconcile_scrubs(state).await?;
+
+    let schedule = ScrubSchedule::for_pool(&state.db, state.zpool.name())
+        .await?
+        .filter(|schedule| schedule.is_due());
+
+    if let Some(mut schedule) = schedule {
+        if ScrubResult::running(&state.db, state.zpool.name())
+            .await?
+            .is_none()
+        {
+            start_scrub(state, true).await?;
+        }
+
+        schedule.advance();
+        schedule.save(state.db.handle()).await?;
+    }
+
+    Ok(())
+}
+
+/// Starts (or resumes, if paused) a scrub of the pool and records it.
+pub(crate) async fn start_scrub(state: &ServerState, scheduled: bool) -> Result<()> {
+    reconcile_scrubs(state).await?;
+    let running = ScrubResult::running(&state.db, state.zpool.name()).await?;
+
+    state.zpool.scrub().await?;
+
+    if running.is_none() {
+        ScrubResult::new_started(state.zpool.name(), scheduled)
+            .save(state.db.handle())
+            .await?;
+
+        if scheduled {
+            let mut map: HashMap<&str, &str> = HashMap::default();
+            map.insert("pool", state.zpool.name());
+
+            AuditLog::builder()
+                .with_entry("Starting scheduled scrub")
+                .with_data(&map)?
+                .complete(&state.db)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Completes any recorded scrub that the pool reports as finished or canceled.
+pub(crate) async fn reconcile_scrubs(state: &ServerState) -> Result<()> {
+    let Some(mut result) = ScrubResult::running(&state.db, state.zpool.name()).await? else {
+        return Ok(());
+    };
+
+    let scan = state.zpool.status().await?.scan.unwrap_or_default();
+    let finished = result.finish(&scan);
+    result.save(state.db.handle()).await?;
+
+    if finished {
+        let entry = match scan.state {
+            ScanState::Canceled => "Scrub canceled",
+            ScanState::None => "Scrub ended without a result",
+            _ => "Scrub finished",
+        };
+
+        AuditLog::builder()
+            .with_entry(entry)
+            .with_data(result.deref())?
+            .complete(&state.db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Streams the backup's snapshot to its file and records the outcome. Only the latest complete
+/// backup's snapshot is kept, as the base for the next incremental one.
+pub(crate) async fn send_backup(
+    state: Arc<ServerState>,
+    mut backup: DbState<Backup>,
+    from: Option<String>,
+) {
+    let file = state.config.zfs.backups.join(&backup.file);
+    let sent = state
+        .zpool
+        .send(&backup.dataset, &backup.snapshot, from.as_deref(), &file)
+        .await;
+
+    // the backup being sent is still running, so this is the one before it
+    let previous = Backup::latest(&state.db, &backup.dataset).await;
+
+    let (entry, stale) = match sent {
+        Ok((size, checksum)) => {
+            backup.complete(size, checksum);
+            let stale = match previous {
+                Ok(previous) => previous.map(|previous| previous.snapshot),
+                Err(e) => {
+                    error!("Error finding the backup before {}: {}", backup.file, e);
+                    None
+                }
+            };
+            ("Backup complete", stale)
+        }
+        Err(e) => {
+            backup.fail(&e.to_string());
+            ("Backup failed", Some(backup.snapshot.clone()))
+        }
+    };
+
+    if let Some(snapshot) = stale {
+        destroy_backup_snapshot(&state, &backup.dataset, &snapshot).await;
+    }
+
+    let dataset = backup.dataset.clone();
+    if let Err(e) = record_backup(&state, backup, entry).await {
+        error!("Error recording backup of {}: {}", dataset, e);
+    }
+}
+
+async fn destroy_backup_snapshot(state: &ServerState, dataset: &str, snapshot: &str) {
+    if let Err(e) = state.zpool.destroy_snapshot(dataset, snapshot).await {
+        warn!("could not destroy snapshot {}@{}: {}", dataset, snapshot, e);
+    }
+}
+
+async fn record_backup(
+    state: &ServerState,
+    mut backup: DbState<Backup>,
+    entry: &str,
+) -> Result<()> {
+    backup.save(state.db.handle()).await?;
+
+    AuditLog::builder()
+        .with_entry(entry)
+        .with_data(backup.deref())?
+        .complete(&state.db)
+        .await
+}
+
+/// Checks the backup file against the checksum recorded when it was sent.
+pub(crate) async fn verify_backup(state: &ServerState, backup: &Backup) -> Result<()> {
+    let checksum = crate::zfs::checksum(&state.config.zfs.backups.join(&backup.file)).await?;
+
+    if backup.checksum.as_ref() != Some(&checksum) {
+        return Err(anyhow!(
+            "backup {} of {} does not match its checksum",
+            backup.id,
+            backup.dataset
+        ));
+    }
+
+    Ok(())
+}
+
+/// Bundles an installed package's responses, and optionally its volumes, into an archive in the
+/// backups directory.
+pub(crate) async fn export_package(
+    state: &ServerState,
+    pkg: &PackageTitle,
+    volumes: bool,
+) -> Result<(String, PackageArchive)> {
+    if !state
+        .charon
+        .query()
+        .await?
+        .list_installed()
+        .await?
+        .contains(pkg)
+    {
+        return Err(anyhow!("{} {} is not installed", pkg.name, pkg.version));
+    }
+
+    let package = state.registries().await?.load(pkg)?;
+    let stamp = chrono::Local::now().format("%Y%m%d%H%M%S%3f").to_string();
+    let file = format!("{}-{}-{}.tar", pkg.name, pkg.version, stamp);
+
+    let streams = staging(state, &file).await?;
+    let res: Result<PackageArchive> = async {
+        let mut archive = PackageArchive {
+            package: pkg.clone(),
+            responses: jobs::responses(state, &pkg.name).await?,
+            volumes: Vec::new(),
+        };
+
+        if volumes {
+            let snapshot = format!("gild-export-{}", stamp);
+            for volume in &package.storage.volumes {
+                let mut archived = ArchivedVolume {
+                    name: volume.name.clone(),
+                    dataset: volume_dataset(pkg, volume),
+                    ..Default::default()
+                };
+
+                state.zpool.snapshot(&archived.dataset, &snapshot).await?;
+                let sent = state
+                    .zpool
+                    .send(
+                        &archived.dataset,
+                        &snapshot,
+                        None,
+                        &archived.stream(&streams)?,
+                    )
+                    .await;
+                // the stream is all the archive needs
+                let destroyed = state
+                    .zpool
+                    .destroy_snapshot(&archived.dataset, &snapshot)
+                    .await;
+                (archived.size, archived.sha256) = sent?;
+                destroyed?;
+                archive.volumes.push(archived);
+            }
+        }
+
+        let path = state.config.zfs.backups.join(&file);
+        let (out, streams) = (archive.clone(), streams.clone());
+        tokio::task::spawn_blocking(move || out.write(&path, &streams)).await??;
+
+        Ok(archive)
+    }
+    .await;
+
+    let _ = tokio::fs::remove_dir_all(&streams).await;
+    Ok((file, res?))
+}
+
+/// Reads the manifest of an archive in the backups directory.
+pub(crate) async fn archive_manifest(state: &ServerState, file: &str) -> Result<PackageArchive> {
+    let path = archive_path(state, file)?;
+    tokio::task::spawn_blocking(move || PackageArchive::read(&path, None)).await?
+}
+
+/// Restores the responses and volumes in a package archive, leaving the package to be installed.
+pub(crate) async fn import_package(state: &ServerState, file: &str) -> Result<PackageArchive> {
+    let path = archive_path(state, file)?;
+
+    let streams = staging(state, file).await?;
+    let res: Result<PackageArchive> = async {
+        let unpack = streams.clone();
+        let archive =
+            tokio::task::spawn_blocking(move || PackageArchive::read(&path, Some(&unpack)))
+                .await??;
+        let pkg = &archive.package;
+
+        let package = state.registries().await?.load(pkg)?;
+        if state
+            .charon
+            .query()
+            .await?
+            .list_installed()
+            .await?
+            .iter()
+            .any(|title| title.name == pkg.name)
+        {
+            return Err(anyhow!("{} is already installed", pkg.name));
+        }
+
+        let prompts = state
+            .charon
+            .query()
+            .await?
+            .get_prompts(&pkg.name, &pkg.version)
+            .await?;
+        if let Some(error) = validate_responses(&prompts.0, &archive.responses).first() {
+            return Err(anyhow!("archived responses do not match: {}", error));
+        }
+
+        // volumes go where the package's definition puts them, not where the manifest says
+        let datasets = state.datasets().await?;
+        let mut targets = Vec::new();
+        for volume in &archive.volumes {
+            let declared = package
+                .storage
+                .volumes
+                .iter()
+                .find(|declared| declared.name == volume.name)
+                .ok_or(anyhow!(
+                    "{} {} has no volume named {}",
+                    pkg.name,
+                    pkg.version,
+                    volume.name
+                ))?;
+
+            let target = volume_dataset(pkg, declared);
+            if datasets.contains(&target) {
+                return Err(anyhow!(
+                    "{} already exists; destroy it before importing",
+                    target
+                ));
+            }
+
+            if crate::zfs::checksum(&volume.stream(&streams)?).await? != volume.sha256 {
+                return Err(anyhow!(
+                    "volume {} does not match its checksum",
+                    volume.name
+                ));
+            }
+            targets.push(target);
+        }
+
+        for (volume, target) in archive.volumes.iter().zip(&targets) {
+            state
+                .zpool
+                .receive(target, &volume.stream(&streams)?)
+                .await?;
+            state.zpool.mount(target).await?;
+        }
+
+        state
+            .charon
+            .query()
+            .await?
+            .set_responses(&pkg.name, archive.responses.clone())
+            .await?;
+
+        Ok(archive)
+    }
+    .await;
+
+    let _ = tokio::fs::remove_dir_all(&streams).await;
+    res
+}
+
+/// Archives are addressed by name, and must live directly in the backups directory.
+pub(crate) fn archive_path(state: &ServerState, file: &str) -> Result<PathBuf> {
+    if Path::new(file).file_name() != Some(std::ffi::OsStr::new(file)) {
+        return Err(anyhow!("invalid archive name: {}", file));
+    }
+
+    Ok(state.config.zfs.backups.join(file))
+}
+
+/// A scratch directory for the volume streams of an archive.
+async fn staging(state: &ServerState, file: &str) -> Result<PathBuf> {
+    let dir = state.config.zfs.backups.join(format!(".{}", file));
+    tokio::fs::create_dir_all(&dir).await?;
+    Ok(dir)
+}
+
+async fn monitor_updates(state: Arc<ServerState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.config.packages.update_interval.max(1),
+    ));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = check_updates(&state).await {
+            error!("Error checking for package updates: {}", e);
+        }
+    }
+}
+
+/// Compares installed packages against the registry and caches the newer versions found.
+pub(crate) async fn check_updates(state: &ServerState) -> Result<Vec<PackageUpdate>> {
+    // two checks at once would both insert a package's first update, and one would prune the
+    // other's results
+    let _lock = state.updates.lock().await;
+    let checked = chrono::Local::now();
+    let installed = state.charon.query().await?.list_installed().await?;
+    let available = state.charon.query().await?.list().await?;
+    let registries = state.registries().await?;
+
+    for current in &installed {
+        let Some(newest) = available
+            .iter()
+            .filter(|title| title.name == current.name)
+            .max_by(|a, b| compare_versions(&a.version, &b.version))
+            .filter(|newest| compare_versions(&newest.version, &current.version).is_gt())
+        else {
+            continue;
+        };
+
+        let changes = match (registries.load(current), registries.load(newest)) {
+            (Ok(old), Ok(new)) => changes(&old, &new),
+            _ => String::new(),
+        };
+
+        let mut update = match PackageUpdate::for_name(&state.db, &current.name).await? {
+            Some(update) => update,
+            None => DbState::new_uncreated(PackageUpdate {
+                name: current.name.clone(),
+                ..Default::default()
+            }),
+        };
+
+        let new_version = update.available != newest.version;
+        update.installed = current.version.clone();
+        update.available = newest.version.clone();
+        update.changes = changes;
+        update.checked = checked;
+        update.save(state.db.handle()).await?;
+
+        if new_version && state.config.packages.update_alerts {
+            AuditLog::builder()
+                .with_entry("Package update available")
+                .with_data(update.deref())?
+                .complete(&state.db)
+                .await?;
+        }
+    }
+
+    PackageUpdate::prune(&state.db, checked).await?;
+    PackageUpdate::list(&state.db).await
+}
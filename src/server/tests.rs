@@ -114,9 +114,215 @@ mod packages {
 
     use crate::{
         db::models::User,
+        packages::{ResponseError, ResponseProblem},
         server::messages::*,
         testutil::{start_server, TestClient},
     };
+    #[cfg(feature = "zfs")]
+    use crate::{
+        db::models::{Job, PackageUpdate},
+        testutil::start_server_with,
+    };
+
+    #[tokio::test]
+    async fn registries() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        client.put::<User, User>("/users", login).await.unwrap();
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let registries = client
+            .get::<Vec<RegistryInfo>>("/registries")
+            .await
+            .unwrap();
+        assert_eq!(registries.len(), 2);
+        assert!(registries[0].default);
+        assert_eq!(registries[1].name, "sideload");
+
+        for (name, path) in [("extra", "testdata/extra"), ("bad/name", "testdata/extra")] {
+            let res = client
+                .put::<RegistryRequest, crate::db::models::PackageRegistry>(
+                    "/registries",
+                    RegistryRequest {
+                        name: name.into(),
+                        path: path.into(),
+                    },
+                )
+                .await;
+            assert_eq!(res.is_ok(), name == "extra");
+        }
+        // no packages directory
+        assert!(client
+            .put::<RegistryRequest, crate::db::models::PackageRegistry>(
+                "/registries",
+                RegistryRequest {
+                    name: "empty".into(),
+                    path: "testdata".into(),
+                },
+            )
+            .await
+            .is_err());
+
+        let packages = client
+            .get::<Vec<crate::packages::RegistryPackage>>("/registries/packages")
+            .await
+            .unwrap();
+        // charon's own list is untouched by other registries
+        assert!(client
+            .get::<Vec<PackageTitle>>("/packages/list")
+            .await
+            .unwrap()
+            .iter()
+            .all(|title| title.name != "hello"));
+        let extra: Vec<&PackageTitle> = packages
+            .iter()
+            .filter(|package| package.registry == "extra")
+            .map(|package| &package.title)
+            .collect();
+        assert_eq!(
+            extra,
+            vec![
+                &PackageTitle {
+                    name: "hello".into(),
+                    version: "0.0.1".into(),
+                },
+                &PackageTitle {
+                    name: "extra/plex".into(),
+                    version: "0.0.3".into(),
+                },
+            ]
+        );
+
+        // planning reads the definitions where they are, and copies nothing into charon's registry
+        let plan = client
+            .post::<PackageTitle, crate::packages::InstallPlan>(
+                "/packages/plan",
+                PackageTitle {
+                    name: "hello".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[0].package.version, "0.0.3");
+        assert!(client
+            .get::<Vec<PackageTitle>>("/packages/list")
+            .await
+            .unwrap()
+            .iter()
+            .all(|title| title.name != "hello"));
+
+        let package = client
+            .post::<PackageTitle, serde_json::Value>(
+                "/packages/detail",
+                PackageTitle {
+                    name: "extra/plex".into(),
+                    version: "0.0.3".into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(package["description"], "A plex build from another registry");
+
+        let refreshed = client
+            .post::<(), Vec<RegistryRefresh>>("/registries/refresh", ())
+            .await
+            .unwrap();
+        assert_eq!(refreshed.len(), 3);
+        assert_eq!(refreshed[2].packages, 2);
+        assert!(refreshed.iter().all(|r| r.errors.is_empty()));
+
+        assert!(client.delete_empty("/registries/default").await.is_err());
+        assert!(client.delete_empty("/registries/sideload").await.is_err());
+        client.delete_empty("/registries/extra").await.unwrap();
+        assert_eq!(
+            client
+                .get::<Vec<RegistryInfo>>("/registries")
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn sideload() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        client.put::<User, User>("/users", login).await.unwrap();
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let invalid = SideloadRequest {
+            definition: r#"{"title": {"name": "in-house", "version": "1"}, "source": {"container": "@image@"}}"#.into(),
+        };
+        let errors = client
+            .post::<_, Vec<crate::packages::DefinitionError>>("/packages/validate", invalid.clone())
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "$.source.container");
+        let err = client
+            .post::<_, crate::packages::RegistryPackage>("/packages/sideload", invalid)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::client::Error::Invalid(_)), "{}", err);
+        assert_eq!(
+            err.problem()
+                .unwrap()
+                .errors_as::<crate::packages::DefinitionError>(),
+            errors
+        );
+
+        let valid = SideloadRequest {
+            definition: r#"{"title": {"name": "in-house", "version": "1"}, "source": {"container": "scratch"}}"#.into(),
+        };
+        assert!(client
+            .post::<_, Vec<crate::packages::DefinitionError>>("/packages/validate", valid.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        let listed = client
+            .post::<_, crate::packages::RegistryPackage>("/packages/sideload", valid)
+            .await
+            .unwrap();
+        assert_eq!(listed.registry, "sideload");
+        assert_eq!(listed.title.name, "in-house");
+
+        // the same name as a package in charon's registry is qualified
+        let listed = client
+            .post::<_, crate::packages::RegistryPackage>(
+                "/packages/sideload",
+                SideloadRequest {
+                    definition: r#"{"title": {"name": "plex", "version": "9.0.0"}}"#.into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(listed.title.name, "sideload/plex");
+    }
 
     #[tokio::test]
     async fn get_prompts() {
@@ -208,11 +414,12 @@ mod packages {
         assert!(client.put::<User, User>("/users", login).await.is_ok());
 
         assert!(client
-            .post::<PromptResponsesWithName, ()>(
+            .post_empty(
                 "/packages/set_responses",
                 PromptResponsesWithName {
                     name: "with-prompts".into(),
                     responses: responses.clone(),
+                    ..Default::default()
                 }
             )
             .await
@@ -226,12 +433,66 @@ mod packages {
             .await
             .unwrap();
 
+        // nothing else answers these prompts, so start from a clean slate
+        let _ = std::fs::remove_file("testdata/charon/variables/with-prompts.json");
+        assert!(client
+            .post::<PackageTitle, crate::db::models::Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "with-prompts".into(),
+                    version: "0.0.1".into(),
+                }
+            )
+            .await
+            .is_err());
+
+        let mut invalid = responses.clone();
+        invalid.0[1].input = Input::String("big".into());
+        invalid.0.pop();
+        invalid.0.push(PromptResponse {
+            input: Input::Boolean(true),
+            template: "private_color".into(),
+        });
+        let err = client
+            .post_empty(
+                "/packages/set_responses",
+                PromptResponsesWithName {
+                    name: "with-prompts".into(),
+                    version: Some("0.0.1".into()),
+                    responses: invalid,
+                },
+            )
+            .await
+            .unwrap_err();
+        let mut errors = err.problem().unwrap().errors_as::<ResponseError>();
+        errors.sort_by(|a, b| a.template.cmp(&b.template));
+        assert_eq!(
+            errors,
+            vec![
+                ResponseError {
+                    template: "private_color".into(),
+                    problem: ResponseProblem::Unknown,
+                },
+                ResponseError {
+                    template: "private_recreate".into(),
+                    problem: ResponseProblem::Missing,
+                },
+                ResponseError {
+                    template: "private_size".into(),
+                    problem: ResponseProblem::WrongType(InputType::Integer),
+                },
+            ],
+            "{}",
+            err
+        );
+
         assert!(client
-            .post::<PromptResponsesWithName, ()>(
+            .post_empty(
                 "/packages/set_responses",
                 PromptResponsesWithName {
                     name: "with-prompts".into(),
                     responses: responses.clone(),
+                    ..Default::default()
                 }
             )
             .await
@@ -254,7 +515,7 @@ mod packages {
 
     #[tokio::test]
     #[cfg(feature = "zfs")]
-    async fn install() {
+    async fn dry_run() {
         let mut client = TestClient::new(start_server(None).await.unwrap());
 
         let login = User {
@@ -262,31 +523,7 @@ mod packages {
             plaintext_password: Some("test-password".into()),
             ..Default::default()
         };
-
         client.put::<User, User>("/users", login).await.unwrap();
-
-        assert!(client
-            .post::<PackageTitle, ()>(
-                "/packages/install",
-                PackageTitle {
-                    name: "podman-test".into(),
-                    version: "0.0.1".into(),
-                }
-            )
-            .await
-            .is_err());
-
-        assert!(client
-            .post::<PackageTitle, ()>(
-                "/packages/uninstall",
-                PackageTitle {
-                    name: "podman-test".into(),
-                    version: "0.0.1".into(),
-                }
-            )
-            .await
-            .is_err());
-
         client
             .login(Authentication {
                 username: "test-login".into(),
@@ -295,82 +532,73 @@ mod packages {
             .await
             .unwrap();
 
-        client
-            .post::<PackageTitle, ()>(
-                "/packages/install",
-                PackageTitle {
-                    name: "podman-test".into(),
-                    version: "0.0.1".into(),
+        let title = PackageTitle {
+            name: "with-prompts".into(),
+            version: "0.0.1".into(),
+        };
+
+        // nothing has been answered yet
+        let run = client
+            .post::<_, crate::packages::DryRun>(
+                "/packages/dry_run",
+                DryRunRequest {
+                    package: title.clone(),
+                    responses: None,
                 },
             )
             .await
             .unwrap();
-
-        client
-            .post::<PackageTitle, bool>(
-                "/packages/installed",
-                PackageTitle {
-                    name: "podman-test".into(),
-                    version: "0.0.1".into(),
+        assert_eq!(run.unresolved.len(), 3);
+        assert_eq!(run.volumes[0].dataset, "with-prompts/private");
+        assert!(!run.volumes[0].exists);
+
+        let run = client
+            .post::<_, crate::packages::DryRun>(
+                "/packages/dry_run",
+                DryRunRequest {
+                    package: title.clone(),
+                    responses: Some(PromptResponses(vec![
+                        PromptResponse {
+                            template: "private_path".into(),
+                            input: Input::String("/tmp/yep".into()),
+                        },
+                        PromptResponse {
+                            template: "private_size".into(),
+                            input: Input::Integer(1234567890),
+                        },
+                        PromptResponse {
+                            template: "private_recreate".into(),
+                            input: Input::Boolean(false),
+                        },
+                    ])),
                 },
             )
             .await
             .unwrap();
-
+        assert!(run.unresolved.is_empty());
+        assert_eq!(run.volumes[0].size, Some(1234567890));
         assert_eq!(
-            client
-                .get::<Vec<PackageTitle>>("/packages/list_installed")
-                .await
-                .unwrap(),
-            vec![PackageTitle {
-                name: "podman-test".into(),
-                version: "0.0.1".into()
-            }]
+            run.package.storage.volumes[0].recreate.as_deref(),
+            Some("false")
         );
 
-        client
-            .post::<PackageTitle, ()>(
-                "/packages/uninstall",
-                PackageTitle {
-                    name: "podman-test".into(),
-                    version: "0.0.1".into(),
-                },
-            )
+        // a dry run installs nothing
+        assert!(!client
+            .post::<PackageTitle, bool>("/packages/installed", title)
             .await
-            .unwrap();
-
-        assert_eq!(
-            client
-                .get::<Vec<PackageTitle>>("/packages/list_installed")
-                .await
-                .unwrap(),
-            vec![]
-        );
+            .unwrap());
     }
-}
-
-mod service {
-    use buckle::client::Info;
-
-    use crate::{
-        db::models::User,
-        server::messages::*,
-        testutil::{start_server, TestClient},
-    };
 
     #[tokio::test]
-    async fn ping() {
+    async fn plan() {
         let mut client = TestClient::new(start_server(None).await.unwrap());
-        let results = client.get::<PingResult>("/status/ping").await.unwrap();
-        assert!(results.info.is_none());
 
         let login = User {
             username: "test-login".into(),
             plaintext_password: Some("test-password".into()),
             ..Default::default()
         };
-        assert!(client.put::<User, User>("/users", login).await.is_ok());
-
+        client.put::<User, User>("/users", login).await.unwrap();
         client
             .login(Authentication {
                 username: "test-login".into(),
@@ -379,39 +607,68 @@ mod service {
             .await
             .unwrap();
 
-        let results = client.get::<PingResult>("/status/ping").await.unwrap();
-        assert!(results.info.is_some());
-        let info: Info = results.info.unwrap().into();
-        assert_ne!(info.uptime, 0);
-        assert_ne!(info.available_memory, 0);
-        assert_ne!(info.total_memory, 0);
-        assert_ne!(info.cpus, 0);
-        assert_ne!(info.cpu_usage, 0.0);
-        assert!(!info.host_name.is_empty());
-        assert!(!info.kernel_version.is_empty());
-        assert_ne!(info.load_average, [0.0, 0.0, 0.0]);
-        assert_ne!(info.processes, 0);
-    }
-}
+        let plan = client
+            .post::<PackageTitle, crate::packages::InstallPlan>(
+                "/packages/plan",
+                PackageTitle {
+                    name: "with-dependencies".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
 
-mod user {
-    use crate::db::models::User;
-    use crate::server::messages::Authentication;
-    use crate::testutil::{start_server, TestClient};
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[0].package.name, "plex");
+        assert_eq!(plan.steps[0].package.version, "0.0.2");
+        assert_eq!(
+            plan.steps[0].required_by.as_ref().unwrap().name,
+            "with-dependencies"
+        );
+        assert!(!plan.steps[0].installed);
+        assert!(plan.steps[0].unanswered.is_empty());
+        assert_eq!(plan.steps[1].package.name, "with-dependencies");
+        assert!(plan.check().is_ok());
+        assert!(plan.privileged().is_empty());
+
+        let privileges = client
+            .post::<PackageTitle, crate::packages::Privileges>(
+                "/packages/privileges",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert!(privileges.host_pid && privileges.host_net && privileges.privileged);
+        assert_eq!(privileges.capabilities, vec!["SYS_ADMIN"]);
+
+        assert!(client
+            .post::<PackageTitle, crate::packages::InstallPlan>(
+                "/packages/plan",
+                PackageTitle {
+                    name: "with-dependencies".into(),
+                    version: "9.9.9".into(),
+                },
+            )
+            .await
+            .is_err());
+    }
 
     #[tokio::test]
-    async fn login_logout() {
-        let mut client = TestClient::new(start_server(None).await.unwrap());
+    async fn search() {
+        use crate::packages::{SearchQuery, SearchResults, SourceKind};
 
-        assert!(client.post::<(), Vec<User>>("/users", ()).await.is_err());
+        let mut client = TestClient::new(start_server(None).await.unwrap());
 
         let login = User {
             username: "test-login".into(),
             plaintext_password: Some("test-password".into()),
             ..Default::default()
         };
-        assert!(client.put::<User, User>("/users", login).await.is_ok());
-
+        client.put::<User, User>("/users", login).await.unwrap();
         client
             .login(Authentication {
                 username: "test-login".into(),
@@ -420,11 +677,91 @@ mod user {
             .await
             .unwrap();
 
-        assert!(client.post::<(), Vec<User>>("/users", ()).await.is_ok());
+        // the definition comes back as it is, including fields gild does not read
+        let package = client
+            .post::<PackageTitle, serde_json::Value>(
+                "/packages/detail",
+                PackageTitle {
+                    name: "plex-qemu".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            package,
+            serde_json::from_slice::<serde_json::Value>(
+                &std::fs::read("testdata/charon/packages/plex-qemu/0.0.1.json").unwrap()
+            )
+            .unwrap()
+        );
+        assert_eq!(package["resources"]["memory"], "4096");
+
+        assert!(client
+            .post::<PackageTitle, serde_json::Value>(
+                "/packages/detail",
+                PackageTitle {
+                    name: "plex-qemu".into(),
+                    version: "9.9.9".into(),
+                },
+            )
+            .await
+            .is_err());
+
+        let results = client
+            .post::<SearchQuery, SearchResults>(
+                "/packages/search",
+                SearchQuery {
+                    name: Some("plex".into()),
+                    source: Some(SourceKind::Container),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(results.errors.is_empty(), "{:?}", results.errors);
+        let results = results.packages;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "plex");
+        assert_eq!(results[0].versions.len(), 2);
+        assert_eq!(results[0].versions[0].title.version, "0.0.1");
+
+        let results = client
+            .post::<SearchQuery, SearchResults>("/packages/search", SearchQuery::default())
+            .await
+            .unwrap();
+        assert!(results
+            .packages
+            .iter()
+            .any(|group| group.name == "podman-test" && group.versions.len() == 3));
+
+        // a definition that cannot be read is reported instead of failing the search
+        let broken = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(broken.path().join("packages/broken")).unwrap();
+        std::fs::write(broken.path().join("packages/broken/0.0.1.json"), "{").unwrap();
+        client
+            .put::<RegistryRequest, crate::db::models::PackageRegistry>(
+                "/registries",
+                RegistryRequest {
+                    name: "broken".into(),
+                    path: broken.path().into(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let results = client
+            .post::<SearchQuery, SearchResults>("/packages/search", SearchQuery::default())
+            .await
+            .unwrap();
+        assert!(results.packages.iter().any(|group| group.name == "plex"));
+        assert_eq!(results.errors.len(), 1);
+        assert!(results.errors[0].starts_with("broken 0.0.1: "));
     }
 
     #[tokio::test]
-    async fn first_time_setup() {
+    #[cfg(feature = "zfs")]
+    async fn install_dependencies() {
         let mut client = TestClient::new(start_server(None).await.unwrap());
 
         let login = User {
@@ -432,23 +769,103 @@ mod user {
             plaintext_password: Some("test-password".into()),
             ..Default::default()
         };
-        assert!(client.put::<User, User>("/users", login).await.is_ok());
-
-        let login = User {
-            username: "test-login2".into(),
-            plaintext_password: Some("test-password".into()),
-            ..Default::default()
-        };
-        assert!(client.put::<User, User>("/users", login).await.is_err());
-
+        client.put::<User, User>("/users", login).await.unwrap();
         client
             .login(Authentication {
-                username: "test-login2".into(),
+                username: "test-login".into(),
                 password: "test-password".into(),
             })
             .await
-            .unwrap_err();
+            .unwrap();
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "with-dependencies".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+        assert!(job
+            .log
+            .contains("Installing plex 0.0.2 for with-dependencies 0.0.1"));
+
+        let installed = client
+            .get::<Vec<PackageTitle>>("/packages/list_installed")
+            .await
+            .unwrap();
+        assert!(installed.contains(&PackageTitle {
+            name: "plex".into(),
+            version: "0.0.2".into(),
+        }));
+        assert!(installed.contains(&PackageTitle {
+            name: "with-dependencies".into(),
+            version: "0.0.1".into(),
+        }));
+
+        // the other version of plex conflicts with the one the dependency installed
+        let plan = client
+            .post::<PackageTitle, crate::packages::InstallPlan>(
+                "/packages/plan",
+                PackageTitle {
+                    name: "plex".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(plan.conflicts.len(), 1);
+        assert!(plan.conflicts[0].installed);
+        assert_eq!(plan.conflicts[0].conflicts_with.version, "0.0.2");
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "plex".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Failed");
+        assert_eq!(
+            job.error,
+            Some("plex 0.0.1 conflicts with plex 0.0.2".into())
+        );
+
+        for (name, version) in [("with-dependencies", "0.0.1"), ("plex", "0.0.2")] {
+            let job = client
+                .post::<PackageTitle, Job>(
+                    "/packages/uninstall",
+                    PackageTitle {
+                        name: name.into(),
+                        version: version.into(),
+                    },
+                )
+                .await
+                .unwrap();
+            let job = client.wait_for_job(job.id).await.unwrap();
+            assert_eq!(job.status, "Complete", "{}", job.log);
+        }
+    }
 
+    #[tokio::test]
+    #[cfg(feature = "zfs")]
+    async fn upgrade() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        client.put::<User, User>("/users", login).await.unwrap();
         client
             .login(Authentication {
                 username: "test-login".into(),
@@ -457,24 +874,182 @@ mod user {
             .await
             .unwrap();
 
+        let upgrade = |version: &str| UpgradeRequest {
+            name: "plex".into(),
+            version: version.into(),
+            ..Default::default()
+        };
+
+        let job = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.2"))
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Failed");
+        assert_eq!(job.error, Some("plex is not installed".into()));
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "plex".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+
+        let job = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.2"))
+            .await
+            .unwrap();
+        assert_eq!(job.kind, "Upgrade");
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+        assert_eq!(job.from_version, Some("0.0.1".into()));
+        assert!(job.log.contains("Replacing plex 0.0.1 with 0.0.2"));
+
+        assert_eq!(
+            client
+                .get::<Vec<PackageTitle>>("/packages/list_installed")
+                .await
+                .unwrap(),
+            vec![PackageTitle {
+                name: "plex".into(),
+                version: "0.0.2".into(),
+            }]
+        );
+
+        let job = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.2"))
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Failed");
+
+        // downgrades work the same way
+        let job = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.1"))
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+        assert_eq!(job.from_version, Some("0.0.2".into()));
+
+        assert!(client
+            .post::<PackageTitle, Vec<crate::packages::VolumeRetention>>(
+                "/packages/uninstall_volumes",
+                PackageTitle {
+                    name: "plex".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap()
+            .is_empty());
+
+        // plex has no volumes to destroy
+        assert!(client
+            .post::<UninstallRequest, Job>(
+                "/packages/uninstall",
+                UninstallRequest {
+                    package: PackageTitle {
+                        name: "plex".into(),
+                        version: "0.0.1".into(),
+                    },
+                    volumes: [("missing".into(), crate::packages::Retention::Destroy)].into(),
+                },
+            )
+            .await
+            .is_err());
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/uninstall",
+                PackageTitle {
+                    name: "plex".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "zfs")]
+    async fn upgrade_rollback() {
+        // test packages never start a service, so any wait for one fails
+        let mut client = TestClient::new(
+            start_server_with(None, |config| config.packages.start_timeout = 2)
+                .await
+                .unwrap(),
+        );
+
         let login = User {
-            username: "test-login2".into(),
+            username: "test-login".into(),
             plaintext_password: Some("test-password".into()),
             ..Default::default()
         };
-        assert!(client.put::<User, User>("/users", login).await.is_ok());
-
+        client.put::<User, User>("/users", login).await.unwrap();
         client
             .login(Authentication {
-                username: "test-login2".into(),
+                username: "test-login".into(),
                 password: "test-password".into(),
             })
             .await
             .unwrap();
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "plex".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+
+        let job = client
+            .post::<_, Job>(
+                "/packages/upgrade",
+                UpgradeRequest {
+                    name: "plex".into(),
+                    version: "0.0.2".into(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Failed", "{}", job.log);
+        assert!(job
+            .error
+            .unwrap()
+            .starts_with("upgrade of plex to 0.0.2 failed and was rolled back to 0.0.1"));
+        assert!(job.log.contains("Upgrade failed"));
+
+        assert_eq!(
+            client
+                .get::<Vec<PackageTitle>>("/packages/list_installed")
+                .await
+                .unwrap(),
+            vec![PackageTitle {
+                name: "plex".into(),
+                version: "0.0.1".into(),
+            }]
+        );
     }
 
     #[tokio::test]
-    async fn users_validate() {
+    #[cfg(feature = "zfs")]
+    async fn updates() {
         let mut client = TestClient::new(start_server(None).await.unwrap());
 
         let login = User {
@@ -482,7 +1057,11 @@ mod user {
             plaintext_password: Some("test-password".into()),
             ..Default::default()
         };
-        assert!(client.put::<User, User>("/users", login).await.is_ok());
+        client.put::<User, User>("/users", login).await.unwrap();
+        assert!(client
+            .get::<Vec<PackageUpdate>>("/packages/updates")
+            .await
+            .is_err());
 
         client
             .login(Authentication {
@@ -492,74 +1071,862 @@ mod user {
             .await
             .unwrap();
 
-        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
-        assert_eq!(list.len(), 1);
+        assert!(client
+            .get::<Vec<PackageUpdate>>("/packages/updates")
+            .await
+            .unwrap()
+            .is_empty());
 
-        let table: &[User] = &[
-            User {
-                username: "".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikh".into(),
-                realname: Some("".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikhaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbeaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.meaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.2".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+
+        let updates = client
+            .post::<_, Vec<PackageUpdate>>("/packages/check_updates", ())
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].name, "podman-test");
+        assert_eq!(updates[0].installed, "0.0.2");
+        assert_eq!(updates[0].available, "0.0.3");
+
+        assert_eq!(
+            client
+                .get::<Vec<PackageUpdate>>("/packages/updates")
+                .await
+                .unwrap(),
+            updates
+        );
+
+        // only the first sighting of a version is logged
+        client
+            .post::<_, Vec<PackageUpdate>>("/packages/check_updates", ())
+            .await
+            .unwrap();
+        let logs: Vec<crate::db::models::AuditLog> = client
+            .post("/status/log", Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            logs.iter()
+                .filter(|log| log.entry == "Package update available")
+                .count(),
+            1
+        );
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/uninstall",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.2".into(),
+                },
+            )
+            .await
+            .unwrap();
+        client.wait_for_job(job.id).await.unwrap();
+
+        assert!(client
+            .post::<_, Vec<PackageUpdate>>("/packages/check_updates", ())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "zfs")]
+    async fn install() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+
+        client.put::<User, User>("/users", login).await.unwrap();
+
+        assert!(client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                }
+            )
+            .await
+            .is_err());
+
+        assert!(client
+            .post::<PackageTitle, Job>(
+                "/packages/uninstall",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                }
+            )
+            .await
+            .is_err());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        // podman-test asks for the host's pid and network namespaces, among other things
+        let err = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("podman-test 0.0.1 (host_pid, host_net, privileged, SYS_ADMIN)"),
+            "{}",
+            err
+        );
+
+        let job = client
+            .post::<InstallRequest, Job>(
+                "/packages/install",
+                InstallRequest {
+                    package: PackageTitle {
+                        name: "podman-test".into(),
+                        version: "0.0.1".into(),
+                    },
+                    approve_privileges: true,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(job.kind, "Install");
+
+        let logs: Vec<crate::db::models::AuditLog> = client
+            .post("/status/log", Pagination::default())
+            .await
+            .unwrap();
+        assert!(logs
+            .iter()
+            .any(|log| log.entry == "Approved elevated privileges"
+                && log.data.contains("podman-test 0.0.1")));
+        assert_eq!(job.package, "podman-test");
+        assert!(!job.is_finished());
+
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+        assert_eq!(job.phase, "Done");
+        assert!(job.log.contains("Installing"));
+
+        // moving back to a version that needs elevated privileges needs approval again
+        let upgrade = |version: &str, approve_privileges| UpgradeRequest {
+            name: "podman-test".into(),
+            version: version.into(),
+            approve_privileges,
+            ..Default::default()
+        };
+        let job = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.2", false))
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+
+        let err = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.1", false))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("elevated privileges must be approved"),
+            "{}",
+            err
+        );
+
+        let job = client
+            .post::<_, Job>("/packages/upgrade", upgrade("0.0.1", true))
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+
+        let statuses = client
+            .get::<Vec<crate::packages::PackageStatus>>("/packages/status")
+            .await
+            .unwrap();
+        let status = statuses
+            .iter()
+            .find(|status| status.package.name == "podman-test")
+            .unwrap();
+        assert_eq!(status.package.version, "0.0.1");
+        assert_eq!(status.source, Some(crate::packages::SourceKind::Container));
+        assert_eq!(
+            status
+                .volumes
+                .iter()
+                .map(|volume| volume.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["private", "shared"]
+        );
+        assert_eq!(status.volumes[0].size, Some(1234567890));
+        // test packages run no services, so systemd has no units for them
+        assert!(!status.running);
+        assert!(status.units.is_empty());
+        assert!(
+            status.error.as_deref().unwrap().contains("no units"),
+            "{:?}",
+            status.error
+        );
+
+        for action in ["stop", "start"] {
+            assert!(client
+                .post::<PackageTitle, Vec<crate::packages::UnitStatus>>(
+                    &format!("/packages/{}", action),
+                    PackageTitle {
+                        name: "podman-test".into(),
+                        version: "0.0.1".into(),
+                    },
+                )
+                .await
+                .is_err());
+        }
+        // only the installed version can be controlled
+        let err = client
+            .post::<PackageTitle, Vec<crate::packages::UnitStatus>>(
+                "/packages/restart",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.2".into(),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::client::Error::NotFound(_)), "{}", err);
+
+        let logs: Vec<crate::db::models::AuditLog> = client
+            .post("/status/log", Pagination::default())
+            .await
+            .unwrap();
+        assert!(logs.iter().any(|log| log.entry == "Stopping package"));
+
+        let export = client
+            .post::<PackageExportRequest, PackageExport>(
+                "/packages/export",
+                PackageExportRequest {
+                    package: PackageTitle {
+                        name: "podman-test".into(),
+                        version: "0.0.1".into(),
+                    },
+                    volumes: false,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(export.file.starts_with("podman-test-0.0.1-"));
+        assert!(export.volumes.is_empty());
+
+        // the archive can only be restored where the package is not installed
+        let err = client
+            .post::<PackageImportRequest, Job>(
+                "/packages/import",
+                PackageImportRequest {
+                    file: export.file.clone(),
+                    approve_privileges: true,
+                },
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("podman-test is already installed"), "{}", err);
+        assert!(client
+            .post::<PackageImportRequest, Job>(
+                "/packages/import",
+                PackageImportRequest {
+                    file: format!("../{}", export.file),
+                    approve_privileges: true,
+                },
+            )
+            .await
+            .is_err());
+
+        // archives move off and onto the server whole, and uploads never overwrite
+        let archive = client.download_archive(&export.file).await.unwrap();
+        assert!(!archive.is_empty());
+        client
+            .upload_archive("podman-test-copy.tar", archive.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            client
+                .download_archive("podman-test-copy.tar")
+                .await
+                .unwrap(),
+            archive
+        );
+        let err = client
+            .upload_archive("podman-test-copy.tar", archive)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("already exists"), "{}", err);
+        assert!(matches!(
+            client.download_archive("missing.tar").await.unwrap_err(),
+            crate::client::Error::NotFound(_)
+        ));
+
+        assert!(
+            client
+                .post::<PackageLogParameters, crate::packages::PackageLog>(
+                    "/packages/log",
+                    PackageLogParameters {
+                        package: PackageTitle {
+                            name: "podman-test".into(),
+                            version: "0.0.1".into(),
+                        },
+                        count: 10,
+                        cursors: Default::default(),
+                        direction: None,
+                    },
+                )
+                .await
+                .unwrap()
+                .entries
+                .len()
+                <= 10
+        );
+        assert!(client
+            .post::<PackageLogParameters, crate::packages::PackageLog>(
+                "/packages/log",
+                PackageLogParameters {
+                    package: PackageTitle {
+                        name: "podman-test".into(),
+                        version: "0.0.2".into(),
+                    },
+                    count: 10,
+                    cursors: Default::default(),
+                    direction: None,
+                },
+            )
+            .await
+            .is_err());
+        assert!(client
+            .post::<_, Job>(&format!("/jobs/{}/cancel", job.id), ())
+            .await
+            .is_err());
+
+        // installing again fails in the background rather than in the request
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/install",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Failed");
+        assert_eq!(
+            job.error,
+            Some("podman-test 0.0.1 is already installed".into())
+        );
+
+        client
+            .post::<PackageTitle, bool>(
+                "/packages/installed",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client
+                .get::<Vec<PackageTitle>>("/packages/list_installed")
+                .await
+                .unwrap(),
+            vec![PackageTitle {
+                name: "podman-test".into(),
+                version: "0.0.1".into()
+            }]
+        );
+
+        let job = client
+            .post::<PackageTitle, Job>(
+                "/packages/uninstall",
+                PackageTitle {
+                    name: "podman-test".into(),
+                    version: "0.0.1".into(),
+                },
+            )
+            .await
+            .unwrap();
+        let job = client.wait_for_job(job.id).await.unwrap();
+        assert_eq!(job.status, "Complete", "{}", job.log);
+        assert!(job.log.contains("Uninstalling"));
+
+        let jobs: Vec<Job> = client.post("/jobs", Pagination::default()).await.unwrap();
+        assert_eq!(
+            jobs.iter().map(|job| job.kind.as_str()).collect::<Vec<_>>(),
+            vec!["Uninstall", "Install", "Upgrade", "Upgrade", "Install"]
+        );
+
+        assert_eq!(
+            client
+                .get::<Vec<PackageTitle>>("/packages/list_installed")
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+}
+
+mod service {
+    use buckle::client::Info;
+
+    use crate::{
+        db::models::User,
+        server::messages::*,
+        testutil::{start_server, TestClient},
+    };
+
+    #[tokio::test]
+    async fn ping() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+        let results = client.get::<PingResult>("/status/ping").await.unwrap();
+        assert!(results.info.is_none());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let results = client.get::<PingResult>("/status/ping").await.unwrap();
+        assert!(results.info.is_some());
+        let info: Info = results.info.unwrap().into();
+        assert_ne!(info.uptime, 0);
+        assert_ne!(info.available_memory, 0);
+        assert_ne!(info.total_memory, 0);
+        assert_ne!(info.cpus, 0);
+        assert_ne!(info.cpu_usage, 0.0);
+        assert!(!info.host_name.is_empty());
+        assert!(!info.kernel_version.is_empty());
+        assert_ne!(info.load_average, [0.0, 0.0, 0.0]);
+        assert_ne!(info.processes, 0);
+    }
+}
+
+mod user {
+    use crate::db::models::User;
+    use crate::server::messages::Authentication;
+    use crate::testutil::{start_server, TestClient};
+
+    #[tokio::test]
+    async fn system_export() {
+        use crate::server::messages::SystemExportRequest;
+        use crate::system::{ImportReport, SystemExport};
+
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        client.put::<User, User>("/users", login).await.unwrap();
+
+        assert!(client
+            .post::<SystemExportRequest, SystemExport>(
+                "/system/export",
+                SystemExportRequest::default()
+            )
+            .await
+            .is_err());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let bare = client
+            .post::<SystemExportRequest, SystemExport>(
+                "/system/export",
+                SystemExportRequest::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(bare.version, crate::system::EXPORT_VERSION);
+        assert_eq!(bare.users.len(), 1);
+        assert_eq!(bare.users[0].user.username, "test-login");
+        assert!(bare.users[0].password_hash.is_none());
+        assert!(!bare.audit_log.is_empty());
+
+        let export = client
+            .post::<SystemExportRequest, SystemExport>(
+                "/system/export",
+                SystemExportRequest { passwords: true },
+            )
+            .await
+            .unwrap();
+        assert!(export.users[0].password_hash.is_some());
+
+        // replay it onto a fresh install, which the imported user can then log in to
+        let addr = start_server(None).await.unwrap();
+        let mut fresh = TestClient::new(addr);
+        let admin = User {
+            username: "test-admin".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        fresh.put::<User, User>("/users", admin).await.unwrap();
+        fresh
+            .login(Authentication {
+                username: "test-admin".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        // users exported without a password are left out rather than locked out
+        let mut bare = bare;
+        bare.audit_log.clear();
+        let report = fresh
+            .post::<SystemExport, ImportReport>("/system/import", bare)
+            .await
+            .unwrap();
+        assert!(report.users.is_empty());
+        assert!(report.conflicts.contains(
+            &"user test-login was exported without a password and was not imported".to_string()
+        ));
+
+        let report = fresh
+            .post::<SystemExport, ImportReport>("/system/import", export.clone())
+            .await
+            .unwrap();
+        assert_eq!(report.users, vec!["test-login"]);
+        assert_eq!(report.audit_log, export.audit_log.len());
+        assert!(report.conflicts.iter().all(|c| !c.contains("test-login")));
+
+        let report = fresh
+            .post::<SystemExport, ImportReport>("/system/import", export.clone())
+            .await
+            .unwrap();
+        assert!(report.users.is_empty());
+        assert!(report
+            .conflicts
+            .contains(&"user test-login already exists".to_string()));
+        assert_eq!(report.audit_log, 0);
+        assert!(report.conflicts.contains(&format!(
+            "{} audit log entries were already imported",
+            export.audit_log.len()
+        )));
+
+        let mut moved = TestClient::new(addr);
+        moved
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let mut newer = export;
+        newer.version = crate::system::EXPORT_VERSION + 1;
+        assert!(fresh
+            .post::<SystemExport, ImportReport>("/system/import", newer)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn audit_log_pages() {
+        use crate::db::models::AuditLog;
+        use crate::server::messages::Pagination;
+
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        for username in ["test-login", "test-other", "test-third"] {
+            let user = User {
+                username: username.into(),
+                plaintext_password: Some("test-password".into()),
+                ..Default::default()
+            };
+            client.put::<User, User>("/users", user).await.unwrap();
+        }
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let all: Vec<AuditLog> = client
+            .post("/status/log", Pagination::default())
+            .await
+            .unwrap();
+        assert!(all.len() >= 3, "{:?}", all);
+
+        // the second page starts after the first page's entries, not after its first entry
+        let page = |page| Pagination {
+            per_page: Some(2),
+            page: Some(page),
+            ..Default::default()
+        };
+        let first: Vec<AuditLog> = client.post("/status/log", page(0)).await.unwrap();
+        let second: Vec<AuditLog> = client.post("/status/log", page(1)).await.unwrap();
+        assert_eq!(first, all[..2]);
+        assert_eq!(second, all[2..all.len().min(4)]);
+    }
+
+    #[tokio::test]
+    async fn login_logout() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        assert!(client.post::<(), Vec<User>>("/users", ()).await.is_err());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        assert!(client.post::<(), Vec<User>>("/users", ()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn first_time_setup() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        let login = User {
+            username: "test-login2".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_err());
+
+        client
+            .login(Authentication {
+                username: "test-login2".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap_err();
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let login = User {
+            username: "test-login2".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
+            .login(Authentication {
+                username: "test-login2".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_session() {
+        use crate::client::Error;
+
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        client.create_user(&login).await.unwrap();
+
+        assert!(client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "wrong-password".into(),
+            })
+            .await
+            .is_err());
+        assert!(client.token().is_none());
+        assert!(matches!(client.me().await, Err(Error::Unauthorized(_))));
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(client.me().await.unwrap().username, "test-login");
+
+        // an endpoint without a body is not read as some default value
+        let me = client.me().await.unwrap();
+        assert!(matches!(
+            client
+                .post::<_, User>(&format!("/user/{}", me.id), &me)
+                .await,
+            Err(Error::Encoding(_))
+        ));
+        client.update_user(me.id, &me).await.unwrap();
+
+        // a refused token is renewed with the credentials from the login
+        client.set_token(Some("expired".into()));
+        assert_eq!(client.me().await.unwrap().username, "test-login");
+        assert_ne!(client.token().as_deref(), Some("expired"));
+
+        client.logout();
+        assert!(matches!(client.me().await, Err(Error::Unauthorized(_))));
+        assert!(matches!(
+            client.get_user(1000).await,
+            Err(Error::Unauthorized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn users_validate() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
+        assert_eq!(list.len(), 1);
+
+        let table: &[User] = &[
+            User {
+                username: "".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikhaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbeaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.meaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
             User {
                 username: "erikh".into(),
                 realname: Some("Erik Hollensbe".into()),
@@ -601,46 +1968,460 @@ mod user {
                 ..Default::default()
             },
             User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlcla".into()),
+                ..Default::default()
+            },
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+        ];
+
+        for (x, item) in table.iter().enumerate() {
+            assert!(
+                client
+                    .put::<User, User>("/users", item.clone())
+                    .await
+                    .is_err(),
+                "#{} succeeded",
+                x
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn users_crud() {
+        let mut client = TestClient::new(start_server(None).await.unwrap());
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
+        assert_eq!(list.len(), 1);
+
+        let table: &[User] = &[
+            User {
+                username: "erikh".into(),
+                realname: Some("Erik Hollensbe".into()),
+                email: Some("erikhollensbe@proton.me".into()),
+                phone: Some("800-867-5309".into()),
+                plaintext_password: Some("horlclax".into()),
+                ..Default::default()
+            },
+            User {
+                username: "scarlett".into(),
+                realname: Some("Scarlett Hollensbe".into()),
+                email: Some("scarlett@hollensbe.org".into()),
+                phone: None,
+                plaintext_password: Some("foobar123".into()),
+                ..Default::default()
+            },
+            User {
+                username: "cmaujean".into(),
+                realname: Some("Christopher Maujean".into()),
+                email: Some("christopher@maujean.org".into()),
+                plaintext_password: Some("pooprocket".into()),
                 ..Default::default()
             },
             User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlcla".into()),
+                username: "day".into(),
+                realname: Some("Day Waterbury".into()),
+                plaintext_password: Some("mmph1234".into()),
                 ..Default::default()
             },
             User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
+                username: "dpnvektor".into(),
+                realname: Some("Julian Sutter".into()),
+                plaintext_password: Some("meh12345".into()),
                 ..Default::default()
             },
         ];
 
-        for (x, item) in table.iter().enumerate() {
-            assert!(
-                client
-                    .put::<User, User>("/users", item.clone())
-                    .await
-                    .is_err(),
-                "#{} succeeded",
-                x
-            )
-        }
+        let mut created = Vec::new();
+
+        for item in table.into_iter() {
+            let user = client
+                .put::<User, User>("/users", item.clone())
+                .await
+                .unwrap();
+            created.push(user);
+        }
+
+        for item in table.into_iter() {
+            assert!(client
+                .put::<User, User>("/users", item.clone())
+                .await
+                .is_err());
+        }
+
+        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
+        assert_eq!(list.len(), table.len() + 1); // add the logged in user
+
+        for item in created.iter() {
+            assert_eq!(
+                client
+                    .get::<User>(&format!("/user/{}", item.id))
+                    .await
+                    .unwrap(),
+                item.clone(),
+            );
+        }
+
+        // update and fetch and compare
+        for mut item in created.clone().into_iter() {
+            item.realname = Some("new realname".into());
+            client
+                .post_empty(&format!("/user/{}", item.id), item.clone())
+                .await
+                .unwrap();
+            assert_eq!(
+                client
+                    .get::<User>(&format!("/user/{}", item.id))
+                    .await
+                    .unwrap(),
+                item.clone(),
+            );
+        }
+
+        for item in created.into_iter() {
+            client
+                .delete_empty(&format!("/user/{}", item.id))
+                .await
+                .unwrap();
+        }
+
+        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
+        assert_eq!(list.len(), table.len() + 1);
+
+        // check that our accounts actually got deleted
+        let mut count = 0;
+        for item in list {
+            if item.deleted_at.is_none() {
+                count += 1;
+            }
+        }
+
+        assert_eq!(count, 1);
+
+        // test deleted user unable to login
+        assert!(client
+            .login(Authentication {
+                username: "erikh".into(),
+                password: "horlclax".into(),
+            })
+            .await
+            .is_err());
+    }
+}
+
+#[cfg(feature = "zfs")]
+mod zfs {
+    use std::collections::HashMap;
+
+    use crate::{
+        db::models::User,
+        server::messages::Authentication,
+        testutil::{start_server, TestClient},
+    };
+    use buckle::client::ZFSStat;
+
+    #[tokio::test]
+    async fn pool_status() {
+        let _ = buckle::testutil::destroy_zpool("pool-status", None);
+        let zpool = buckle::testutil::create_zpool("pool-status").unwrap();
+        let mut client = TestClient::new(
+            start_server(Some("buckle-test-pool-status".into()))
+                .await
+                .unwrap(),
+        );
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        assert!(client
+            .get::<crate::zfs::PoolStatus>("/zfs/pool_status")
+            .await
+            .is_err());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        let status = client
+            .get::<crate::zfs::PoolStatus>("/zfs/pool_status")
+            .await
+            .unwrap();
+        assert_eq!(status.name, "buckle-test-pool-status");
+        assert_eq!(status.state, "ONLINE");
+        assert_eq!(status.vdevs.len(), 1);
+        assert_eq!(status.vdevs[0].name, "buckle-test-pool-status");
+        assert_eq!(status.vdevs[0].checksum_errors, 0);
+
+        let report = client
+            .get::<crate::server::messages::PoolCapacityReport>("/zfs/pool_capacity")
+            .await
+            .unwrap();
+        assert_eq!(report.capacity.name, "buckle-test-pool-status");
+        assert_ne!(report.capacity.size, 0);
+        assert_ne!(report.capacity.free, 0);
+        assert!(report.alerts.is_empty());
+
+        // the first sample is taken when the server starts
+        let history = client
+            .post::<_, Vec<crate::db::models::PoolSample>>(
+                "/zfs/pool_history",
+                crate::server::messages::Pagination::default(),
+            )
+            .await
+            .unwrap();
+        assert!(!history.is_empty());
+        assert_eq!(history[0].pool, "buckle-test-pool-status");
+        assert_eq!(history[0].size as u64, report.capacity.size);
+
+        buckle::testutil::destroy_zpool("pool-status", Some(&zpool)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn quotas() {
+        let _ = buckle::testutil::destroy_zpool("quotas", None);
+        let zpool = buckle::testutil::create_zpool("quotas").unwrap();
+        let mut client = TestClient::new(
+            start_server(Some("buckle-test-quotas".into()))
+                .await
+                .unwrap(),
+        );
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        client
+            .post_empty(
+                "/zfs/create_dataset",
+                buckle::client::Dataset {
+                    name: "dataset".into(),
+                    quota: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let quotas: Vec<crate::zfs::Quota> = client.post("/zfs/quotas", "").await.unwrap();
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0].name, "dataset");
+        assert!(!quotas[0].volume);
+        assert!(quotas[0].quota.is_none());
+        assert!(quotas[0].reservation.is_none());
+        assert_ne!(quotas[0].used, 0);
+
+        let quota: crate::zfs::Quota = client
+            .post(
+                "/zfs/set_quota",
+                crate::server::messages::QuotaRequest {
+                    name: "dataset".into(),
+                    quota: Some("20M".into()),
+                    reservation: Some("5M".into()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(quota.quota, Some(20 * 1024 * 1024));
+        assert_eq!(quota.reservation, Some(5 * 1024 * 1024));
+
+        let err = client
+            .post::<_, crate::zfs::Quota>(
+                "/zfs/set_quota",
+                crate::server::messages::QuotaRequest {
+                    name: "dataset".into(),
+                    quota: Some("100T".into()),
+                    reservation: None,
+                },
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("would overcommit the pool"));
+
+        assert!(client
+            .post::<_, crate::zfs::Quota>(
+                "/zfs/set_quota",
+                crate::server::messages::QuotaRequest {
+                    name: "dataset".into(),
+                    quota: Some("twenty".into()),
+                    reservation: None,
+                },
+            )
+            .await
+            .is_err());
+
+        let quota: crate::zfs::Quota = client
+            .post(
+                "/zfs/set_quota",
+                crate::server::messages::QuotaRequest {
+                    name: "dataset".into(),
+                    quota: Some("none".into()),
+                    reservation: Some("none".into()),
+                },
+            )
+            .await
+            .unwrap();
+        assert!(quota.quota.is_none());
+        assert!(quota.reservation.is_none());
+
+        buckle::testutil::destroy_zpool("quotas", Some(&zpool)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn scrub() {
+        let _ = buckle::testutil::destroy_zpool("scrub", None);
+        let zpool = buckle::testutil::create_zpool("scrub").unwrap();
+        let mut client = TestClient::new(
+            start_server(Some("buckle-test-scrub".into()))
+                .await
+                .unwrap(),
+        );
+
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        assert!(client.post_empty("/zfs/scrub_start", ()).await.is_err());
+
+        client
+            .login(Authentication {
+                username: "test-login".into(),
+                password: "test-password".into(),
+            })
+            .await
+            .unwrap();
+
+        client.post_empty("/zfs/scrub_start", ()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+        let history = client
+            .post::<_, Vec<crate::db::models::ScrubResult>>(
+                "/zfs/scrub_history",
+                crate::server::messages::Pagination::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].pool, "buckle-test-scrub");
+        assert!(!history[0].scheduled);
+        assert!(history[0].finished.is_some());
+        assert_eq!(history[0].state, "Finished");
+        assert_eq!(history[0].errors, Some(0));
+
+        // no scrub is running, so there is nothing to cancel
+        assert!(client.post_empty("/zfs/scrub_cancel", ()).await.is_err());
+
+        assert!(client
+            .get::<Option<crate::db::models::ScrubSchedule>>("/zfs/scrub_schedule")
+            .await
+            .unwrap()
+            .is_none());
+
+        let schedule = client
+            .put::<_, crate::db::models::ScrubSchedule>(
+                "/zfs/scrub_schedule",
+                crate::server::messages::ScrubScheduleRequest {
+                    interval: 30,
+                    next_run: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(schedule.interval, 30);
+        assert!(schedule.next_run > chrono::Local::now());
+
+        assert!(client
+            .put::<_, crate::db::models::ScrubSchedule>(
+                "/zfs/scrub_schedule",
+                crate::server::messages::ScrubScheduleRequest {
+                    interval: 0,
+                    next_run: None,
+                },
+            )
+            .await
+            .is_err());
+
+        assert_eq!(
+            client
+                .get::<Option<crate::db::models::ScrubSchedule>>("/zfs/scrub_schedule")
+                .await
+                .unwrap(),
+            Some(schedule)
+        );
+
+        client.delete_empty("/zfs/scrub_schedule").await.unwrap();
+        assert!(client
+            .get::<Option<crate::db::models::ScrubSchedule>>("/zfs/scrub_schedule")
+            .await
+            .unwrap()
+            .is_none());
+
+        buckle::testutil::destroy_zpool("scrub", Some(&zpool)).unwrap();
     }
 
     #[tokio::test]
-    async fn users_crud() {
-        let mut client = TestClient::new(start_server(None).await.unwrap());
+    async fn backup() {
+        let _ = buckle::testutil::destroy_zpool("backup", None);
+        let zpool = buckle::testutil::create_zpool("backup").unwrap();
+        let mut client = TestClient::new(
+            start_server(Some("buckle-test-backup".into()))
+                .await
+                .unwrap(),
+        );
 
         let login = User {
             username: "test-login".into(),
@@ -657,134 +2438,255 @@ mod user {
             .await
             .unwrap();
 
-        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
-        assert_eq!(list.len(), 1);
+        client
+            .post_empty(
+                "/zfs/create_dataset",
+                buckle::client::Dataset {
+                    name: "dataset".into(),
+                    quota: None,
+                },
+            )
+            .await
+            .unwrap();
 
-        let table: &[User] = &[
-            User {
-                username: "erikh".into(),
-                realname: Some("Erik Hollensbe".into()),
-                email: Some("erikhollensbe@proton.me".into()),
-                phone: Some("800-867-5309".into()),
-                plaintext_password: Some("horlclax".into()),
-                ..Default::default()
-            },
-            User {
-                username: "scarlett".into(),
-                realname: Some("Scarlett Hollensbe".into()),
-                email: Some("scarlett@hollensbe.org".into()),
-                phone: None,
-                plaintext_password: Some("foobar123".into()),
-                ..Default::default()
-            },
-            User {
-                username: "cmaujean".into(),
-                realname: Some("Christopher Maujean".into()),
-                email: Some("christopher@maujean.org".into()),
-                plaintext_password: Some("pooprocket".into()),
-                ..Default::default()
-            },
-            User {
-                username: "day".into(),
-                realname: Some("Day Waterbury".into()),
-                plaintext_password: Some("mmph1234".into()),
-                ..Default::default()
-            },
-            User {
-                username: "dpnvektor".into(),
-                realname: Some("Julian Sutter".into()),
-                plaintext_password: Some("meh12345".into()),
-                ..Default::default()
-            },
-        ];
+        // only datasets in the pool can be backed up
+        assert!(client
+            .post::<_, crate::db::models::Backup>(
+                "/zfs/backup",
+                crate::server::messages::BackupRequest {
+                    dataset: "missing".into(),
+                    incremental: false,
+                },
+            )
+            .await
+            .is_err());
 
-        let mut created = Vec::new();
+        // nothing to base an incremental backup on yet
+        assert!(client
+            .post::<_, crate::db::models::Backup>(
+                "/zfs/backup",
+                crate::server::messages::BackupRequest {
+                    dataset: "dataset".into(),
+                    incremental: true,
+                },
+            )
+            .await
+            .is_err());
 
-        for item in table.into_iter() {
-            let user = client
-                .put::<User, User>("/users", item.clone())
+        let mut ids = Vec::new();
+        for incremental in [false, true] {
+            let backup = client
+                .post::<_, crate::db::models::Backup>(
+                    "/zfs/backup",
+                    crate::server::messages::BackupRequest {
+                        dataset: "dataset".into(),
+                        incremental,
+                    },
+                )
                 .await
                 .unwrap();
-            created.push(user);
+            assert_eq!(backup.status, "Running");
+            assert_eq!(backup.parent_id, ids.last().copied());
+
+            let mut backup = backup;
+            for _ in 0..20 {
+                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+                backup = client
+                    .get(&format!("/zfs/backup/{}", backup.id))
+                    .await
+                    .unwrap();
+                if backup.status != "Running" {
+                    break;
+                }
+            }
+            assert_eq!(backup.status, "Complete");
+            assert!(backup.size > 0);
+            assert!(backup.checksum.is_some());
+            ids.push(backup.id);
         }
 
-        for item in table.into_iter() {
-            assert!(client
-                .put::<User, User>("/users", item.clone())
-                .await
-                .is_err());
-        }
+        let backups: Vec<crate::db::models::Backup> = client
+            .post(
+                "/zfs/backups",
+                crate::server::messages::BackupListRequest {
+                    dataset: Some("dataset".into()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            backups.iter().map(|backup| backup.id).collect::<Vec<_>>(),
+            vec![ids[1], ids[0]]
+        );
 
-        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
-        assert_eq!(list.len(), table.len() + 1); // add the logged in user
+        let verified: Vec<crate::server::messages::BackupVerification> =
+            client.post("/zfs/backup_verify", ids[1]).await.unwrap();
+        assert_eq!(verified.len(), 2);
+        assert!(verified.iter().all(|v| v.valid));
 
-        for item in created.iter() {
-            assert_eq!(
-                client
-                    .get::<User>(&format!("/user/{}", item.id))
-                    .await
-                    .unwrap(),
-                item.clone(),
-            );
-        }
+        client
+            .post_empty(
+                "/zfs/restore",
+                crate::server::messages::RestoreRequest {
+                    id: ids[1],
+                    target: "restored".into(),
+                },
+            )
+            .await
+            .unwrap();
 
-        // update and fetch and compare
-        for mut item in created.clone().into_iter() {
-            item.realname = Some("new realname".into());
-            client
-                .post::<User, ()>(&format!("/user/{}", item.id), item.clone())
-                .await
-                .unwrap();
-            assert_eq!(
-                client
-                    .get::<User>(&format!("/user/{}", item.id))
-                    .await
-                    .unwrap(),
-                item.clone(),
-            );
-        }
+        let result: Vec<ZFSStat> = client.post("/zfs/list", "restored").await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].full_name, "buckle-test-backup/restored");
 
-        for item in created.into_iter() {
-            client
-                .delete::<()>(&format!("/user/{}", item.id))
-                .await
-                .unwrap();
-        }
+        // an existing dataset is never overwritten
+        assert!(client
+            .post_empty(
+                "/zfs/restore",
+                crate::server::messages::RestoreRequest {
+                    id: ids[1],
+                    target: "restored".into(),
+                },
+            )
+            .await
+            .is_err());
+        let result: Vec<ZFSStat> = client.post("/zfs/list", "restored").await.unwrap();
+        assert_eq!(result.len(), 1);
 
-        let list = client.post::<(), Vec<User>>("/users", ()).await.unwrap();
-        assert_eq!(list.len(), table.len() + 1);
+        buckle::testutil::destroy_zpool("backup", Some(&zpool)).unwrap();
+    }
 
-        // check that our accounts actually got deleted
-        let mut count = 0;
-        for item in list {
-            if item.deleted_at.is_none() {
-                count += 1;
-            }
-        }
+    #[tokio::test]
+    async fn encryption() {
+        use crate::{
+            server::messages::{
+                ChangeKeyRequest, EncryptedDatasetRequest, UnlockRequest, ZFSListEntry,
+            },
+            zfs::{EncryptionKey, KeyStatus},
+        };
 
-        assert_eq!(count, 1);
+        let _ = buckle::testutil::destroy_zpool("encryption", None);
+        let zpool = buckle::testutil::create_zpool("encryption").unwrap();
+        let mut client = TestClient::new(
+            start_server(Some("buckle-test-encryption".into()))
+                .await
+                .unwrap(),
+        );
 
-        // test deleted user unable to login
-        assert!(client
+        let login = User {
+            username: "test-login".into(),
+            plaintext_password: Some("test-password".into()),
+            ..Default::default()
+        };
+        assert!(client.put::<User, User>("/users", login).await.is_ok());
+
+        client
             .login(Authentication {
-                username: "erikh".into(),
-                password: "horlclax".into(),
+                username: "test-login".into(),
+                password: "test-password".into(),
             })
             .await
+            .unwrap();
+
+        assert!(client
+            .post_empty(
+                "/zfs/create_encrypted_dataset",
+                EncryptedDatasetRequest {
+                    dataset: buckle::client::Dataset {
+                        name: "secret".into(),
+                        quota: None,
+                    },
+                    key: EncryptionKey::Passphrase("short".into()),
+                },
+            )
+            .await
             .is_err());
-    }
-}
 
-#[cfg(feature = "zfs")]
-mod zfs {
-    use std::collections::HashMap;
+        client
+            .post_empty(
+                "/zfs/create_encrypted_dataset",
+                EncryptedDatasetRequest {
+                    dataset: buckle::client::Dataset {
+                        name: "secret".into(),
+                        quota: None,
+                    },
+                    key: EncryptionKey::Passphrase("first passphrase".into()),
+                },
+            )
+            .await
+            .unwrap();
 
-    use crate::{
-        db::models::User,
-        server::messages::Authentication,
-        testutil::{start_server, TestClient},
-    };
-    use buckle::client::ZFSStat;
+        let key_status = |list: Vec<ZFSListEntry>| list[0].key_status;
+        assert_eq!(
+            key_status(client.post("/zfs/list", "secret").await.unwrap()),
+            KeyStatus::Available
+        );
+
+        client.post_empty("/zfs/lock", "secret").await.unwrap();
+        assert_eq!(
+            key_status(client.post("/zfs/list", "secret").await.unwrap()),
+            KeyStatus::Unavailable
+        );
+
+        let unlock = |passphrase: &str| UnlockRequest {
+            name: "secret".into(),
+            passphrase: Some(passphrase.into()),
+        };
+        assert!(client
+            .post_empty("/zfs/unlock", unlock("wrong passphrase"))
+            .await
+            .is_err());
+        client
+            .post_empty("/zfs/unlock", unlock("first passphrase"))
+            .await
+            .unwrap();
+        assert_eq!(
+            key_status(client.post("/zfs/list", "secret").await.unwrap()),
+            KeyStatus::Available
+        );
+
+        client
+            .post_empty(
+                "/zfs/change_key",
+                ChangeKeyRequest {
+                    name: "secret".into(),
+                    key: EncryptionKey::Passphrase("second passphrase".into()),
+                },
+            )
+            .await
+            .unwrap();
+
+        client.post_empty("/zfs/lock", "secret").await.unwrap();
+        assert!(client
+            .post_empty("/zfs/unlock", unlock("first passphrase"))
+            .await
+            .is_err());
+        client
+            .post_empty("/zfs/unlock", unlock("second passphrase"))
+            .await
+            .unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let logs: Vec<crate::db::models::AuditLog> = client
+            .post(
+                "/status/log",
+                crate::server::messages::Pagination::default(),
+            )
+            .await
+            .unwrap();
+        assert!(logs
+            .iter()
+            .any(|log| log.entry == "Changing encryption key"));
+        assert!(logs
+            .iter()
+            .any(|log| log.entry == "Unlocking dataset" && log.error.is_some()));
+        assert!(!logs
+            .iter()
+            .any(|log| log.data.contains("first") || log.data.contains("second")));
+
+        buckle::testutil::destroy_zpool("encryption", Some(&zpool)).unwrap();
+    }
 
     #[tokio::test]
     async fn zfs_errors() {
@@ -809,7 +2711,7 @@ mod zfs {
             .unwrap();
 
         let res = client
-            .post::<_, ()>(
+            .post_empty(
                 "/zfs/modify_volume",
                 buckle::client::ModifyVolume {
                     name: "volume".into(),
@@ -858,7 +2760,7 @@ mod zfs {
         let result: Vec<ZFSStat> = client.post("/zfs/list", "").await.unwrap();
         assert_eq!(result.len(), 0);
         client
-            .post::<_, ()>(
+            .post_empty(
                 "/zfs/create_dataset",
                 buckle::client::Dataset {
                     name: "dataset".into(),
@@ -880,7 +2782,7 @@ mod zfs {
             Some("/buckle-test-gild/dataset".into())
         );
         client
-            .post::<_, ()>(
+            .post_empty(
                 "/zfs/create_volume",
                 buckle::client::Volume {
                     name: "volume".into(),
@@ -902,7 +2804,7 @@ mod zfs {
         assert_eq!(result[0].mountpoint, None);
 
         client
-            .post::<_, ()>(
+            .post_empty(
                 "/zfs/modify_volume",
                 buckle::client::ModifyVolume {
                     name: "volume".into(),
@@ -940,7 +2842,7 @@ mod zfs {
         );
 
         client
-            .post::<_, ()>(
+            .post_empty(
                 "/zfs/modify_dataset",
                 buckle::client::ModifyDataset {
                     name: "dataset".into(),
@@ -967,17 +2869,77 @@ mod zfs {
         );
 
         client
-            .post::<_, ()>("/zfs/destroy", "dataset2")
+            .post_empty(
+                "/zfs/modify_dataset",
+                crate::server::messages::ModifyDatasetRequest {
+                    dataset: buckle::client::ModifyDataset {
+                        name: "dataset2".into(),
+                        modifications: buckle::client::Dataset {
+                            name: "dataset2".into(),
+                            quota: Some(5 * 1024 * 1024),
+                        },
+                    },
+                    properties: crate::zfs::PropertyChanges {
+                        compression: Some("zstd".into()),
+                        atime: Some(false),
+                        ..Default::default()
+                    },
+                },
+            )
             .await
             .unwrap();
+
+        // volume-only validation rejects dataset properties on volumes
+        assert!(client
+            .post_empty(
+                "/zfs/modify_volume",
+                crate::server::messages::ModifyVolumeRequest {
+                    volume: buckle::client::ModifyVolume {
+                        name: "volume2".into(),
+                        modifications: buckle::client::Volume {
+                            name: "volume2".into(),
+                            size: 100 * 1024 * 1024,
+                        },
+                    },
+                    properties: crate::zfs::PropertyChanges {
+                        recordsize: Some("1M".into()),
+                        ..Default::default()
+                    },
+                },
+            )
+            .await
+            .is_err());
+
+        let properties: Vec<crate::zfs::Property> =
+            client.post("/zfs/properties", "dataset2").await.unwrap();
+        let property = |name: &str| {
+            properties
+                .iter()
+                .find(|property| property.name == name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(property("compression").value, "zstd");
+        assert_eq!(
+            property("compression").source,
+            crate::zfs::PropertySource::Local
+        );
+        assert_eq!(property("atime").value, "off");
+        assert_eq!(
+            property("recordsize").source,
+            crate::zfs::PropertySource::Default
+        );
+        assert!(client
+            .post::<_, Vec<crate::zfs::Property>>("/zfs/properties", "missing")
+            .await
+            .is_err());
+
+        client.post_empty("/zfs/destroy", "dataset2").await.unwrap();
         let result: Vec<ZFSStat> = client.post("/zfs/list", "dataset2").await.unwrap();
         assert_eq!(result.len(), 0);
         let result: Vec<ZFSStat> = client.post("/zfs/list", "").await.unwrap();
         assert_eq!(result.len(), 1);
-        client
-            .post::<_, ()>("/zfs/destroy", "volume2")
-            .await
-            .unwrap();
+        client.post_empty("/zfs/destroy", "volume2").await.unwrap();
         let result: Vec<ZFSStat> = client.post("/zfs/list", "volume2").await.unwrap();
         assert_eq!(result.len(), 0);
 
@@ -0,0 +1,323 @@
+use crate::{
+    config::{Config, PackagesConfig, ZFSConfig},
+    db::{
+        models::{AuditLog, ScrubSchedule, User},
+        DB,
+    },
+};
+use anyhow::{anyhow, Result};
+use charon::{PackageTitle, PromptResponses};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use welds::{exts::VecStateExt, state::DbState, TransactStart};
+
+//
+// Whole-system export and import: everything gild keeps, so a box can be rebuilt from scratch.
+// The document is versioned so older exports can still be read as the format grows.
+//
+
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemExport {
+    pub version: u32,
+    pub exported: chrono::DateTime<chrono::Local>,
+    pub users: Vec<ExportedUser>,
+    pub audit_log: Vec<AuditLog>,
+    pub packages: Vec<ExportedPackage>,
+    pub scrub_schedules: Vec<ScrubSchedule>,
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportedUser {
+    #[serde(flatten)]
+    pub user: User,
+    // only exported when asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedPackage {
+    pub package: PackageTitle,
+    pub responses: PromptResponses,
+}
+
+/// Configuration at the time of the export. It is file-based, so it is compared rather than
+/// imported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    pub zfs: ZFSConfig,
+    pub packages: PackagesConfig,
+}
+
+/// What an import did, and what it left alone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub users: Vec<String>,
+    pub audit_log: usize,
+    pub scrub_schedules: Vec<String>,
+    // packages whose responses were restored; they still need to be installed
+    pub packages: Vec<PackageTitle>,
+    pub conflicts: Vec<String>,
+}
+
+pub(crate) async fn export(
+    db: &DB,
+    charon: &charon::Client,
+    config: &Config,
+    passwords: bool,
+) -> Result<SystemExport> {
+    let users = User::all()
+        .run(db.handle())
+        .await?
+        .into_inners()
+        .into_iter()
+        .map(|user| ExportedUser {
+            password_hash: passwords.then(|| user.password.clone()),
+            user,
+        })
+        .collect();
+
+    let mut packages = Vec::new();
+    for package in charon.query().await?.list_installed().await? {
+        // installed packages were configured on the way in, so missing responses are an error
+        let responses = charon
+            .query()
+            .await?
+            .get_responses(&package.name)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "could not read the responses of {} {}: {}",
+                    package.name,
+                    package.version,
+                    e
+                )
+            })?;
+        packages.push(ExportedPackage { responses, package });
+    }
+
+    Ok(SystemExport {
+        version: EXPORT_VERSION,
+        exported: chrono::Local::now(),
+        users,
+        audit_log: AuditLog::all()
+            .order_by_asc(|c| c.id)
+            .run(db.handle())
+            .await?
+            .into_inners(),
+        packages,
+        scrub_schedules: ScrubSchedule::all().run(db.handle()).await?.into_inners(),
+        settings: Settings {
+            zfs: config.zfs.clone(),
+            packages: config.packages.clone(),
+        },
+    })
+}
+
+/// Replays an export, skipping and reporting anything that already exists. The database changes
+/// are made in one transaction; package responses are only restored once it has committed.
+pub(crate) async fn import(
+    db: &DB,
+    charon: &charon::Client,
+    config: &Config,
+    export: SystemExport,
+) -> Result<ImportReport> {
+    if export.version > EXPORT_VERSION {
+        return Err(anyhow!(
+            "export version {} is newer than the supported version {}",
+            export.version,
+            EXPORT_VERSION
+        ));
+    }
+
+    let mut report = ImportReport::default();
+    let transaction = db.handle().begin().await?;
+
+    // audit log entries refer to users by id, which change on the way in
+    let existing: HashMap<String, u32> = User::all()
+        .run(&transaction)
+        .await?
+        .into_inners()
+        .into_iter()
+        .map(|user| (user.username, user.id))
+        .collect();
+    let mut ids = HashMap::new();
+
+    for exported in export.users {
+        let old = exported.user.id;
+
+        if let Some(id) = existing.get(&exported.user.username) {
+            report
+                .conflicts
+                .push(format!("user {} already exists", exported.user.username));
+            ids.insert(old, *id);
+            continue;
+        }
+
+        // an account without a password could never log in, and nothing would say so
+        let Some(hash) = exported.password_hash else {
+            report.conflicts.push(format!(
+                "user {} was exported without a password and was not imported",
+                exported.user.username
+            ));
+            continue;
+        };
+
+        let mut user = DbState::new_uncreated(User {
+            id: Default::default(),
+            password: hash,
+            ..exported.user
+        });
+        user.save(&transaction).await?;
+
+        ids.insert(old, user.id);
+        report.users.push(user.username.clone());
+    }
+
+    // entries already here came from an earlier import of the same log
+    let logged: HashSet<(chrono::DateTime<chrono::Local>, String, String)> = AuditLog::all()
+        .run(&transaction)
+        .await?
+        .into_inners()
+        .into_iter()
+        .map(|entry| (entry.time, entry.entry, entry.endpoint))
+        .collect();
+    let mut duplicates = 0;
+
+    for entry in export.audit_log {
+        if logged.contains(&(entry.time, entry.entry.clone(), entry.endpoint.clone())) {
+            duplicates += 1;
+            continue;
+        }
+
+        DbState::new_uncreated(AuditLog {
+            id: Default::default(),
+            user_id: entry.user_id.and_then(|id| ids.get(&id).copied()),
+            ..entry
+        })
+        .save(&transaction)
+        .await?;
+        report.audit_log += 1;
+    }
+
+    if duplicates > 0 {
+        report.conflicts.push(format!(
+            "{} audit log entries were already imported",
+            duplicates
+        ));
+    }
+
+    for schedule in export.scrub_schedules {
+        let scheduled = ScrubSchedule::all()
+            .where_col(|c| c.pool.equal(schedule.pool.as_str()))
+            .count(&transaction)
+            .await?
+            > 0;
+        if scheduled {
+            report.conflicts.push(format!(
+                "a scrub schedule for pool {} already exists",
+                schedule.pool
+            ));
+            continue;
+        }
+
+        let pool = schedule.pool.clone();
+        DbState::new_uncreated(ScrubSchedule {
+            id: Default::default(),
+            ..schedule
+        })
+        .save(&transaction)
+        .await?;
+        report.scrub_schedules.push(pool);
+    }
+
+    transaction.commit().await?;
+
+    if serde_json::to_value(&export.settings.zfs)? != serde_json::to_value(&config.zfs)? {
+        report
+            .conflicts
+            .push("zfs settings differ from this system's configuration".into());
+    }
+    if serde_json::to_value(&export.settings.packages)? != serde_json::to_value(&config.packages)? {
+        report
+            .conflicts
+            .push("package settings differ from this system's configuration".into());
+    }
+
+    let installed = charon.query().await?.list_installed().await?;
+    for exported in export.packages {
+        let package = exported.package;
+
+        if let Some(current) = installed.iter().find(|title| title.name == package.name) {
+            if current.version != package.version {
+                report.conflicts.push(format!(
+                    "{} {} is installed, but the export has {}",
+                    current.name, current.version, package.version
+                ));
+            }
+            continue;
+        }
+
+        charon
+            .query()
+            .await?
+            .set_responses(&package.name, exported.responses)
+            .await?;
+        report.packages.push(package);
+    }
+
+    Ok(report)
+}
+
+/// Writes an export of the system described by `config` to `file`, as JSON.
+pub async fn export_file(config: &Config, file: &Path, passwords: bool) -> Result<()> {
+    let export = export(
+        &config.get_db().await?,
+        &config.charon()?,
+        config,
+        passwords,
+    )
+    .await?;
+    tokio::fs::write(file, serde_json::to_vec_pretty(&export)?).await?;
+    Ok(())
+}
+
+/// Imports a JSON export from `file` into the system described by `config`.
+pub async fn import_file(config: &Config, file: &Path) -> Result<ImportReport> {
+    let export = serde_json::from_slice(&tokio::fs::read(file).await?)?;
+    import(&config.get_db().await?, &config.charon()?, config, export).await
+}
+
+/// Creates an account for the system described by `config`. Every account has full access, so
+/// this is how the first administrator is made without the web interface.
+pub async fn create_admin(config: &Config, username: &str, password: &str) -> Result<()> {
+    use validator::Validate;
+
+    let db = config.get_db().await?;
+    let exists = User::all()
+        .where_col(|c| c.username.equal(username))
+        .where_col(|c| c.deleted_at.equal(None))
+        .count(db.handle())
+        .await?
+        > 0;
+    if exists {
+        return Err(anyhow!("user {} already exists", username));
+    }
+
+    let mut user = DbState::new_uncreated(User {
+        username: username.into(),
+        plaintext_password: Some(password.into()),
+        ..Default::default()
+    });
+    user.validate()?;
+    user.set_password(password.into())?;
+    user.plaintext_password = None;
+    user.save(db.handle()).await?;
+    Ok(())
+}
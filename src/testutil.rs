@@ -1,17 +1,16 @@
 use crate::{
+    client::Client,
     config::{Config, SocketConfig},
-    server::{messages::*, Server},
+    server::Server,
 };
 use anyhow::{anyhow, Result};
 use buckle::{config::ZFSConfig, testutil::make_server};
 use rand::Fill;
-use reqwest::Client;
-use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
-use serde::{
-    de::{Deserialize, DeserializeOwned},
-    Serialize,
+use std::{
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    path::PathBuf,
 };
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 use tempfile::NamedTempFile;
 
 pub async fn find_listener() -> Result<SocketAddr> {
@@ -35,6 +34,14 @@ pub async fn make_config(addr: Option<SocketAddr>, poolname: Option<String>) ->
     key.fill(&mut rand::rng());
     salt.fill(&mut rand::rng());
 
+    let mut zfs = crate::config::ZFSConfig {
+        backups: PathBuf::from("tmp").join(format!("backups-{}", rand::random::<u32>())),
+        ..Default::default()
+    };
+    if let Some(poolname) = &poolname {
+        zfs.pool = poolname.clone();
+    }
+
     Ok(Config {
         listen: if let Some(addr) = addr {
             addr
@@ -54,6 +61,14 @@ pub async fn make_config(addr: Option<SocketAddr>, poolname: Option<String>) ->
             .await?,
             charon: start_charon("testdata/charon".into()).await?,
         },
+        zfs,
+        packages: crate::config::PackagesConfig {
+            registry: "testdata/charon".into(),
+            sideload: PathBuf::from("tmp").join(format!("sideload-{}", rand::random::<u32>())),
+            // test packages do not run real services
+            start_timeout: 0,
+            ..Default::default()
+        },
 
         db: dbfile,
         signing_key: key.to_vec(),
@@ -63,10 +78,19 @@ pub async fn make_config(addr: Option<SocketAddr>, poolname: Option<String>) ->
 }
 
 pub async fn start_server(poolname: Option<String>) -> Result<SocketAddr> {
+    start_server_with(poolname, |_| {}).await
+}
+
+/// Starts a server with the test configuration, adjusted by `configure` first.
+pub async fn start_server_with(
+    poolname: Option<String>,
+    configure: impl FnOnce(&mut Config),
+) -> Result<SocketAddr> {
     let addr = find_listener().await?;
     let ret = addr.clone();
     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-    let config = make_config(Some(addr), poolname).await.unwrap();
+    let mut config = make_config(Some(addr), poolname).await.unwrap();
+    configure(&mut config);
     let call = async move {
         Server::new(config).await.unwrap().start().await.unwrap();
     };
@@ -99,156 +123,39 @@ pub async fn start_charon(registry: PathBuf) -> Result<PathBuf> {
     Ok(path)
 }
 
-pub struct TestClient {
-    client: Client,
-    baseurl: String,
-    token: Option<String>,
-}
+/// The API client, with the shorter job timeout the tests want.
+pub struct TestClient(Client);
 
 impl TestClient {
     pub fn new(addr: SocketAddr) -> Self {
-        let store = Arc::new(CookieStoreMutex::new(CookieStore::default()));
-        Self {
-            client: Client::builder().cookie_provider(store).build().unwrap(),
-            baseurl: format!("http://{}", addr),
-            token: None,
-        }
-    }
-
-    pub async fn login(&mut self, input: Authentication) -> Result<()> {
-        self.token = Default::default();
-        let response = self
-            .post::<Authentication, Token>("/session/login", input)
-            .await?;
-        self.token = Some(response.token);
-        Ok(())
-    }
-
-    pub async fn get<T>(&self, path: &str) -> Result<T>
-    where
-        T: for<'de> Deserialize<'de> + DeserializeOwned + Default,
-    {
-        let mut req = self.client.get(&format!("{}{}", self.baseurl, path));
-
-        if let Some(token) = &self.token {
-            req = req.header("Authorization", &format!("Bearer {}", token))
-        }
-
-        let resp = req.send().await?;
-
-        if resp.status() != 200 {
-            return Err(anyhow!(
-                "{}",
-                String::from_utf8(resp.bytes().await?.to_vec())?
-            ));
-        }
-
-        let byt = resp.bytes().await?;
-
-        if byt.len() > 0 {
-            Ok(ciborium::from_reader(std::io::Cursor::new(byt))?)
-        } else {
-            Ok(T::default())
-        }
+        Self(Client::new(format!("http://{}", addr)))
     }
 
-    pub async fn delete<T>(&self, path: &str) -> Result<T>
-    where
-        T: for<'de> Deserialize<'de> + DeserializeOwned + Default,
-    {
-        let mut req = self.client.delete(&format!("{}{}", self.baseurl, path));
-
-        if let Some(token) = &self.token {
-            req = req.header("Authorization", &format!("Bearer {}", token))
-        }
-
-        let resp = req.send().await?;
+    /// Polls a background job until it finishes.
+    pub async fn wait_for_job(&self, id: u32) -> Result<crate::db::models::Job> {
+        for _ in 0..600 {
+            let job = self.get_job(id).await?;
+            if job.is_finished() {
+                return Ok(job);
+            }
 
-        if resp.status() != 200 {
-            return Err(anyhow!(
-                "{}",
-                String::from_utf8(resp.bytes().await?.to_vec())?
-            ));
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         }
 
-        let byt = resp.bytes().await?;
-
-        if byt.len() > 0 {
-            Ok(ciborium::from_reader(std::io::Cursor::new(byt))?)
-        } else {
-            Ok(T::default())
-        }
+        Err(anyhow!("job {} did not finish", id))
     }
+}
 
-    pub async fn post<I, O>(&self, path: &str, input: I) -> Result<O>
-    where
-        I: Serialize,
-        O: for<'de> Deserialize<'de> + DeserializeOwned + Default,
-    {
-        let mut inner = Vec::with_capacity(65535);
-        let mut buf = std::io::Cursor::new(&mut inner);
-        ciborium::into_writer(&input, &mut buf)?;
-
-        let mut req = self
-            .client
-            .post(&format!("{}{}", self.baseurl, path))
-            .header("Content-type", "application/cbor");
-
-        if let Some(token) = &self.token {
-            req = req.header("Authorization", &format!("Bearer {}", token))
-        }
-
-        let resp = req.body(buf.into_inner().to_vec()).send().await?;
-
-        if resp.status() != 200 {
-            return Err(anyhow!(
-                "{}",
-                String::from_utf8(resp.bytes().await?.to_vec())?
-            ));
-        }
-
-        let byt = resp.bytes().await?;
+impl Deref for TestClient {
+    type Target = Client;
 
-        if byt.len() > 0 {
-            Ok(ciborium::from_reader(std::io::Cursor::new(byt))?)
-        } else {
-            Ok(O::default())
-        }
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
+}
 
-    pub async fn put<I, O>(&self, path: &str, input: I) -> Result<O>
-    where
-        I: Serialize,
-        O: for<'de> Deserialize<'de> + DeserializeOwned + Default,
-    {
-        let mut inner = Vec::with_capacity(65535);
-        let mut buf = std::io::Cursor::new(&mut inner);
-        ciborium::into_writer(&input, &mut buf)?;
-
-        let mut req = self
-            .client
-            .put(&format!("{}{}", self.baseurl, path))
-            .header("Content-type", "application/cbor");
-
-        if let Some(token) = &self.token {
-            req = req.header("Authorization", &format!("Bearer {}", token))
-        }
-
-        let resp = req.body(buf.into_inner().to_vec()).send().await?;
-
-        if resp.status() != 200 {
-            return Err(anyhow!(
-                "{}",
-                String::from_utf8(resp.bytes().await?.to_vec())?
-            ));
-        }
-
-        let byt = resp.bytes().await?;
-
-        if byt.len() > 0 {
-            Ok(ciborium::from_reader(std::io::Cursor::new(byt))?)
-        } else {
-            Ok(O::default())
-        }
+impl DerefMut for TestClient {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
     }
 }
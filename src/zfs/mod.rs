@@ -0,0 +1,1105 @@
+#[cfg(test)]
+mod tests;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+//
+// buckle only exposes dataset and volume operations. Anything pool-level is read from the zpool
+// tooling on the host directly.
+//
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PoolCapacity {
+    pub name: String,
+    pub size: u64,
+    pub allocated: u64,
+    pub free: u64,
+    // percentage of the pool in use
+    pub capacity: u8,
+    pub health: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PoolStatus {
+    pub name: String,
+    pub state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan: Option<ScanStatus>,
+    pub vdevs: Vec<VDev>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VDev {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    pub read_errors: u64,
+    pub write_errors: u64,
+    pub checksum_errors: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub children: Vec<VDev>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanState {
+    #[default]
+    None,
+    InProgress,
+    Paused,
+    Finished,
+    Canceled,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanStatus {
+    // "scrub" or "resilver"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    pub state: ScanState,
+    // percentage complete, only available while in progress
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    // in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repaired: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<u64>,
+    // in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    pub name: String,
+    pub full_name: String,
+    pub volume: bool,
+    pub used: u64,
+    pub available: u64,
+    // volumes do not have quotas
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservation: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PropertySource {
+    Local,
+    Default,
+    // the dataset the value is inherited from
+    Inherited(String),
+    Temporary,
+    Received,
+    // read-only properties such as used or creation
+    #[default]
+    None,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub source: PropertySource,
+}
+
+/// The properties gild allows changing on an existing dataset or volume.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PropertyChanges {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    // datasets only, a power of two such as "128K"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recordsize: Option<String>,
+    // datasets only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub atime: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync: Option<String>,
+}
+
+impl PropertyChanges {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn validate(&self, volume: bool) -> Result<()> {
+        if let Some(compression) = &self.compression {
+            let level = |prefix: &str, max: u8| {
+                compression
+                    .strip_prefix(prefix)
+                    .and_then(|level| level.parse::<u8>().ok())
+                    .is_some_and(|level| (1..=max).contains(&level))
+            };
+
+            if !matches!(
+                compression.as_str(),
+                "on" | "off" | "lz4" | "lzjb" | "zle" | "gzip" | "zstd"
+            ) && !level("gzip-", 9)
+                && !level("zstd-", 19)
+            {
+                return Err(anyhow!("invalid compression: {}", compression));
+            }
+        }
+
+        if let Some(sync) = self
+            .sync
+            .as_ref()
+            .filter(|sync| !matches!(sync.as_str(), "standard" | "always" | "disabled"))
+        {
+            return Err(anyhow!("invalid sync: {}", sync));
+        }
+
+        if volume && (self.recordsize.is_some() || self.atime.is_some()) {
+            return Err(anyhow!("recordsize and atime do not apply to volumes"));
+        }
+
+        if let Some(recordsize) = &self.recordsize {
+            let size = parse_size(recordsize)?;
+            if !size.is_power_of_two() || !(512..=16 * 1024 * 1024).contains(&size) {
+                return Err(anyhow!(
+                    "recordsize must be a power of two between 512 and 16M"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn args(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+
+        if let Some(compression) = &self.compression {
+            args.push(format!("compression={}", compression));
+        }
+
+        if let Some(recordsize) = &self.recordsize {
+            args.push(format!("recordsize={}", parse_size(recordsize)?));
+        }
+
+        if let Some(atime) = self.atime {
+            args.push(format!("atime={}", if atime { "on" } else { "off" }));
+        }
+
+        if let Some(sync) = &self.sync {
+            args.push(format!("sync={}", sync));
+        }
+
+        Ok(args)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyStatus {
+    // not encrypted
+    #[default]
+    None,
+    Available,
+    Unavailable,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EncryptionKey {
+    Passphrase(String),
+    // absolute path to a file holding a raw 32 byte key
+    Keyfile(PathBuf),
+}
+
+// keep passphrases out of logs
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+            Self::Keyfile(path) => f.debug_tuple("Keyfile").field(path).finish(),
+        }
+    }
+}
+
+impl EncryptionKey {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Self::Passphrase(passphrase) => {
+                if !(8..=512).contains(&passphrase.len()) {
+                    return Err(anyhow!("passphrase must be between 8 and 512 bytes"));
+                }
+            }
+            Self::Keyfile(path) => {
+                if !path.is_absolute() {
+                    return Err(anyhow!("keyfile must be an absolute path"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The keyformat, which is all of the key that is safe to record.
+    pub fn format(&self) -> &'static str {
+        match self {
+            Self::Passphrase(_) => "passphrase",
+            Self::Keyfile(_) => "raw",
+        }
+    }
+
+    fn options(&self) -> Vec<String> {
+        let location = match self {
+            Self::Passphrase(_) => "prompt".to_string(),
+            Self::Keyfile(path) => format!("file://{}", path.display()),
+        };
+
+        vec![
+            "-o".into(),
+            format!("keyformat={}", self.format()),
+            "-o".into(),
+            format!("keylocation={}", location),
+        ]
+    }
+
+    fn input(&self) -> &str {
+        match self {
+            Self::Passphrase(passphrase) => passphrase,
+            Self::Keyfile(_) => "",
+        }
+    }
+}
+
+//
+// Creating, modifying and destroying datasets and volumes, and quotas, go through buckle. The
+// pool runs what buckle's zfs client has no calls for yet: pool status and capacity, scrubs,
+// reservations, properties, encryption keys, snapshots and send/receive. Each of these needs a
+// buckle call before gild can run without zfs privileges; until then they are kept here, in one
+// place, rather than spread through the handlers.
+//
+
+#[derive(Debug, Clone)]
+pub struct Pool {
+    name: String,
+}
+
+impl Pool {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) async fn run(&self, command: &str, args: &[&str]) -> Result<String> {
+        let output = tokio::process::Command::new(command)
+            .args(args)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    /// Like `run`, but feeds `input` to the command, which is how zfs takes passphrases.
+    async fn run_with_input(&self, command: &str, args: &[String], input: &str) -> Result<String> {
+        let mut child = tokio::process::Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or(anyhow!("could not write {} input", command))?;
+        stdin.write_all(input.as_bytes()).await?;
+        drop(stdin);
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    pub async fn status(&self) -> Result<PoolStatus> {
+        parse_status(&self.run("zpool", &["status", "-p", &self.name]).await?)
+    }
+
+    pub async fn scrub(&self) -> Result<()> {
+        self.run("zpool", &["scrub", &self.name]).await?;
+        Ok(())
+    }
+
+    pub async fn pause_scrub(&self) -> Result<()> {
+        self.run("zpool", &["scrub", "-p", &self.name]).await?;
+        Ok(())
+    }
+
+    pub async fn cancel_scrub(&self) -> Result<()> {
+        self.run("zpool", &["scrub", "-s", &self.name]).await?;
+        Ok(())
+    }
+
+    /// Free space in the pool, as seen by a dataset without a quota.
+    pub async fn available(&self) -> Result<u64> {
+        Ok(self
+            .run(
+                "zfs",
+                &["get", "-Hp", "-o", "value", "available", &self.name],
+            )
+            .await?
+            .trim()
+            .parse()?)
+    }
+
+    pub async fn quotas(&self, filter: Option<&str>) -> Result<Vec<Quota>> {
+        let quotas = parse_quotas(
+            &self.name,
+            &self
+                .run(
+                    "zfs",
+                    &[
+                        "get",
+                        "-Hp",
+                        "-r",
+                        "-t",
+                        "filesystem,volume",
+                        "-o",
+                        "name,property,value",
+                        "type,used,available,quota,reservation",
+                        &self.name,
+                    ],
+                )
+                .await?,
+        )?;
+
+        Ok(match filter {
+            Some(filter) => quotas
+                .into_iter()
+                .filter(|quota| quota.name.starts_with(filter))
+                .collect(),
+            None => quotas,
+        })
+    }
+
+    pub async fn set_reservation(&self, name: &str, reservation: u64) -> Result<()> {
+        self.run(
+            "zfs",
+            &[
+                "set",
+                &format!("reservation={}", reservation),
+                &format!("{}/{}", self.name, name),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Every property of the dataset or volume, with where its value comes from.
+    pub async fn properties(&self, name: &str) -> Result<Vec<Property>> {
+        parse_properties(
+            &self
+                .run(
+                    "zfs",
+                    &[
+                        "get",
+                        "-Hp",
+                        "-o",
+                        "property,value,source",
+                        "all",
+                        &format!("{}/{}", self.name, name),
+                    ],
+                )
+                .await?,
+        )
+    }
+
+    pub async fn set_properties(&self, name: &str, changes: &PropertyChanges) -> Result<()> {
+        let mut args = vec!["set".to_string()];
+        args.append(&mut changes.args()?);
+        args.push(format!("{}/{}", self.name, name));
+
+        self.run("zfs", &args.iter().map(String::as_str).collect::<Vec<_>>())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn create_encrypted(
+        &self,
+        name: &str,
+        quota: Option<u64>,
+        key: &EncryptionKey,
+    ) -> Result<()> {
+        let mut args = vec!["create".to_string(), "-o".into(), "encryption=on".into()];
+        args.append(&mut key.options());
+        if let Some(quota) = quota {
+            args.push("-o".into());
+            args.push(format!("quota={}", quota));
+        }
+        args.push(format!("{}/{}", self.name, name));
+
+        self.run_with_input("zfs", &args, key.input()).await?;
+        Ok(())
+    }
+
+    /// Loads the key and mounts the dataset. Without a passphrase the key is read from its
+    /// keylocation.
+    pub async fn unlock(&self, name: &str, passphrase: Option<&str>) -> Result<()> {
+        let full_name = format!("{}/{}", self.name, name);
+        self.run_with_input(
+            "zfs",
+            &["load-key".into(), full_name.clone()],
+            passphrase.unwrap_or_default(),
+        )
+        .await?;
+
+        self.mount(name).await
+    }
+
+    /// Unmounts the dataset and unloads its key.
+    pub async fn lock(&self, name: &str) -> Result<()> {
+        let full_name = format!("{}/{}", self.name, name);
+
+        if self.is_filesystem(&full_name).await? {
+            self.run("zfs", &["unmount", &full_name]).await?;
+        }
+
+        self.run("zfs", &["unload-key", &full_name]).await?;
+        Ok(())
+    }
+
+    /// Replaces the wrapping key. The current key must be loaded.
+    pub async fn change_key(&self, name: &str, key: &EncryptionKey) -> Result<()> {
+        let mut args = vec!["change-key".to_string()];
+        args.append(&mut key.options());
+        args.push(format!("{}/{}", self.name, name));
+
+        self.run_with_input("zfs", &args, key.input()).await?;
+        Ok(())
+    }
+
+    /// Key status of every dataset and volume, by full name.
+    pub async fn key_status(&self) -> Result<HashMap<String, KeyStatus>> {
+        parse_key_status(
+            &self
+                .run(
+                    "zfs",
+                    &[
+                        "get",
+                        "-Hp",
+                        "-r",
+                        "-t",
+                        "filesystem,volume",
+                        "-o",
+                        "name,value",
+                        "keystatus",
+                        &self.name,
+                    ],
+                )
+                .await?,
+        )
+    }
+
+    async fn is_filesystem(&self, full_name: &str) -> Result<bool> {
+        Ok(self
+            .run("zfs", &["get", "-H", "-o", "value", "type", full_name])
+            .await?
+            .trim()
+            == "filesystem")
+    }
+
+    pub async fn snapshot(&self, name: &str, snapshot: &str) -> Result<()> {
+        self.run(
+            "zfs",
+            &["snapshot", &format!("{}/{}@{}", self.name, name, snapshot)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn destroy_snapshot(&self, name: &str, snapshot: &str) -> Result<()> {
+        self.run(
+            "zfs",
+            &["destroy", &format!("{}/{}@{}", self.name, name, snapshot)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Streams `zfs send` of the snapshot into the file, incrementally from `from` when given.
+    /// Returns the size and sha256 of the stream.
+    pub async fn send(
+        &self,
+        name: &str,
+        snapshot: &str,
+        from: Option<&str>,
+        file: &Path,
+    ) -> Result<(u64, String)> {
+        let mut args = vec!["send".to_string()];
+        if let Some(from) = from {
+            args.push("-i".into());
+            args.push(format!("@{}", from));
+        }
+        args.push(format!("{}/{}@{}", self.name, name, snapshot));
+
+        let mut child = tokio::process::Command::new("zfs")
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let errors = drain_stderr(&mut child)?;
+        let mut stdout = child
+            .stdout
+            .take()
+            .ok_or(anyhow!("could not read zfs send output"))?;
+        let mut out = tokio::fs::File::create(file).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut buf = vec![0u8; 65536];
+
+        loop {
+            let len = stdout.read(&mut buf).await?;
+            if len == 0 {
+                break;
+            }
+
+            hasher.update(&buf[..len]);
+            out.write_all(&buf[..len]).await?;
+            size += len as u64;
+        }
+        out.flush().await?;
+
+        finish_streaming(child, errors).await?;
+        Ok((size, format!("{:x}", hasher.finalize())))
+    }
+
+    /// Feeds a stream written by `send` into `zfs receive`. The target is never replaced: a full
+    /// stream needs it to be absent, and an incremental one needs it unchanged since the stream
+    /// before. It is left unmounted so later streams in a chain still apply; see `mount`.
+    pub async fn receive(&self, name: &str, file: &Path) -> Result<()> {
+        let mut child = tokio::process::Command::new("zfs")
+            .args(["receive", "-u", &format!("{}/{}", self.name, name)])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let errors = drain_stderr(&mut child)?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or(anyhow!("could not write zfs receive input"))?;
+        let mut input = tokio::fs::File::open(file).await?;
+        tokio::io::copy(&mut input, &mut stdin).await?;
+        drop(stdin);
+
+        finish_streaming(child, errors).await
+    }
+
+    /// Mounts a received dataset. Volumes have nothing to mount.
+    pub async fn mount(&self, name: &str) -> Result<()> {
+        let full_name = format!("{}/{}", self.name, name);
+        if self.is_filesystem(&full_name).await? {
+            self.run("zfs", &["mount", &full_name]).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn capacity(&self) -> Result<PoolCapacity> {
+        parse_capacity(
+            &self
+                .run(
+                    "zpool",
+                    &[
+                        "list",
+                        "-Hp",
+                        "-o",
+                        "name,size,allocated,free,capacity,health",
+                        &self.name,
+                    ],
+                )
+                .await?,
+        )
+    }
+}
+
+/// Reads a streaming command's stderr alongside its data, so a chatty command cannot fill the
+/// pipe and stall.
+fn drain_stderr(
+    child: &mut tokio::process::Child,
+) -> Result<tokio::task::JoinHandle<std::io::Result<String>>> {
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or(anyhow!("could not read zfs errors"))?;
+
+    Ok(tokio::spawn(async move {
+        let mut errors = String::new();
+        stderr.read_to_string(&mut errors).await?;
+        Ok(errors)
+    }))
+}
+
+async fn finish_streaming(
+    mut child: tokio::process::Child,
+    errors: tokio::task::JoinHandle<std::io::Result<String>>,
+) -> Result<()> {
+    let status = child.wait().await?;
+    let errors = errors.await??;
+
+    if !status.success() {
+        return Err(anyhow!("{}", errors.trim()));
+    }
+
+    Ok(())
+}
+
+/// Parses sizes in the format zfs prints them (e.g. "20G", "1.5T", "512"). Units are powers of
+/// 1024; a trailing "B" or "iB" is accepted.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let idx = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(idx);
+    let number: f64 = number.parse().map_err(|_| anyhow!("invalid size: {}", s))?;
+
+    let unit = unit.trim().to_ascii_uppercase();
+    let multiplier: u64 = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        "P" => 1 << 50,
+        "E" => 1 << 60,
+        _ => return Err(anyhow!("invalid size unit: {}", s)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Formats a byte count the way zfs does, e.g. "20G" or "1.5T".
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut value = size as f64;
+    let mut unit = "";
+    for u in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = u;
+    }
+
+    let formatted = format!("{:.2}", value);
+    format!(
+        "{}{}",
+        formatted.trim_end_matches('0').trim_end_matches('.'),
+        unit
+    )
+}
+
+/// sha256 of a stream written by `Pool::send`, for verifying it before a restore.
+pub async fn checksum(file: &Path) -> Result<String> {
+    let mut input = tokio::fs::File::open(file).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 65536];
+
+    loop {
+        let len = input.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..len]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Checks a requested quota and reservation against what the pool can still provide. `available`
+/// is the free space of the pool.
+pub fn check_overcommit(
+    current: &Quota,
+    available: u64,
+    quota: Option<u64>,
+    reservation: Option<u64>,
+) -> Result<()> {
+    // zero removes the quota or reservation
+    if let Some(quota) = quota.filter(|q| *q != 0) {
+        if quota < current.used {
+            return Err(anyhow!(
+                "quota of {} for {} is smaller than the {} it already uses",
+                format_size(quota),
+                current.name,
+                format_size(current.used),
+            ));
+        }
+
+        if quota > current.used + available {
+            return Err(anyhow!(
+                "quota of {} for {} would overcommit the pool: it uses {} and only {} is free",
+                format_size(quota),
+                current.name,
+                format_size(current.used),
+                format_size(available),
+            ));
+        }
+    }
+
+    if let Some(reservation) = reservation {
+        let additional = reservation.saturating_sub(current.reservation.unwrap_or_default());
+        if additional > available {
+            return Err(anyhow!(
+                "reservation of {} for {} would overcommit the pool: it needs {} more and only {} is free",
+                format_size(reservation),
+                current.name,
+                format_size(additional),
+                format_size(available),
+            ));
+        }
+
+        let limit = quota.or(current.quota).filter(|q| *q != 0);
+        if let Some(limit) = limit.filter(|limit| reservation > *limit) {
+            return Err(anyhow!(
+                "reservation of {} for {} is larger than its quota of {}",
+                format_size(reservation),
+                current.name,
+                format_size(limit),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn parse_quotas(pool: &str, output: &str) -> Result<Vec<Quota>> {
+    let mut quotas: Vec<Quota> = Vec::new();
+    let prefix = format!("{}/", pool);
+
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 3 {
+            return Err(anyhow!("invalid output from zfs get: {}", line));
+        }
+
+        let Some(name) = fields[0].strip_prefix(&prefix) else {
+            // the root of the pool
+            continue;
+        };
+
+        if quotas
+            .last()
+            .map(|q| q.full_name != fields[0])
+            .unwrap_or(true)
+        {
+            quotas.push(Quota {
+                name: name.to_string(),
+                full_name: fields[0].to_string(),
+                ..Default::default()
+            });
+        }
+
+        let quota = quotas.last_mut().unwrap();
+        // "-" means the property does not apply, "0" means it is not set
+        let value: Option<u64> = match (fields[1], fields[2]) {
+            ("type", _) | (_, "-" | "0" | "none") => None,
+            (_, value) => Some(value.parse()?),
+        };
+
+        match fields[1] {
+            "type" => quota.volume = fields[2] == "volume",
+            "used" => quota.used = value.unwrap_or_default(),
+            "available" => quota.available = value.unwrap_or_default(),
+            "quota" => quota.quota = value,
+            "reservation" => quota.reservation = value,
+            _ => {}
+        }
+    }
+
+    Ok(quotas)
+}
+
+pub(crate) fn parse_properties(output: &str) -> Result<Vec<Property>> {
+    let mut properties = Vec::new();
+
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 3 {
+            return Err(anyhow!("invalid output from zfs get: {}", line));
+        }
+
+        let source = match fields[2] {
+            "local" => PropertySource::Local,
+            "default" => PropertySource::Default,
+            "temporary" => PropertySource::Temporary,
+            "received" => PropertySource::Received,
+            "-" => PropertySource::None,
+            source => match source.strip_prefix("inherited from ") {
+                Some(parent) => PropertySource::Inherited(parent.to_string()),
+                None => return Err(anyhow!("invalid property source: {}", source)),
+            },
+        };
+
+        properties.push(Property {
+            name: fields[0].to_string(),
+            value: fields[1].to_string(),
+            source,
+        });
+    }
+
+    Ok(properties)
+}
+
+pub(crate) fn parse_key_status(output: &str) -> Result<HashMap<String, KeyStatus>> {
+    let mut status = HashMap::new();
+
+    for line in output.lines() {
+        let Some((name, value)) = line.split_once('\t') else {
+            return Err(anyhow!("invalid output from zfs get: {}", line));
+        };
+
+        status.insert(
+            name.to_string(),
+            match value {
+                "available" => KeyStatus::Available,
+                "unavailable" => KeyStatus::Unavailable,
+                _ => KeyStatus::None,
+            },
+        );
+    }
+
+    Ok(status)
+}
+
+pub(crate) fn parse_capacity(output: &str) -> Result<PoolCapacity> {
+    let fields: Vec<&str> = output.trim().split('\t').collect();
+    if fields.len() != 6 {
+        return Err(anyhow!("invalid output from zpool list: {}", output.trim()));
+    }
+
+    Ok(PoolCapacity {
+        name: fields[0].into(),
+        size: fields[1].parse()?,
+        allocated: fields[2].parse()?,
+        free: fields[3].parse()?,
+        capacity: fields[4].trim_end_matches('%').parse()?,
+        health: fields[5].into(),
+    })
+}
+
+pub(crate) fn parse_status(output: &str) -> Result<PoolStatus> {
+    // zpool status is a series of "key: value" sections; continuation lines are indented with a
+    // tab.
+    let mut sections: HashMap<String, Vec<String>> = HashMap::default();
+    let mut current: Option<String> = None;
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let header = line.split_once(':').filter(|_| !line.starts_with('\t'));
+
+        if let Some((key, value)) = header {
+            let key = key.trim().to_string();
+            let value = value.trim();
+            let entry = sections.entry(key.clone()).or_default();
+            if !value.is_empty() {
+                entry.push(value.to_string());
+            }
+            current = Some(key);
+            continue;
+        }
+
+        if let Some(current) = &current {
+            sections
+                .entry(current.clone())
+                .or_default()
+                .push(line.strip_prefix('\t').unwrap_or(line).to_string());
+        }
+    }
+
+    let joined = |key: &str| sections.get(key).map(|lines| lines.join(" "));
+
+    let name = joined("pool").ok_or(anyhow!("pool not found in zpool status output"))?;
+
+    Ok(PoolStatus {
+        name,
+        state: joined("state").unwrap_or_default(),
+        status: joined("status"),
+        action: joined("action"),
+        scan: joined("scan").map(|scan| parse_scan(&scan)),
+        vdevs: sections
+            .get("config")
+            .map(|lines| parse_vdevs(lines))
+            .unwrap_or_default(),
+        errors: joined("errors"),
+    })
+}
+
+fn parse_scan(text: &str) -> ScanStatus {
+    let mut scan = ScanStatus {
+        description: text.to_string(),
+        ..Default::default()
+    };
+
+    if text.starts_with("none requested") {
+        return scan;
+    }
+
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    scan.function = words.first().map(|w| {
+        if w.starts_with("resilver") {
+            "resilver".to_string()
+        } else {
+            w.to_string()
+        }
+    });
+
+    scan.state = if text.contains("in progress") {
+        ScanState::InProgress
+    } else if text.contains("paused") {
+        ScanState::Paused
+    } else if text.contains("canceled") {
+        ScanState::Canceled
+    } else {
+        ScanState::Finished
+    };
+
+    for (i, word) in words.iter().enumerate() {
+        let next = words.get(i + 1);
+        match *word {
+            // finished scans: "scrub repaired 0B in 00:00:01 with 0 errors on ..."
+            "repaired" | "resilvered" if scan.state == ScanState::Finished => {
+                scan.repaired = next.and_then(|n| parse_size(n).ok());
+            }
+            // scans in progress: "0B repaired, 5.00% done, ..."
+            "repaired," if i > 0 => {
+                scan.repaired = parse_size(words[i - 1]).ok();
+            }
+            "done," | "done" if i > 0 => {
+                scan.progress = words[i - 1].trim_end_matches('%').parse().ok();
+            }
+            "with" => {
+                scan.errors = next.and_then(|n| n.parse().ok());
+            }
+            "in" if scan.duration.is_none() => {
+                scan.duration = parse_duration(&words[i + 1..]);
+            }
+            _ => {}
+        }
+    }
+
+    scan
+}
+
+// parses "00:01:02" or "1 days 00:01:02" into seconds
+fn parse_duration(words: &[&str]) -> Option<u64> {
+    let (days, clock) = match words {
+        [days, "days", clock, ..] => (days.parse::<u64>().ok()?, *clock),
+        [clock, ..] => (0, *clock),
+        [] => return None,
+    };
+
+    let parts = clock
+        .split(':')
+        .map(|p| p.parse::<u64>())
+        .collect::<std::result::Result<Vec<u64>, _>>()
+        .ok()?;
+
+    match parts.as_slice() {
+        [h, m, s] => Some(days * 86400 + h * 3600 + m * 60 + s),
+        _ => None,
+    }
+}
+
+fn parse_vdevs(lines: &[String]) -> Vec<VDev> {
+    let mut flat = Vec::new();
+
+    for line in lines {
+        let depth = (line.len() - line.trim_start_matches(' ').len()) / 2;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        if fields.is_empty() || fields[0] == "NAME" {
+            continue;
+        }
+
+        let vdev = VDev {
+            name: fields[0].to_string(),
+            state: fields.get(1).map(|s| s.to_string()),
+            read_errors: fields
+                .get(2)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default(),
+            write_errors: fields
+                .get(3)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default(),
+            checksum_errors: fields
+                .get(4)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default(),
+            note: if fields.len() > 5 {
+                Some(fields[5..].join(" "))
+            } else {
+                None
+            },
+            children: Vec::new(),
+        };
+
+        flat.push((depth, vdev));
+    }
+
+    build_tree(&flat)
+}
+
+fn build_tree(flat: &[(usize, VDev)]) -> Vec<VDev> {
+    let mut tree = Vec::new();
+    let mut i = 0;
+
+    while i < flat.len() {
+        let (depth, mut vdev) = flat[i].clone();
+        let mut end = i + 1;
+        while end < flat.len() && flat[end].0 > depth {
+            end += 1;
+        }
+
+        vdev.children = build_tree(&flat[i + 1..end]);
+        tree.push(vdev);
+        i = end;
+    }
+
+    tree
+}
@@ -0,0 +1,328 @@
+use super::*;
+
+const STATUS_FINISHED: &str = "  pool: trunk
+ state: ONLINE
+  scan: scrub repaired 0B in 00:01:05 with 0 errors on Sun Oct 18 10:00:00 2026
+config:
+
+\tNAME        STATE     READ WRITE CKSUM
+\ttrunk       ONLINE       0     0     0
+\t  mirror-0  ONLINE       0     0     0
+\t    sda     ONLINE       0     0     0
+\t    sdb     ONLINE       0     0     3
+\tlogs
+\t  sdc       ONLINE       0     0     0
+
+errors: No known data errors
+";
+
+const STATUS_IN_PROGRESS: &str = "  pool: trunk
+ state: DEGRADED
+status: One or more devices could not be used because the label is missing or
+\tinvalid.  Sufficient replicas exist for the pool to continue
+\tfunctioning in a degraded state.
+action: Replace the device using 'zpool replace'.
+  scan: scrub in progress since Sun Oct 18 10:00:00 2026
+\t1.23G scanned at 100M/s, 500M issued at 50M/s, 10G total
+\t1M repaired, 5.00% done, 00:03:00 to go
+config:
+
+\tNAME        STATE     READ WRITE CKSUM
+\ttrunk       DEGRADED     0     0     0
+\t  sda       UNAVAIL      0     0     0  corrupted data
+
+errors: No known data errors
+";
+
+#[test]
+fn status_finished() {
+    let status = parse_status(STATUS_FINISHED).unwrap();
+    assert_eq!(status.name, "trunk");
+    assert_eq!(status.state, "ONLINE");
+    assert_eq!(status.errors, Some("No known data errors".into()));
+    assert!(status.status.is_none());
+
+    let scan = status.scan.unwrap();
+    assert_eq!(scan.function, Some("scrub".into()));
+    assert_eq!(scan.state, ScanState::Finished);
+    assert_eq!(scan.repaired, Some(0));
+    assert_eq!(scan.errors, Some(0));
+    assert_eq!(scan.duration, Some(65));
+    assert!(scan.progress.is_none());
+
+    assert_eq!(status.vdevs.len(), 2);
+    assert_eq!(status.vdevs[0].name, "trunk");
+    assert_eq!(status.vdevs[0].children.len(), 1);
+    let mirror = &status.vdevs[0].children[0];
+    assert_eq!(mirror.name, "mirror-0");
+    assert_eq!(mirror.children.len(), 2);
+    assert_eq!(mirror.children[1].name, "sdb");
+    assert_eq!(mirror.children[1].checksum_errors, 3);
+    assert_eq!(status.vdevs[1].name, "logs");
+    assert!(status.vdevs[1].state.is_none());
+    assert_eq!(status.vdevs[1].children[0].name, "sdc");
+}
+
+#[test]
+fn status_in_progress() {
+    let status = parse_status(STATUS_IN_PROGRESS).unwrap();
+    assert_eq!(status.state, "DEGRADED");
+    assert!(status
+        .status
+        .unwrap()
+        .ends_with("functioning in a degraded state."));
+    assert!(status.action.is_some());
+
+    let scan = status.scan.unwrap();
+    assert_eq!(scan.state, ScanState::InProgress);
+    assert_eq!(scan.progress, Some(5.0));
+    assert_eq!(scan.repaired, Some(1024 * 1024));
+    assert!(scan.duration.is_none());
+
+    let sda = &status.vdevs[0].children[0];
+    assert_eq!(sda.state, Some("UNAVAIL".into()));
+    assert_eq!(sda.note, Some("corrupted data".into()));
+}
+
+#[test]
+fn capacity() {
+    let capacity =
+        parse_capacity("trunk\t10737418240\t1073741824\t9663676416\t10\tONLINE\n").unwrap();
+    assert_eq!(capacity.name, "trunk");
+    assert_eq!(capacity.size, 10737418240);
+    assert_eq!(capacity.allocated, 1073741824);
+    assert_eq!(capacity.free, 9663676416);
+    assert_eq!(capacity.capacity, 10);
+    assert_eq!(capacity.health, "ONLINE");
+
+    assert!(parse_capacity("trunk\t10\n").is_err());
+}
+
+#[test]
+fn sizes() {
+    assert_eq!(parse_size("512").unwrap(), 512);
+    assert_eq!(parse_size("0B").unwrap(), 0);
+    assert_eq!(parse_size("20G").unwrap(), 20 * 1024 * 1024 * 1024);
+    assert_eq!(parse_size("20GiB").unwrap(), 20 * 1024 * 1024 * 1024);
+    assert_eq!(parse_size("1.5k").unwrap(), 1536);
+    assert_eq!(parse_size("2T").unwrap(), 2 << 40);
+    assert!(parse_size("twenty").is_err());
+    assert!(parse_size("20X").is_err());
+}
+
+#[test]
+fn quotas() {
+    let output = "trunk\ttype\tfilesystem
+trunk\tused\t2048
+trunk\tavailable\t1073741824
+trunk\tquota\t0
+trunk\treservation\t0
+trunk/dataset\ttype\tfilesystem
+trunk/dataset\tused\t1024
+trunk/dataset\tavailable\t1048576
+trunk/dataset\tquota\t1048576
+trunk/dataset\treservation\t0
+trunk/volume\ttype\tvolume
+trunk/volume\tused\t4096
+trunk/volume\tavailable\t1073741824
+trunk/volume\tquota\t-
+trunk/volume\treservation\t0
+";
+
+    let quotas = parse_quotas("trunk", output).unwrap();
+    assert_eq!(
+        quotas,
+        vec![
+            Quota {
+                name: "dataset".into(),
+                full_name: "trunk/dataset".into(),
+                volume: false,
+                used: 1024,
+                available: 1048576,
+                quota: Some(1048576),
+                reservation: None,
+            },
+            Quota {
+                name: "volume".into(),
+                full_name: "trunk/volume".into(),
+                volume: true,
+                used: 4096,
+                available: 1073741824,
+                quota: None,
+                reservation: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn overcommit() {
+    let gig = 1024 * 1024 * 1024;
+    let current = Quota {
+        name: "dataset".into(),
+        used: 2 * gig,
+        reservation: Some(gig),
+        ..Default::default()
+    };
+
+    assert!(check_overcommit(&current, 10 * gig, Some(5 * gig), None).is_ok());
+    assert!(check_overcommit(&current, 10 * gig, Some(12 * gig), None).is_ok());
+    assert!(check_overcommit(&current, 10 * gig, Some(0), None).is_ok());
+
+    let err = check_overcommit(&current, 10 * gig, Some(20 * gig), None)
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "quota of 20G for dataset would overcommit the pool: it uses 2G and only 10G is free"
+    );
+
+    let err = check_overcommit(&current, 10 * gig, Some(gig), None)
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "quota of 1G for dataset is smaller than the 2G it already uses"
+    );
+
+    assert!(check_overcommit(&current, 10 * gig, None, Some(11 * gig)).is_ok());
+    let err = check_overcommit(&current, 10 * gig, None, Some(12 * gig))
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "reservation of 12G for dataset would overcommit the pool: it needs 11G more and only 10G is free"
+    );
+
+    assert!(check_overcommit(&current, 10 * gig, Some(3 * gig), Some(4 * gig)).is_err());
+}
+
+#[test]
+fn format_sizes() {
+    assert_eq!(format_size(512), "512");
+    assert_eq!(format_size(1024), "1K");
+    assert_eq!(format_size(1536), "1.5K");
+    assert_eq!(format_size(20 * 1024 * 1024 * 1024), "20G");
+    assert_eq!(format_size(parse_size("1.25T").unwrap()), "1.25T");
+}
+
+#[test]
+fn properties() {
+    let output = "type\tfilesystem\t-
+compression\tlz4\tinherited from trunk
+recordsize\t131072\tdefault
+atime\toff\tlocal
+mountpoint\t/trunk/dataset\tinherited from trunk
+encryption\toff\tdefault
+";
+
+    let properties = parse_properties(output).unwrap();
+    assert_eq!(properties.len(), 6);
+    assert_eq!(properties[0].source, PropertySource::None);
+    assert_eq!(
+        properties[1],
+        Property {
+            name: "compression".into(),
+            value: "lz4".into(),
+            source: PropertySource::Inherited("trunk".into()),
+        }
+    );
+    assert_eq!(properties[2].source, PropertySource::Default);
+    assert_eq!(properties[3].source, PropertySource::Local);
+
+    assert!(parse_properties("atime\toff\tsomewhere\n").is_err());
+    assert!(parse_properties("atime\toff\n").is_err());
+}
+
+#[test]
+fn property_changes() {
+    let changes = PropertyChanges {
+        compression: Some("zstd-3".into()),
+        recordsize: Some("1M".into()),
+        atime: Some(false),
+        sync: Some("always".into()),
+    };
+    assert!(changes.validate(false).is_ok());
+    assert!(changes.validate(true).is_err());
+    assert_eq!(
+        changes.args().unwrap(),
+        vec![
+            "compression=zstd-3",
+            "recordsize=1048576",
+            "atime=off",
+            "sync=always"
+        ]
+    );
+
+    let volume = PropertyChanges {
+        compression: Some("off".into()),
+        ..Default::default()
+    };
+    assert!(volume.validate(true).is_ok());
+    assert!(!volume.is_empty());
+    assert!(PropertyChanges::default().is_empty());
+
+    for invalid in [
+        PropertyChanges {
+            compression: Some("zstd-20".into()),
+            ..Default::default()
+        },
+        PropertyChanges {
+            compression: Some("brotli".into()),
+            ..Default::default()
+        },
+        PropertyChanges {
+            recordsize: Some("100K".into()),
+            ..Default::default()
+        },
+        PropertyChanges {
+            recordsize: Some("32M".into()),
+            ..Default::default()
+        },
+        PropertyChanges {
+            sync: Some("sometimes".into()),
+            ..Default::default()
+        },
+    ] {
+        assert!(invalid.validate(false).is_err());
+    }
+}
+
+#[test]
+fn key_status() {
+    let status =
+        parse_key_status("trunk\t-\ntrunk/secret\tavailable\ntrunk/locked\tunavailable\n").unwrap();
+    assert_eq!(status["trunk"], KeyStatus::None);
+    assert_eq!(status["trunk/secret"], KeyStatus::Available);
+    assert_eq!(status["trunk/locked"], KeyStatus::Unavailable);
+    assert!(parse_key_status("trunk\n").is_err());
+}
+
+#[test]
+fn encryption_keys() {
+    let key = EncryptionKey::Passphrase("correct horse".into());
+    assert!(key.validate().is_ok());
+    assert_eq!(key.format(), "passphrase");
+    assert_eq!(
+        key.options(),
+        vec!["-o", "keyformat=passphrase", "-o", "keylocation=prompt"]
+    );
+    assert!(!format!("{:?}", key).contains("horse"));
+
+    assert!(EncryptionKey::Passphrase("short".into())
+        .validate()
+        .is_err());
+
+    let key = EncryptionKey::Keyfile("/etc/gild/key".into());
+    assert!(key.validate().is_ok());
+    assert_eq!(
+        key.options(),
+        vec![
+            "-o",
+            "keyformat=raw",
+            "-o",
+            "keylocation=file:///etc/gild/key"
+        ]
+    );
+    assert!(EncryptionKey::Keyfile("key".into()).validate().is_err());
+}